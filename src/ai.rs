@@ -6,10 +6,9 @@ use rand::seq::SliceRandom;
 #[cfg(feature = "perf")]
 use flamer::flame;
 
+use crate::bitboard;
 use crate::board::*;
 
-type Move = (BoardCoord, BoardCoord);
-
 /// This trait describes a computer player. An AIPlayer will have `next_move`
 /// called with a certain board position and a player, and is expected to return
 /// a legal move. Note that the return type is a `Poll`, so the AIPlayer may
@@ -19,47 +18,34 @@ type Move = (BoardCoord, BoardCoord);
 pub trait AIPlayer: std::fmt::Debug {
     /// Given a board, this function should return the next move the AI intends
     /// to play.
+    /// A pawn reaching the last rank is promoted to the piece given in the
+    /// move's `promotion`.
     fn next_move(&mut self, board: &BoardState, player: Color) -> Poll<Move>;
-    /// Given a board that requires a promotion for a pawn, return what piece the
-    /// pawn should be promoted to. By default, this is always a queen, but can
-    /// be manually overridden if desired.
-    fn next_promote(&mut self, _board: &BoardState) -> Poll<PieceType> {
-        Poll::Ready(PieceType::Queen)
-    }
 }
 
 #[derive(Debug)]
 pub struct RandomPlayer {}
 
 impl AIPlayer for RandomPlayer {
-    fn next_move(&mut self, board: &BoardState, player: Color) -> Poll<Move> {
-        let moves = board.board.get_all_moves(player);
+    fn next_move(&mut self, board: &BoardState, _player: Color) -> Poll<Move> {
+        let moves = board.legal_moves();
         if moves.is_empty() {
-            panic!(format!("Expected AI player to have at least one valid move! Board is in {:?} and needs promote: {:?}", board.checkmate, board.need_promote()))
+            panic!(format!(
+                "Expected AI player to have at least one valid move! Board is in {:?}",
+                board.checkmate
+            ))
         }
         let rand_move = *moves.choose(&mut rand::thread_rng()).unwrap();
         Poll::Ready(rand_move)
     }
-
-    fn next_promote(&mut self, _board: &BoardState) -> Poll<PieceType> {
-        let choice = *[
-            PieceType::Knight,
-            PieceType::Bishop,
-            PieceType::Rook,
-            PieceType::Queen,
-        ]
-        .choose(&mut rand::thread_rng())
-        .unwrap();
-        Poll::Ready(choice)
-    }
 }
 
 #[derive(Debug)]
 pub struct MinOptPlayer {}
 
 impl AIPlayer for MinOptPlayer {
-    fn next_move(&mut self, board: &BoardState, player: Color) -> Poll<Move> {
-        let my_moves = board.board.get_all_moves(player);
+    fn next_move(&mut self, board: &BoardState, _player: Color) -> Poll<Move> {
+        let my_moves = board.legal_moves();
 
         // lower score is better. here we have the score as the number of moves the opponent can make afterwards
         let mut best_score = usize::MAX;
@@ -67,13 +53,13 @@ impl AIPlayer for MinOptPlayer {
         let mut move_scores: HashMap<usize, Vec<Move>> = HashMap::new();
         // for each of my possible moves, try making it and see how many moves the
         // opponent now has and track best move so far
-        for (start, end) in my_moves {
+        for my_move in my_moves {
             let mut board = board.clone();
-            board.take_turn(start, end);
-            let opponent_moves = board.board.get_all_moves(player.opposite());
+            board.take_turn(my_move);
+            let opponent_moves = board.legal_moves();
             let score = opponent_moves.len();
 
-            move_scores.entry(score).or_default().push((start, end));
+            move_scores.entry(score).or_default().push(my_move);
 
             if score < best_score {
                 best_score = score;
@@ -108,11 +94,36 @@ struct TreeSearch {
     /// The "expected" sequence of moves, has length of `max_depth`
     principal_variation: Vec<Option<Move>>,
     /// For debugging. Counts how many branches were "generated" (were seen by
-    /// `get_all_moves()`)
+    /// `legal_moves()`)
     total_branches: usize,
     /// For debugging. Counts how many branches were actually searched (has `search()`
     /// called on them)
     branches_searched: usize,
+    /// Scores of positions already searched, keyed by `BoardState::position_hash`.
+    /// The same position is often reached by several move orders, and is
+    /// reached again by each iteration of `search()`, so this saves searching
+    /// it again. Emptied at the end of each `search()`.
+    transpositions: HashMap<u64, Transposition>,
+}
+
+/// A position scored by `TreeSearch::score`, stored in the transposition table.
+#[derive(Debug, Clone, Copy)]
+struct Transposition {
+    /// How many plys were searched below the position.
+    depth: usize,
+    score: i32,
+    /// Whether `score` is exact, or only a bound because of an alpha or beta cutoff.
+    bound: Bound,
+    best_move: Option<Move>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Bound {
+    Exact,
+    /// The actual score is at least this good for the searching player.
+    Lower,
+    /// The actual score is at most this good for the searching player.
+    Upper,
 }
 
 impl AIPlayer for TreeSearchPlayer {
@@ -126,8 +137,6 @@ impl AIPlayer for TreeSearchPlayer {
                 // (This would cause problems, for example, if there was code in
                 // screen.rs that modified the board while the thread ran!)
                 // Thus, we must work on a copy of the board.
-                // TODO: Consider not using a BoardState internally--instead something
-                // faster like a bitboard.
                 let board = board.clone();
                 let mut treesearch = self.state.clone();
                 std::thread::spawn(move || {
@@ -169,6 +178,7 @@ impl TreeSearchPlayer {
                 principal_variation: vec![None; max_depth],
                 total_branches: 0,
                 branches_searched: 0,
+                transpositions: HashMap::new(),
             },
             reciever: None,
         }
@@ -177,6 +187,8 @@ impl TreeSearchPlayer {
 
 impl TreeSearch {
     fn search(&mut self, position: &BoardState, player: Color) -> (i32, Move) {
+        // Moves are made and then undone on this copy while searching.
+        let mut position = position.clone();
         let max_depth = self.max_depth;
         let mut result = (0, None, -1, -1);
         for i in 1..=max_depth {
@@ -185,8 +197,9 @@ impl TreeSearch {
             self.max_depth = i;
             self.total_branches = 0;
             self.branches_searched = 0;
-            result = self.score(position, 0, i32::MIN, i32::MAX, player);
+            result = self.score(&mut position, 0, i32::MIN, i32::MAX, player);
         }
+        self.transpositions.clear();
         (
             result.0,
             result.1.expect("Expected search to return a move"),
@@ -196,7 +209,7 @@ impl TreeSearch {
     #[cfg_attr(feature = "perf", flame)]
     fn score(
         &mut self,
-        position: &BoardState,
+        position: &mut BoardState,
         current_depth: usize,
         mut alpha: i32,
         mut beta: i32,
@@ -209,61 +222,81 @@ impl TreeSearch {
             return (score, None, alpha, beta);
         }
 
-        let mut moves = position.board.get_all_moves(position.current_player);
-        // See also: https://www.chessprogramming.org/MVV-LVA
-        // We sort here to make the AI check the most "useful" moves first. This
-        // helps in causing an earlier alpha or beta cutoff, thereby reducing the
-        // number of branches we have to check.
-        // We first check all the captures. We sort the captures by the "most valuable victim"
-        // and then by the "least valuable attacker". This is nice because it
-        // lets us consider the most "useful" moves first--ie: use a pawn to defend an
-        // attacking piece, or try and capture a high-value attacker first.
-        // Note that sort_by_key will sort with smallest values first.const
-        // For normal moves, we start with the most valuable pieces (queen, rook, etc)
-        moves.sort_by_key(|&(start, end)| {
-            let attacker = position.get(start);
-            let victim = position.get(end);
-            match &victim.0 {
-                None => 10 - value(attacker),
-                Some(_) => -(10 * value(victim) - value(attacker)),
+        // The soonest the game can end from here is on the next ply, so if
+        // even a checkmate that soon can't beat alpha, this line can't either.
+        // See also: https://www.chessprogramming.org/Mate_Distance_Pruning
+        let fastest_mate = 999_999_999 - (current_depth as i32 + 1);
+        if alpha >= fastest_mate {
+            return (fastest_mate, None, alpha, beta);
+        }
+
+        // If this position was already searched at least as deeply, reuse its
+        // score when it is exact or when it would cause a cutoff anyway. The
+        // root is always searched, since it needs to return a move.
+        // See also: https://www.chessprogramming.org/Transposition_Table
+        let depth = self.max_depth - current_depth;
+        let transposition = self.transpositions.get(&position.position_hash()).copied();
+        if let Some(transposition) = transposition {
+            let score = from_table_score(transposition.score, current_depth);
+            let usable = match transposition.bound {
+                Bound::Exact => true,
+                Bound::Lower => score >= beta,
+                Bound::Upper => score <= alpha,
+            };
+            if current_depth > 0 && transposition.depth >= depth && usable {
+                return (score, transposition.best_move, alpha, beta);
             }
-        });
+        }
+        let (original_alpha, original_beta) = (alpha, beta);
 
+        let mut moves = position.legal_moves();
         self.total_branches += moves.len();
 
         let my_turn = player == position.current_player;
         let mut best_score = if my_turn { i32::MIN } else { i32::MAX };
         let mut best_move = self.principal_variation[current_depth];
 
-        // First, try checking the principal moves, to get a better value for alpha and beta
-        let principal_move = self.principal_variation[current_depth];
-        if let Some(i) = moves
-            .iter()
-            .position(|&the_move| Some(the_move) == principal_move)
-        {
-            moves.swap(0, i);
+        // First, try checking the best move found last time this position was
+        // searched, then the principal move, to get a better value for alpha and beta
+        let principal_moves = [
+            transposition.and_then(|transposition| transposition.best_move),
+            self.principal_variation[current_depth],
+        ];
+        let mut checked_first = 0;
+        for principal_move in principal_moves.iter().flatten() {
+            if let Some(i) = moves[checked_first..]
+                .iter()
+                .position(|the_move| the_move == principal_move)
+            {
+                moves.swap(checked_first, checked_first + i);
+                checked_first += 1;
+            }
         }
 
         // Then, for each of our moves, try making it and see which one has the best score
         let mut i = 0;
-        for (start, end) in moves {
-            let mut next_position = position.clone();
-            next_position.take_turn(start, end);
-            // TODO: This really should get a real analysis, but for now, assuming the
-            // player or ourself always promos to queen is an ok compromise.
-            if let Some(coord) = next_position.need_promote() {
-                next_position.promote(coord, PieceType::Queen);
+        while i < moves.len() {
+            if i == checked_first {
+                // See also: https://www.chessprogramming.org/MVV-LVA
+                // We sort here to make the AI check the most "useful" moves first. This
+                // helps in causing an earlier alpha or beta cutoff, thereby reducing the
+                // number of branches we have to check. See `move_order` for the order.
+                // The sort is skipped when the moves checked first already cause a cutoff.
+                moves[checked_first..]
+                    .sort_by_cached_key(|&the_move| move_order(position, the_move));
             }
+            let the_move = moves[i];
+            position.take_turn(the_move);
 
-            let (score, _, _, _) =
-                self.score(&next_position, current_depth + 1, alpha, beta, player);
+            let (score, _, _, _) = self.score(position, current_depth + 1, alpha, beta, player);
+            position.undo();
 
             if my_turn {
                 // is it is our turn, pick our best move
                 if best_score < score {
                     alpha = alpha.max(score);
                     best_score = best_score.max(score);
-                    best_move = Some((start, end));
+                    best_move = Some(the_move);
 
                     if alpha >= beta {
                         // entering this block means that the move we just found is better than the worst possible outcome
@@ -277,7 +310,7 @@ impl TreeSearch {
                 if best_score > score {
                     beta = beta.min(score);
                     best_score = best_score.min(score);
-                    best_move = Some((start, end));
+                    best_move = Some(the_move);
 
                     if alpha >= beta {
                         // entering this block means that the opponent can always force a worse outcome for this than the
@@ -294,6 +327,23 @@ impl TreeSearch {
         // Add the best moves found so far to the principal move list
         self.principal_variation[current_depth] = best_move;
 
+        let bound = if best_score <= original_alpha {
+            Bound::Upper
+        } else if best_score >= original_beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.transpositions.insert(
+            position.position_hash(),
+            Transposition {
+                depth,
+                score: to_table_score(best_score, current_depth),
+                bound,
+                best_move,
+            },
+        );
+
         self.branches_searched += i;
 
         (best_score, best_move, alpha, beta)
@@ -407,83 +457,189 @@ impl TreeSearch {
                     400
                 }
             }
-            CheckmateState::Checkmate => {
-                if my_turn {
-                    return -999_999_999;
-                } else {
+            // The game is over, so ask the variant who won.
+            CheckmateState::Checkmate
+            | CheckmateState::InsuffientMaterial
+            | CheckmateState::Stalemate
+            | CheckmateState::ThreefoldRepetition
+            | CheckmateState::FivefoldRepetition
+            | CheckmateState::FiftyMoveRule
+            | CheckmateState::SeventyFiveMoveRule
+            | CheckmateState::VariantEnd { .. } => match position.winner() {
+                Some(winner) if winner == player => {
                     // Subtracting the current_depth makes the AI prefer shorter
                     // checkmates over longer ones. We also immediately return because
                     // there is no reason to find the position scores since checkmate
                     // is the best possible thing to do.
                     return 999_999_999 - current_depth as i32;
                 }
-            }
-            // A stalemate is draw, and so we try to make the bot play to win when
-            // possible.
-            CheckmateState::InsuffientMaterial | CheckmateState::Stalemate => -200,
+                Some(_) => return -999_999_999,
+                // A stalemate is draw, and so we try to make the bot play to win when
+                // possible.
+                None => -200,
+            },
         };
 
         let mut my_piece_score = 0;
         let mut my_position_score = 0;
         let mut their_piece_score = 0;
         let mut their_position_score = 0;
-        for i in 0..8 {
-            for j in 0..8 {
-                let coord = BoardCoord::new((i, j)).expect("Expected a valid BoardCoord");
-                // offsets into the position tables
-                // we flip them vertically when playing as black because the
-                // tables are constructed for white's side
-                let (offset_x, offset_y) = match player {
-                    Color::White => (i as usize, j as usize),
-                    Color::Black => (i as usize, 7 - j as usize),
-                };
-
-                let tile = position.get(coord);
-
-                if tile.0.is_none() {
-                    continue;
-                }
-                let piece = tile.0.unwrap();
-                use PieceType::*;
-                // Values here are also taken from https://www.chessprogramming.org/Simplified_Evaluation_Function
-                // It seems that this causes the bot to value the bishops a bit more than
-                // the knights.
-                let (piece_score, position_score) = match piece.piece {
-                    Pawn { .. } => (100, PAWN_POSITION_TABLE[offset_x][offset_y]),
-                    Knight => (320, KNIGHT_POSITION_TABLE[offset_x][offset_y]),
-                    Bishop => (330, BISHOP_POSITION_TABLE[offset_x][offset_y]),
-                    Rook => (500, ROOK_POSITION_TABLE[offset_x][offset_y]),
-                    Queen => (900, QUEEN_POSITION_TABLE[offset_x][offset_y]),
-                    // TODO use the late position table
-                    King => (20000, EARLY_KING_POSITION_TABLE[offset_x][offset_y]),
-                };
-
-                let my_piece = piece.color == player;
-                if my_piece {
-                    my_piece_score += piece_score;
-                    my_position_score += position_score;
-                } else {
-                    their_piece_score += piece_score;
-                    their_position_score += position_score;
-                }
+        let Dimensions { files, ranks } = position.board.dimensions();
+        for (BoardCoord(i, j), piece) in position.board.iter_pieces() {
+            // offsets into the position tables
+            // we flip them vertically when playing as black because the
+            // tables are constructed for white's side
+            let (i, j) = match player {
+                Color::White => (i, j),
+                Color::Black => (i, ranks - 1 - j),
+            };
+            // The tables are 8x8, so boards of other sizes are stretched to fit
+            let offset_x = (i as usize * 8) / files as usize;
+            let offset_y = (j as usize * 8) / ranks as usize;
+            use PieceType::*;
+            let piece_score = piece.piece.value();
+            let position_score = match piece.piece {
+                Pawn { .. } => PAWN_POSITION_TABLE[offset_x][offset_y],
+                Knight => KNIGHT_POSITION_TABLE[offset_x][offset_y],
+                Bishop => BISHOP_POSITION_TABLE[offset_x][offset_y],
+                Rook => ROOK_POSITION_TABLE[offset_x][offset_y],
+                Queen => QUEEN_POSITION_TABLE[offset_x][offset_y],
+                // TODO use the late position table
+                King => EARLY_KING_POSITION_TABLE[offset_x][offset_y],
+                // Fairy pieces mostly leap or move like a Knight does, so
+                // they also want to be near the center
+                Fairy(_) => KNIGHT_POSITION_TABLE[offset_x][offset_y],
+            };
+
+            let my_piece = piece.color == player;
+            if my_piece {
+                my_piece_score += piece_score;
+                my_position_score += position_score;
+            } else {
+                their_piece_score += piece_score;
+                their_position_score += position_score;
+            }
+        }
+
+        // In Crazyhouse, captured pieces may be dropped back onto the board, so
+        // a piece in a pocket is worth as much as one on the board.
+        for &color in &[Color::White, Color::Black] {
+            let pocket_score: i32 = position
+                .pocket(color)
+                .pieces()
+                .map(|(piece, count)| piece.value() * count as i32)
+                .sum();
+            if color == player {
+                my_piece_score += pocket_score;
+            } else {
+                their_piece_score += pocket_score;
             }
         }
 
+        // In Three-check, each check brings a player closer to winning, and
+        // the checks get more valuable as the third one gets closer.
+        if position.variant().counts_checks() {
+            const CHECKS_BONUS: [i32; 3] = [0, 300, 900];
+            let checks_bonus = |color| CHECKS_BONUS[(position.checks_given(color) as usize).min(2)];
+            my_position_score += checks_bonus(player);
+            their_position_score += checks_bonus(player.opposite());
+        }
+
+        // In King of the Hill, encourage the king to walk towards the hill.
+        // Standing on the hill wins, which is handled above.
+        let hill = position.variant().hill();
+        if hill != bitboard::EMPTY {
+            const HILL_BONUS: [i32; 4] = [0, 400, 150, 50];
+            let hill_bonus = |color| match position.board.get_king(color) {
+                Some(king) => HILL_BONUS
+                    .get(hill_distance(hill, king))
+                    .copied()
+                    .unwrap_or(0),
+                None => 0,
+            };
+            my_position_score += hill_bonus(player);
+            their_position_score += hill_bonus(player.opposite());
+        }
+
         my_piece_score + my_position_score - (their_piece_score + their_position_score) + bonus
     }
 }
 
-fn value(tile: &Tile) -> i32 {
-    use PieceType::*;
-    match tile.0 {
+/// Checkmate scores depend on how many plys away the checkmate is, so they're
+/// stored in the transposition table relative to the position instead of the
+/// root of the search.
+fn to_table_score(score: i32, current_depth: usize) -> i32 {
+    if score >= 999_000_000 {
+        score + current_depth as i32
+    } else {
+        score
+    }
+}
+
+/// The inverse of `to_table_score`.
+fn from_table_score(score: i32, current_depth: usize) -> i32 {
+    if score >= 999_000_000 {
+        score - current_depth as i32
+    } else {
+        score
+    }
+}
+
+/// The key used to order the moves searched by `TreeSearch::score`. Moves
+/// with smaller keys are searched first.
+/// We first check all the captures. We sort the captures by the "most valuable victim"
+/// and then by the "least valuable attacker". This is nice because it
+/// lets us consider the most "useful" moves first--ie: use a pawn to defend an
+/// attacking piece, or try and capture a high-value attacker first.
+/// Captures which lose material (see `Board::see`) are checked after the
+/// normal moves, since they are rarely any good.
+/// For normal moves, we start with the most valuable pieces (queen, rook, etc)
+/// Underpromotions are very rarely better than promoting to a queen, so
+/// they are checked last. Drops have no piece on their start square, so
+/// they are checked after the other non-captures.
+/// The king counts as the least valuable piece here, since it can only
+/// capture undefended pieces and rarely has the best normal move.
+fn move_order(position: &BoardState, the_move: Move) -> (u8, i32, i32) {
+    let piece = position.get(the_move.start).0.map(|piece| piece.piece);
+    let attacker = match piece {
+        Some(PieceType::King) => 0,
+        Some(piece) => piece.value(),
         None => -1,
-        Some(piece) => match piece.piece {
-            Pawn { .. } => 1,
-            Knight => 2,
-            Bishop => 2,
-            Rook => 3,
-            Queen => 4,
-            King => 0,
-        },
+    };
+    match (the_move.is_capture(), the_move.promotion) {
+        (_, Some(PieceType::Knight))
+        | (_, Some(PieceType::Bishop))
+        | (_, Some(PieceType::Rook)) => (3, 0, 0),
+        (false, _) => (1, -attacker, 0),
+        (true, _) => {
+            // En passant captures a pawn which isn't on the end square
+            let victim = match position.get(the_move.end).0 {
+                Some(piece) => piece.piece.value(),
+                None => PieceType::Pawn { just_lunged: false }.value(),
+            };
+            // The exchange can't lose more than the capturing piece is worth
+            // over its victim, so only those captures need to be checked.
+            let may_lose = match piece {
+                Some(piece) => the_move.promotion.is_some() || piece.value() > victim,
+                None => false,
+            };
+            if may_lose && position.board.see(the_move) < 0 {
+                (2, 0, 0)
+            } else {
+                (0, -victim, attacker)
+            }
+        }
     }
 }
+
+/// The number of king moves it takes to get from `king` to the nearest square
+/// of `hill`, which must not be empty.
+fn hill_distance(hill: bitboard::Bitboard, king: BoardCoord) -> usize {
+    bitboard::squares(hill)
+        .map(|square| {
+            let BoardCoord(file, rank) = bitboard::coord(square);
+            (file - king.0).abs().max((rank - king.1).abs()) as usize
+        })
+        .min()
+        .expect("Expected a non-empty hill")
+}
@@ -0,0 +1,327 @@
+//! Queries about which pieces attack which squares: the attackers of a square,
+//! the pieces giving check, the pieces pinned to their king, how many pieces
+//! of each color attack every square, and the material won or lost by the
+//! captures on a square (see `Board::see`). None of these clone the board, so
+//! they are cheap enough to use in evaluation and in the UI.
+//!
+//! A piece attacks a square if it could capture an enemy piece there. This
+//! includes squares with pieces of its own color on them (the piece defends
+//! them), and for pawns it is only the diagonal squares in front of them.
+
+use crate::bitboard::{self, Bitboard};
+use crate::board::*;
+
+/// A piece which can't move off the line between its king and an enemy piece
+/// without exposing the king to that enemy piece.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pin {
+    /// The location of the pinned piece
+    pub pinned: BoardCoord,
+    /// The location of the enemy piece pinning it
+    pub pinner: BoardCoord,
+}
+
+/// How many pieces of each color attack each square of a board. See
+/// `Board::attack_map`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttackMap {
+    /// The number of attackers, indexed by `Color as usize`, then by rank and
+    /// file like the board itself.
+    counts: [[[u8; bitboard::FILES]; bitboard::RANKS]; 2],
+}
+
+impl AttackMap {
+    /// Return the number of `color`'s pieces which attack `coord`.
+    pub fn count(&self, BoardCoord(x, y): BoardCoord, color: Color) -> u8 {
+        self.counts[color as usize][y as usize][x as usize]
+    }
+
+    /// Return every square attacked by at least one of `color`'s pieces.
+    pub fn attacked(&self, color: Color) -> Bitboard {
+        let mut attacked = bitboard::EMPTY;
+        for (y, rank) in self.counts[color as usize].iter().enumerate() {
+            for (x, &count) in rank.iter().enumerate() {
+                if count > 0 {
+                    attacked |= bitboard::bit(BoardCoord(x as i8, y as i8));
+                }
+            }
+        }
+        attacked
+    }
+}
+
+impl Board {
+    /// Return the squares the piece on `coord` attacks, or nothing if there
+    /// is no piece there.
+    pub fn attacks_from(&self, coord: BoardCoord) -> Bitboard {
+        let piece = match self.get(coord).0 {
+            Some(piece) => piece,
+            None => return bitboard::EMPTY,
+        };
+        let square = bitboard::square(coord);
+        let occupied = self.occupied();
+        use PieceType::*;
+        let attacks = match piece.piece {
+            Pawn { .. } => bitboard::pawn_attacks(piece.color, square),
+            Knight => bitboard::knight_attacks(square),
+            Bishop => self.bishop_attacks(square, occupied),
+            Rook => self.rook_attacks(square, occupied),
+            Queen => self.queen_attacks(square, occupied),
+            King => bitboard::king_attacks(square),
+            Fairy(fairy) => fairy.movement().attacks(square, occupied),
+        };
+        attacks & self.area()
+    }
+
+    /// Return `color`'s pieces which attack `coord`.
+    pub fn attackers(&self, coord: BoardCoord, color: Color) -> Bitboard {
+        self.attackers_to(bitboard::square(coord), self.occupied()) & self.pieces_of(color)
+    }
+
+    /// Return the enemy pieces giving check to `color`'s king. This is empty
+    /// if `color` has no king.
+    pub fn checkers(&self, color: Color) -> Bitboard {
+        match self.get_king(color) {
+            Some(king) => self.attackers(king, color.opposite()),
+            None => bitboard::EMPTY,
+        }
+    }
+
+    /// Return every pin on `color`'s pieces. A piece pinned by two enemy
+    /// pieces (which can happen with a Grasshopper) appears twice.
+    pub fn pins(&self, color: Color) -> Vec<Pin> {
+        let king = match self.get_king(color) {
+            Some(king) => bitboard::square(king),
+            None => return vec![],
+        };
+        let occupied = self.occupied();
+        let enemies = self.pieces_of(color.opposite());
+        let attackers = self.attackers_to(king, occupied) & enemies;
+
+        // Every piece which can be pinned is on a line through the king, so
+        // only those pieces need to be taken off the board to see which enemy
+        // pieces would then attack the king.
+        let candidates = self.queen_attacks(king, bitboard::EMPTY) & self.pieces_of(color);
+        let mut pins = vec![];
+        for square in bitboard::squares(candidates) {
+            let uncovered = self.attackers_to(king, occupied & !(1 << square)) & enemies;
+            for pinner in bitboard::squares(uncovered & !attackers) {
+                pins.push(Pin {
+                    pinned: bitboard::coord(square),
+                    pinner: bitboard::coord(pinner),
+                });
+            }
+        }
+        pins
+    }
+
+    /// Return `color`'s pieces which are pinned to their king.
+    pub fn pinned(&self, color: Color) -> Bitboard {
+        self.pins(color)
+            .iter()
+            .fold(bitboard::EMPTY, |pinned, pin| {
+                pinned | bitboard::bit(pin.pinned)
+            })
+    }
+
+    /// Count how many pieces of each color attack each square.
+    pub fn attack_map(&self) -> AttackMap {
+        let mut counts = [[[0; bitboard::FILES]; bitboard::RANKS]; 2];
+        for (coord, piece) in self.iter_pieces() {
+            for square in bitboard::squares(self.attacks_from(coord)) {
+                let BoardCoord(x, y) = bitboard::coord(square);
+                counts[piece.color as usize][y as usize][x as usize] += 1;
+            }
+        }
+        AttackMap { counts }
+    }
+
+    /// Return the material the player making `mv` can expect to win with it,
+    /// in centipawns (see `PieceType::value`), if both players then keep
+    /// capturing on the end square for as long as that gains them material.
+    /// This is static exchange evaluation, see
+    /// https://www.chessprogramming.org/Static_Exchange_Evaluation
+    /// Each capture is made with the least valuable piece attacking the square,
+    /// including pieces which only attack it once the pieces in front of them
+    /// have captured (x-rays). A negative value means the move loses material,
+    /// such as a queen taking a pawn which another pawn defends. A move which
+    /// isn't a capture is worth 0, or less if the moved piece can be taken.
+    /// Pins are ignored, as are promotions on the recaptures. Castling and
+    /// drops are always worth 0.
+    pub fn see(&self, mv: Move) -> i32 {
+        if mv.is_castle() || mv.is_drop() {
+            return 0;
+        }
+        let piece = match self.get(mv.start).0 {
+            Some(piece) => piece,
+            None => return 0,
+        };
+        let target = bitboard::square(mv.end);
+        let mut occupied = self.occupied() & !bitboard::bit(mv.start);
+
+        // The material each player has won after each capture, from the point
+        // of view of the player who made it, if the other player doesn't
+        // recapture.
+        let mut gains = vec![];
+        let captured = if mv.is_en_passant() {
+            occupied &= !bitboard::bit(BoardCoord(mv.end.0, mv.start.1));
+            PieceType::Pawn { just_lunged: false }.value()
+        } else {
+            self.get(mv.end).0.map_or(0, |victim| victim.piece.value())
+        };
+        // The value of the piece which is now on the end square
+        let mut on_square = piece.piece.value();
+        match mv.promotion {
+            Some(promotion) => {
+                gains.push(captured + promotion.value() - on_square);
+                on_square = promotion.value();
+            }
+            None => gains.push(captured),
+        }
+
+        let mut side = piece.color.opposite();
+        loop {
+            let attackers = self.attackers_to(target, occupied);
+            let value_at = |square| self.get(bitboard::coord(square)).0.unwrap().piece.value();
+            let attacker = match bitboard::squares(attackers & self.pieces_of(side))
+                .min_by_key(|&square| value_at(square))
+            {
+                Some(attacker) => attacker,
+                None => break,
+            };
+            // The king can't capture onto a square which is still defended.
+            let is_king = self
+                .get(bitboard::coord(attacker))
+                .is(side, PieceType::King);
+            if is_king && attackers & self.pieces_of(side.opposite()) != bitboard::EMPTY {
+                break;
+            }
+            gains.push(on_square - gains.last().unwrap());
+            on_square = value_at(attacker);
+            occupied &= !(1 << attacker);
+            side = side.opposite();
+        }
+
+        // Each player may stop capturing instead of recapturing, if that
+        // leaves them better off.
+        while gains.len() > 1 {
+            let recapture = gains.pop().unwrap();
+            let gain = gains.last_mut().unwrap();
+            *gain = -(-*gain).max(recapture);
+        }
+        gains[0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::STARTING_FEN;
+    use crate::variant::Capablanca;
+
+    fn from_fen(fen: &str) -> Board {
+        BoardState::from_fen(fen).unwrap().board
+    }
+
+    fn coord(square: &str) -> BoardCoord {
+        square.parse().unwrap()
+    }
+
+    fn squares(squares: &[&str]) -> Bitboard {
+        squares.iter().fold(bitboard::EMPTY, |bb, square| {
+            bb | bitboard::bit(coord(square))
+        })
+    }
+
+    #[test]
+    fn test_attackers() {
+        let board = from_fen("4k3/8/3p4/4p3/8/3N1N2/8/4R1K1 w - - 0 1");
+        assert_eq!(
+            board.attackers(coord("e5"), Color::White),
+            squares(&["d3", "f3", "e1"])
+        );
+        assert_eq!(board.attackers(coord("e5"), Color::Black), squares(&["d6"]));
+        // The pawn on e5 blocks the rook
+        assert_eq!(board.attackers(coord("e6"), Color::White), bitboard::EMPTY);
+        assert_eq!(board.attacks_from(coord("a1")), bitboard::EMPTY);
+
+        let map = board.attack_map();
+        assert_eq!(map.count(coord("e5"), Color::White), 3);
+        assert_eq!(map.count(coord("e5"), Color::Black), 1);
+        assert_eq!(map.count(coord("e4"), Color::White), 1);
+        assert_eq!(map.count(coord("e4"), Color::Black), 0);
+        assert_eq!(map.count(coord("d4"), Color::White), 1);
+        assert_eq!(map.count(coord("d4"), Color::Black), 1);
+    }
+
+    #[test]
+    fn test_pins_and_checkers() {
+        let board = from_fen("4r1k1/8/8/8/8/8/4B3/4K3 w - - 0 1");
+        assert_eq!(
+            board.pins(Color::White),
+            vec![Pin {
+                pinned: coord("e2"),
+                pinner: coord("e8"),
+            }]
+        );
+        assert_eq!(board.pinned(Color::White), squares(&["e2"]));
+        assert_eq!(board.pinned(Color::Black), bitboard::EMPTY);
+        assert_eq!(board.checkers(Color::White), bitboard::EMPTY);
+
+        // Double check, while the knight on d2 is pinned
+        let board = from_fen("4k3/8/8/8/1b6/5n2/3N4/4K2r w - - 0 1");
+        assert_eq!(board.checkers(Color::White), squares(&["f3", "h1"]));
+        assert_eq!(board.pinned(Color::White), squares(&["d2"]));
+        // A piece of the same color isn't pinned by its own pieces
+        assert_eq!(board.checkers(Color::Black), bitboard::EMPTY);
+        assert_eq!(board.pins(Color::Black), vec![]);
+    }
+
+    #[test]
+    fn test_see() {
+        let see = |fen: &str, uci: &str| {
+            let state = BoardState::from_fen(fen).unwrap();
+            state.board.see(state.parse_uci_move(uci).unwrap())
+        };
+        // The pawn is defended by another pawn
+        assert_eq!(see("4k3/3p4/4p3/8/8/8/8/4QK2 w - - 0 1", "e1e6"), -800);
+        assert_eq!(see("4k3/8/4p3/8/8/8/8/4QK2 w - - 0 1", "e1e6"), 100);
+        // The rook behind the first one recaptures
+        assert_eq!(see("4r1k1/8/4n3/8/8/8/4R3/4R1K1 w - - 0 1", "e2e6"), 320);
+        assert_eq!(see("4r1k1/8/4n3/8/8/8/4R3/6K1 w - - 0 1", "e2e6"), -180);
+        // The king may only recapture if the square isn't defended
+        assert_eq!(see("4k3/5p2/8/8/8/8/8/5QK1 w - - 0 1", "f1f7"), -800);
+        assert_eq!(see("4k3/5p2/8/8/2B5/8/8/5QK1 w - - 0 1", "f1f7"), 100);
+        // Moving the queen where a pawn can take it
+        assert_eq!(see("4k3/8/3p4/8/8/8/8/4QK2 w - - 0 1", "e1e5"), -900);
+        assert_eq!(see("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), 100);
+    }
+
+    #[test]
+    fn test_attack_map_matches_attackers() {
+        let fens = [
+            STARTING_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            Capablanca::STARTING_FEN,
+            "4k5/3c6/10/2a7/10/4M5/1G8/4K5 w - - 0 1",
+        ];
+        for fen in fens.iter() {
+            let board = from_fen(fen);
+            let map = board.attack_map();
+            for square in bitboard::squares(board.area()) {
+                let coord = bitboard::coord(square);
+                for &color in [Color::White, Color::Black].iter() {
+                    let attackers = board.attackers(coord, color);
+                    assert_eq!(
+                        map.count(coord, color),
+                        attackers.count_ones() as u8,
+                        "{} {} {:?}",
+                        fen,
+                        coord,
+                        color
+                    );
+                }
+            }
+        }
+    }
+}
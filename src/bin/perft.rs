@@ -0,0 +1,45 @@
+use std::time::Instant;
+
+use chess::board::BoardState;
+
+// Usage: perft "<fen>" <depth>
+// Prints the node count after each move and the total, in the same format as
+// Stockfish's "go perft", so the output of the two can be diffed.
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() != 3 {
+        eprintln!("Usage: {} \"<fen>\" <depth>", args[0]);
+        std::process::exit(1);
+    }
+
+    let state = match BoardState::from_fen(&args[1]) {
+        Ok(state) => state,
+        Err(err) => {
+            eprintln!("Couldn't read FEN: {}", err);
+            std::process::exit(1);
+        }
+    };
+    let depth: u32 = match args[2].parse() {
+        Ok(depth) => depth,
+        Err(_) => {
+            eprintln!("Expected a depth, got \"{}\"", args[2]);
+            std::process::exit(1);
+        }
+    };
+
+    let now = Instant::now();
+    let divide = state.divide(depth);
+    let nodes = if depth == 0 {
+        1
+    } else {
+        divide.iter().map(|(_, nodes)| nodes).sum()
+    };
+    let duration = now.elapsed();
+
+    for (mv, nodes) in divide {
+        println!("{}: {}", mv, nodes);
+    }
+    println!();
+    println!("Nodes searched: {}", nodes);
+    println!("Took {:?}", duration);
+}
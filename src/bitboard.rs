@@ -0,0 +1,690 @@
+//! Bitboards and precomputed attack tables. See https://www.chessprogramming.org/Bitboards
+//! A bitboard is a `u128` with one bit per square of a 16x8 grid, where bit
+//! `16 * rank + file` is set if that square is in the set. Every board, no
+//! matter its size, is laid out on this grid starting from a1, so a board with
+//! fewer files or ranks just never uses some of the bits. This lets us find
+//! every square a piece attacks with a table lookup and a few bitwise
+//! operations, instead of walking over the board tile by tile.
+//! The tables don't know how large the board is, so attacks may include
+//! squares past its edges. `Board` masks these off with `Dimensions::area`.
+//! Attacks of the sliding pieces (Rooks, Bishops, and Queens) depend on which
+//! squares are occupied. On a standard 8x8 board, they are found using "fancy"
+//! magic bitboards. See https://www.chessprogramming.org/Magic_Bitboards
+//! Magic tables for the whole grid would be far too large, so on boards of any
+//! other size they are found by looking up the ray in each direction and
+//! cutting it off at the first blocker. See
+//! https://www.chessprogramming.org/Classical_Approach
+
+use lazy_static::lazy_static;
+
+use crate::board::{BoardCoord, Color};
+
+pub type Bitboard = u128;
+
+/// The number of files and ranks of the grid. Boards may be at most this large.
+pub const FILES: usize = 16;
+pub const RANKS: usize = 8;
+/// The number of squares of the grid.
+pub const SQUARES: usize = FILES * RANKS;
+
+pub const EMPTY: Bitboard = 0;
+/// Every square of the first rank of the grid.
+pub const RANK_1: Bitboard = (1 << FILES) - 1;
+
+/// Every dark square of the grid, such as a1. Every other square is light.
+pub const DARK_SQUARES: Bitboard = dark_squares();
+
+/// Return the square index (`16 * rank + file`) of `coord`.
+pub const fn square(coord: BoardCoord) -> usize {
+    FILES * coord.1 as usize + coord.0 as usize
+}
+
+/// Return the coordinate of the square index `square`.
+pub fn coord(square: usize) -> BoardCoord {
+    BoardCoord((square % FILES) as i8, (square / FILES) as i8)
+}
+
+/// Return a bitboard containing only `coord`.
+pub const fn bit(coord: BoardCoord) -> Bitboard {
+    1 << square(coord)
+}
+
+/// Return every square of `rank`.
+pub fn rank(rank: i8) -> Bitboard {
+    RANK_1 << (FILES * rank as usize)
+}
+
+/// An iterator over the square indicies in a bitboard, from a1 upwards.
+pub struct Squares(Bitboard);
+
+impl Iterator for Squares {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.0 == EMPTY {
+            return None;
+        }
+        let square = self.0.trailing_zeros() as usize;
+        // Clear the lowest set bit
+        self.0 &= self.0 - 1;
+        Some(square)
+    }
+}
+
+/// Iterate over the square indicies in `bitboard`.
+pub fn squares(bitboard: Bitboard) -> Squares {
+    Squares(bitboard)
+}
+
+const KNIGHT_DELTAS: [(i8, i8); 8] = [
+    (1, 2),
+    (2, 1),
+    (-1, 2),
+    (-2, 1),
+    (1, -2),
+    (2, -1),
+    (-1, -2),
+    (-2, -1),
+];
+
+const KING_DELTAS: [(i8, i8); 8] = [
+    (0, 1),
+    (1, 1),
+    (1, 0),
+    (1, -1),
+    (0, -1),
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+];
+
+/// The directions of the rays in `RAYS`. The first four go towards higher
+/// squares and the last four towards lower ones.
+const DIRECTIONS: [(i8, i8); 8] = [
+    (1, 0),
+    (0, 1),
+    (1, 1),
+    (-1, 1),
+    (-1, 0),
+    (0, -1),
+    (-1, -1),
+    (1, -1),
+];
+/// Indicies into `DIRECTIONS` of the lines a Rook or Bishop moves along.
+const ROOK_DIRECTIONS: [usize; 4] = [0, 1, 4, 5];
+const BISHOP_DIRECTIONS: [usize; 4] = [2, 3, 6, 7];
+
+/// Returns true if `(file, rank)` is on the grid.
+const fn on_grid(file: i8, rank: i8) -> bool {
+    0 <= file && file < FILES as i8 && 0 <= rank && rank < RANKS as i8
+}
+
+/// Build a table of the squares a piece which jumps by `deltas` attacks from
+/// each square. This is done at compile time.
+const fn jump_table(deltas: &[(i8, i8); 8]) -> [Bitboard; SQUARES] {
+    let mut table = [EMPTY; SQUARES];
+    let mut square = 0;
+    while square < SQUARES {
+        let mut i = 0;
+        while i < 8 {
+            let file = (square % FILES) as i8 + deltas[i].0;
+            let rank = (square / FILES) as i8 + deltas[i].1;
+            if on_grid(file, rank) {
+                table[square] |= 1 << (FILES as i8 * rank + file);
+            }
+            i += 1;
+        }
+        square += 1;
+    }
+    table
+}
+
+/// Build the bitboard of the dark squares, which are the squares whose file and
+/// rank add up to an even number. This is done at compile time.
+const fn dark_squares() -> Bitboard {
+    let mut dark = EMPTY;
+    let mut square = 0;
+    while square < SQUARES {
+        if (square % FILES + square / FILES) & 1 == 0 {
+            dark |= 1 << square;
+        }
+        square += 1;
+    }
+    dark
+}
+
+/// Build a table of the squares a pawn of each color attacks from each square.
+/// Indexed by `Color as usize` and then by square.
+const fn pawn_table() -> [[Bitboard; SQUARES]; 2] {
+    let mut table = [[EMPTY; SQUARES]; 2];
+    let mut square = 0;
+    while square < SQUARES {
+        let file = (square % FILES) as i8;
+        let rank = (square / FILES) as i8;
+        // White pawns attack up the board and black pawns attack down it.
+        let mut color = 0;
+        while color < 2 {
+            let rank = if color == 0 { rank + 1 } else { rank - 1 };
+            let mut side = -1;
+            while side <= 1 {
+                if on_grid(file + side, rank) {
+                    table[color][square] |= 1 << (FILES as i8 * rank + file + side);
+                }
+                side += 2;
+            }
+            color += 1;
+        }
+        square += 1;
+    }
+    table
+}
+
+/// Build a table of the squares from each square (not including it) to the
+/// edge of the grid, in each of `DIRECTIONS`. Indexed by direction and then by
+/// square.
+const fn ray_table() -> [[Bitboard; SQUARES]; 8] {
+    let mut table = [[EMPTY; SQUARES]; 8];
+    let mut direction = 0;
+    while direction < 8 {
+        let (dx, dy) = DIRECTIONS[direction];
+        let mut square = 0;
+        while square < SQUARES {
+            let mut file = (square % FILES) as i8 + dx;
+            let mut rank = (square / FILES) as i8 + dy;
+            while on_grid(file, rank) {
+                table[direction][square] |= 1 << (FILES as i8 * rank + file);
+                file += dx;
+                rank += dy;
+            }
+            square += 1;
+        }
+        direction += 1;
+    }
+    table
+}
+
+const KNIGHT_ATTACKS: [Bitboard; SQUARES] = jump_table(&KNIGHT_DELTAS);
+const KING_ATTACKS: [Bitboard; SQUARES] = jump_table(&KING_DELTAS);
+const PAWN_ATTACKS: [[Bitboard; SQUARES]; 2] = pawn_table();
+const RAYS: [[Bitboard; SQUARES]; 8] = ray_table();
+
+/// The squares a piece on `square` sees in `DIRECTIONS[direction]`, up to and
+/// including the first square in `occupied`.
+#[inline]
+fn ray_attacks(square: usize, occupied: Bitboard, direction: usize) -> Bitboard {
+    let ray = RAYS[direction][square];
+    let blockers = ray & occupied;
+    if blockers == EMPTY {
+        return ray;
+    }
+    // The closest blocker is the lowest one for rays going up the board, and
+    // the highest one for rays going down.
+    let blocker = if direction < 4 {
+        blockers.trailing_zeros() as usize
+    } else {
+        SQUARES - 1 - blockers.leading_zeros() as usize
+    };
+    ray ^ RAYS[direction][blocker]
+}
+
+/// The magic multipliers for Rook attacks on a standard board, indexed by
+/// `8 * rank + file`. These were found by trial and error with random sparse
+/// numbers, and map every relevant occupancy of the Rook's lines of sight (as
+/// packed by `fold`) to an index in its attack table without any harmful
+/// collisions.
+const ROOK_MAGICS: [u64; 64] = [
+    0x2080_0020_8040_0010,
+    0x00C0_0020_0140_1000,
+    0x2100_1100_0840_2002,
+    0x0880_0800_8104_1000,
+    0x0200_0200_2004_1008,
+    0x2300_0400_0801_0012,
+    0x0C00_2830_0400_8201,
+    0x0180_0100_0040_7A80,
+    0x0640_8080_0040_0021,
+    0x0004_8280_2000_4000,
+    0x4001_0100_4020_0010,
+    0x2802_1200_2200_4008,
+    0x0481_0300_1008_0004,
+    0x0244_0080_0200_0480,
+    0x0000_0400_1081_0208,
+    0x2000_0200_0044_8534,
+    0x4A08_4000_8480_0025,
+    0x00C1_0640_0100_2980,
+    0x8080_2001_0100_1840,
+    0x9021_0020_0900_1001,
+    0x0000_0800_8080_0401,
+    0x0404_8002_0080_0400,
+    0x0100_3001_0400_0258,
+    0x2020_4494_0A00_0045,
+    0x0008_2144_8211_0200,
+    0x5041_008A_0020_4E00,
+    0x2030_0480_1020_0080,
+    0x0021_0008_2010_0100,
+    0x1441_0004_0802_1100,
+    0x4700_0400_0200_8080,
+    0x1308_5102_0890_0400,
+    0x4008_4400_8041_0200,
+    0x6001_8000_4000_8024,
+    0x9208_4010_0040_2004,
+    0x000C_8020_0081_1000,
+    0x2011_8008_0180_1000,
+    0x2002_0009_0600_2010,
+    0x2002_8002_0004_0080,
+    0x1104_0008_1001_0402,
+    0x0402_0002_1040_A504,
+    0x0040_4000_8000_2080,
+    0x0A00_4006_8020_0090,
+    0x0200_1000_8080_2000,
+    0x8000_0800_8010_0086,
+    0x0040_0400_8008_0080,
+    0xE204_0040_4002_0100,
+    0x2040_1004_0052_0138,
+    0x0000_0042_0000_8134,
+    0x3051_82B0_4004_8000,
+    0x5030_0040_2010_4000,
+    0xA042_0840_8022_0010,
+    0x4088_0800_1000_8080,
+    0x5002_0801_0011_0004,
+    0x2012_0020_1004_0400,
+    0x0040_3182_1044_0008,
+    0x0120_9410_4082_0001,
+    0x0050_8003_0010_2045,
+    0x8208_2100_4012_0882,
+    0x8010_6001_0118_3441,
+    0x020B_0009_1000_6045,
+    0x0241_0010_0248_0005,
+    0x0081_0004_0088_0241,
+    0x0000_0090_0802_4124,
+    0x0048_1229_8041_0402,
+];
+
+/// The magic multipliers for Bishop attacks on a standard board. See
+/// `ROOK_MAGICS`.
+const BISHOP_MAGICS: [u64; 64] = [
+    0x4001_2001_0202_1000,
+    0x8001_0401_0181_1808,
+    0x0001_1010_0130_0040,
+    0x0000_1404_0419_0580,
+    0x0000_0400_1048_2620,
+    0x0020_3200_5280_0280,
+    0x0418_0060_0125_D000,
+    0x0002_0810_0640_4600,
+    0x2010_8420_9000_9000,
+    0x0013_2002_2020_A402,
+    0x0414_0A10_2208_1200,
+    0x0000_810A_8410_0400,
+    0x8060_0011_0402_C240,
+    0x0882_4011_0440_1002,
+    0x0042_0102_4420_6200,
+    0x3061_4C08_2920_0300,
+    0x0004_4048_1018_9008,
+    0x0C21_0448_0211_1000,
+    0x0042_010A_0402_0200,
+    0x0402_0800_4002_0088,
+    0x0008_1040_1080_2200,
+    0x2004_0020_0060_4442,
+    0x408A_2201_4820_2080,
+    0x011E_0089_0030_0420,
+    0x0410_4530_0490_0800,
+    0x0D10_9004_8400_8000,
+    0xAA10_0049_0912_0000,
+    0x0004_0104_0800_1013,
+    0x4102_0102_0401_0001,
+    0x0009_4408_0020_0208,
+    0x4081_0104_0050_2008,
+    0x2110_8400_8024_2004,
+    0x5100_A804_2044_D104,
+    0x8634_0422_4A24_2051,
+    0x4002_0401_1402_0201,
+    0x0080_0020_4010_0403,
+    0x0000_0404_2008_0200,
+    0x0070_0804_1002_0022,
+    0x0601_0404_00C4_0080,
+    0x0010_3402_0081_202C,
+    0x8009_0400_0440_021B,
+    0x0024_4208_0612_1000,
+    0x0004_0200_0112_0402,
+    0x0044_0900_0202_0210,
+    0x0010_4884_0804_0100,
+    0x9100_4000_020A_0806,
+    0x0004_4840_0800_8800,
+    0x0041_0400_0084_4400,
+    0x0010_C440_0202_0200,
+    0x0014_0430_2480_0822,
+    0x0004_0041_0080_0094,
+    0x0020_8008_0000_0020,
+    0x8028_2412_0381_4208,
+    0x4804_0801_0420_6000,
+    0x0084_0810_6000_4404,
+    0x4002_4888_5004_4400,
+    0x0214_0042_0020_4820,
+    0x2004_40C2_0020_0100,
+    0x1800_4024_0042_0080,
+    0x8008_4042_0208_8980,
+    0x0070_0812_1022_0A80,
+    0x0808_0801_0804_4002,
+    0x208A_00A8_1E02_080B,
+    0x11C4_0822_2022_A400,
+];
+
+/// The magic bitboard lookup for the attacks of a sliding piece on one square
+/// of a standard board.
+struct Magic {
+    /// The squares whose occupancy can block the piece. The edges of the board
+    /// are left out, as a piece on the edge doesn't block anything beyond it.
+    mask: Bitboard,
+    magic: u64,
+    shift: u32,
+    /// Where this square's attacks start in `SliderTables::attacks`
+    offset: usize,
+}
+
+impl Magic {
+    fn index(&self, occupied: Bitboard) -> usize {
+        self.offset + (fold(occupied & self.mask).wrapping_mul(self.magic) >> self.shift) as usize
+    }
+}
+
+/// Pack the squares of a standard board in `bitboard` into a `u64` so that it
+/// can be multiplied by a magic. The first four ranks stay where they are, and
+/// the last four move into the unused files of the first four. `bitboard` must
+/// not have any squares off the standard board.
+fn fold(bitboard: Bitboard) -> u64 {
+    bitboard as u64 | ((bitboard >> 64) as u64) << 8
+}
+
+/// Return the index of `square` on a standard board, which is
+/// `8 * rank + file`.
+fn standard_square(square: usize) -> usize {
+    8 * (square / FILES) + square % FILES
+}
+
+/// The tables which are too large to build at compile time. These are built
+/// the first time they are used.
+struct SliderTables {
+    /// The magic lookups of Rooks and Bishops on a standard board, indexed by
+    /// `standard_square`.
+    rook: Vec<Magic>,
+    bishop: Vec<Magic>,
+    /// The attacks of every square and relevant occupancy of a standard board,
+    /// shared by both Rooks and Bishops.
+    attacks: Vec<Bitboard>,
+    /// Indexed by two squares. The squares strictly between them if they are on
+    /// the same rank, file, or diagonal, otherwise empty.
+    between: Vec<[Bitboard; SQUARES]>,
+    /// Indexed by two squares. The entire rank, file, or diagonal which passes
+    /// through both squares, or empty if there is none.
+    line: Vec<[Bitboard; SQUARES]>,
+}
+
+impl SliderTables {
+    fn new() -> SliderTables {
+        let mut attacks = vec![];
+        let rook = init_magics(&ROOK_MAGICS, &ROOK_DIRECTIONS, &mut attacks);
+        let bishop = init_magics(&BISHOP_MAGICS, &BISHOP_DIRECTIONS, &mut attacks);
+
+        let mut between = vec![[EMPTY; SQUARES]; SQUARES];
+        let mut line = vec![[EMPTY; SQUARES]; SQUARES];
+        for start in 0..SQUARES {
+            for (direction, rays) in RAYS.iter().enumerate() {
+                let opposite = (direction + 4) % 8;
+                let full_line = rays[start] | RAYS[opposite][start] | 1 << start;
+                let mut passed = EMPTY;
+                for end in squares_in_order(start, direction) {
+                    between[start][end] = passed;
+                    line[start][end] = full_line;
+                    passed |= 1 << end;
+                }
+            }
+        }
+        SliderTables {
+            rook,
+            bishop,
+            attacks,
+            between,
+            line,
+        }
+    }
+}
+
+/// Build the magic lookups for a sliding piece moving in `directions` on a
+/// standard board, adding its attacks to the end of `attacks`.
+fn init_magics(
+    magics: &[u64; 64],
+    directions: &[usize],
+    attacks: &mut Vec<Bitboard>,
+) -> Vec<Magic> {
+    let standard = (0..8).fold(EMPTY, |area, rank| area | 0xFF << (FILES * rank));
+    let mut table = Vec::with_capacity(64);
+    for (index, &magic) in magics.iter().enumerate() {
+        let square = FILES * (index / 8) + index % 8;
+        let mut mask = EMPTY;
+        for &direction in directions {
+            // Leave off the last square of each ray, which is on the edge.
+            let ray: Vec<usize> = squares_in_order(square, direction)
+                .into_iter()
+                .take_while(|&square| standard & 1 << square != EMPTY)
+                .collect();
+            for &square in ray.iter().rev().skip(1) {
+                mask |= 1 << square;
+            }
+        }
+
+        let bits = mask.count_ones();
+        let entry = Magic {
+            mask,
+            magic,
+            shift: 64 - bits,
+            offset: attacks.len(),
+        };
+        attacks.resize(attacks.len() + (1 << bits), EMPTY);
+
+        // Enumerate every subset of the mask (the "Carry-Rippler" trick). The
+        // squares off the standard board block the rays like any other piece,
+        // and are then taken back off.
+        let mut occupied = EMPTY;
+        loop {
+            let blockers = occupied | !standard;
+            let ray_attacks = directions.iter().fold(EMPTY, |attacks, &direction| {
+                attacks | ray_attacks(square, blockers, direction)
+            });
+            attacks[entry.index(occupied)] = ray_attacks & standard;
+            occupied = occupied.wrapping_sub(mask) & mask;
+            if occupied == EMPTY {
+                break;
+            }
+        }
+        table.push(entry);
+    }
+    table
+}
+
+/// Return the squares of the ray from `square` in `DIRECTIONS[direction]`,
+/// closest first.
+fn squares_in_order(square: usize, direction: usize) -> Vec<usize> {
+    let mut ray: Vec<usize> = squares(RAYS[direction][square]).collect();
+    if direction >= 4 {
+        ray.reverse();
+    }
+    ray
+}
+
+lazy_static! {
+    static ref TABLES: SliderTables = SliderTables::new();
+}
+
+/// The squares a Knight on `square` attacks.
+pub fn knight_attacks(square: usize) -> Bitboard {
+    KNIGHT_ATTACKS[square]
+}
+
+/// The squares a King on `square` attacks.
+pub fn king_attacks(square: usize) -> Bitboard {
+    KING_ATTACKS[square]
+}
+
+/// The squares a pawn of `color` on `square` attacks. Note that this is only
+/// the diagonal captures, not the squares the pawn can move forward to.
+pub fn pawn_attacks(color: Color, square: usize) -> Bitboard {
+    PAWN_ATTACKS[color as usize][square]
+}
+
+/// The squares a Rook on `square` attacks, given the `occupied` squares.
+pub fn rook_attacks(square: usize, occupied: Bitboard) -> Bitboard {
+    ROOK_DIRECTIONS.iter().fold(EMPTY, |attacks, &direction| {
+        attacks | ray_attacks(square, occupied, direction)
+    })
+}
+
+/// The squares a Bishop on `square` attacks, given the `occupied` squares.
+pub fn bishop_attacks(square: usize, occupied: Bitboard) -> Bitboard {
+    BISHOP_DIRECTIONS.iter().fold(EMPTY, |attacks, &direction| {
+        attacks | ray_attacks(square, occupied, direction)
+    })
+}
+
+/// The squares a Queen on `square` attacks, given the `occupied` squares.
+pub fn queen_attacks(square: usize, occupied: Bitboard) -> Bitboard {
+    rook_attacks(square, occupied) | bishop_attacks(square, occupied)
+}
+
+/// Like `rook_attacks`, but much faster, for a Rook on a standard 8x8 board.
+/// `square` must be on the standard board, and the attacks stop at its edges.
+pub fn standard_rook_attacks(square: usize, occupied: Bitboard) -> Bitboard {
+    let magic = &TABLES.rook[standard_square(square)];
+    TABLES.attacks[magic.index(occupied)]
+}
+
+/// Like `bishop_attacks`, but for a Bishop on a standard 8x8 board. See
+/// `standard_rook_attacks`.
+pub fn standard_bishop_attacks(square: usize, occupied: Bitboard) -> Bitboard {
+    let magic = &TABLES.bishop[standard_square(square)];
+    TABLES.attacks[magic.index(occupied)]
+}
+
+/// Like `queen_attacks`, but for a Queen on a standard 8x8 board. See
+/// `standard_rook_attacks`.
+pub fn standard_queen_attacks(square: usize, occupied: Bitboard) -> Bitboard {
+    standard_rook_attacks(square, occupied) | standard_bishop_attacks(square, occupied)
+}
+
+/// The squares strictly between `a` and `b`, if they share a rank, file, or
+/// diagonal. Otherwise, this is empty.
+pub fn between(a: usize, b: usize) -> Bitboard {
+    TABLES.between[a][b]
+}
+
+/// The entire rank, file, or diagonal passing through both `a` and `b`, or
+/// empty if there is none.
+pub fn line(a: usize, b: usize) -> Bitboard {
+    TABLES.line[a][b]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Find the attacks of a sliding piece the slow way, by walking along each
+    /// of its lines of sight until hitting an occupied square.
+    fn sliding_attacks(square: usize, occupied: Bitboard, directions: &[usize]) -> Bitboard {
+        let mut attacks = EMPTY;
+        for &direction in directions {
+            for square in squares_in_order(square, direction) {
+                attacks |= 1 << square;
+                if occupied & 1 << square != EMPTY {
+                    break;
+                }
+            }
+        }
+        attacks
+    }
+
+    #[test]
+    fn test_slider_attacks() {
+        // Every slider attack should match walking the lines of sight, for an
+        // assortment of occupancies.
+        let mut occupied: u64 = 0x9E37_79B9_7F4A_7C15;
+        for _ in 0..64 {
+            occupied ^= occupied << 13;
+            occupied ^= occupied >> 7;
+            occupied ^= occupied << 17;
+            let occupied =
+                Bitboard::from(occupied) << 64 | Bitboard::from(occupied.rotate_left(29));
+            for square in 0..SQUARES {
+                assert_eq!(
+                    rook_attacks(square, occupied),
+                    sliding_attacks(square, occupied, &ROOK_DIRECTIONS)
+                );
+                assert_eq!(
+                    bishop_attacks(square, occupied),
+                    sliding_attacks(square, occupied, &BISHOP_DIRECTIONS)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_standard_slider_attacks() {
+        // On a standard board, the magic lookups should match the rays cut
+        // off at the edges of the board.
+        let standard = (0..8).fold(EMPTY, |area, rank| area | 0xFF << (FILES * rank));
+        let mut occupied: u64 = 0x2545_F491_4F6C_DD1D;
+        for _ in 0..64 {
+            occupied ^= occupied << 13;
+            occupied ^= occupied >> 7;
+            occupied ^= occupied << 17;
+            // Squares off the standard board shouldn't matter.
+            let occupied =
+                Bitboard::from(occupied) << 64 | Bitboard::from(occupied.rotate_left(29));
+            for square in squares(standard) {
+                assert_eq!(
+                    standard_rook_attacks(square, occupied),
+                    sliding_attacks(square, occupied | !standard, &ROOK_DIRECTIONS) & standard
+                );
+                assert_eq!(
+                    standard_bishop_attacks(square, occupied),
+                    sliding_attacks(square, occupied | !standard, &BISHOP_DIRECTIONS) & standard
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_between_and_line() {
+        let a1 = square(BoardCoord(0, 0));
+        let d4 = square(BoardCoord(3, 3));
+        let h8 = square(BoardCoord(7, 7));
+        let b3 = square(BoardCoord(1, 2));
+        assert_eq!(
+            between(a1, d4),
+            bit(BoardCoord(1, 1)) | bit(BoardCoord(2, 2))
+        );
+        assert_eq!(between(d4, a1), between(a1, d4));
+        assert_eq!(between(a1, b3), EMPTY);
+        let diagonal = (0..8).fold(EMPTY, |line, i| line | bit(BoardCoord(i, i)));
+        assert_eq!(line(d4, h8), diagonal);
+        assert_eq!(line(a1, b3), EMPTY);
+    }
+
+    #[test]
+    fn test_jump_attacks() {
+        let a1 = square(BoardCoord(0, 0));
+        assert_eq!(
+            knight_attacks(a1),
+            bit(BoardCoord(1, 2)) | bit(BoardCoord(2, 1))
+        );
+        assert_eq!(king_attacks(a1).count_ones(), 3);
+        assert_eq!(
+            pawn_attacks(Color::White, square(BoardCoord(4, 1))),
+            bit(BoardCoord(3, 2)) | bit(BoardCoord(5, 2))
+        );
+        assert_eq!(
+            pawn_attacks(Color::Black, square(BoardCoord(0, 6))),
+            bit(BoardCoord(1, 5))
+        );
+        // Pieces on the i file and beyond attack like any other.
+        let j1 = square(BoardCoord(9, 0));
+        assert_eq!(king_attacks(j1).count_ones(), 5);
+    }
+}
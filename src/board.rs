@@ -1,12 +1,18 @@
 use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::bitboard::{self, Bitboard};
+use crate::fairy::Fairy;
+use crate::outcome::{GameOutcome, Termination};
+use crate::variant::{Standard, Variant};
+use crate::zobrist;
 
 #[cfg(feature = "perf")]
 use flame as fire;
 #[cfg(feature = "perf")]
 use flamer::flame;
 
-const ROWS: std::ops::Range<i8> = 0..8;
-const COLS: std::ops::Range<i8> = 0..8;
 pub const PAWN_STR: &str = "♟";
 pub const KNIGHT_STR: &str = "♞";
 pub const BISHOP_STR: &str = "♝";
@@ -24,137 +30,698 @@ pub struct BoardState {
     /// The color of the player-to-move
     pub current_player: Color,
     pub checkmate: CheckmateState,
+    /// The number of halfmoves since the last capture or pawn move. This is
+    /// used for the fifty-move rule.
+    pub halfmove_clock: u32,
+    /// The number of the full move. This starts at 1 and is incremented after
+    /// each of Black's moves.
+    pub fullmove_number: u32,
+    /// The moves made so far this game, oldest first.
+    history: Vec<HistoryEntry>,
+    /// The moves which have been undone, most recently undone last. This is
+    /// cleared whenever a new move is made.
+    redo_stack: Vec<HistoryEntry>,
+    /// The Zobrist hash of the current position. This is updated incrementally
+    /// as moves are made. See the `zobrist` module.
+    hash: u64,
+    /// The hashes of the positions before each move in `history`. This is used
+    /// to detect repeated positions.
+    position_history: Vec<u64>,
+    /// The rules this game is played with. See the `variant` module.
+    variant: Arc<dyn Variant>,
+    /// `Variant::standard_rules` of `variant`. Moves are made and generated
+    /// without calling into `variant` at all if this is true, since most
+    /// games (and most searches by the AI) are of standard chess.
+    standard_rules: bool,
+    /// The pieces each player has captured and may drop, indexed by
+    /// `Color as usize`. These are only used in variants where
+    /// `Variant::uses_pockets` is true, and are otherwise always empty.
+    pockets: [Pocket; 2],
+    /// The number of checks each player has given, indexed by
+    /// `Color as usize`. These are only counted in variants where
+    /// `Variant::counts_checks` is true, and are otherwise always 0.
+    checks: [u8; 2],
+    /// How the game ended, if it was ended by something other than the
+    /// position, such as a resignation. See `set_outcome`.
+    ended: Option<GameOutcome>,
 }
 
 impl BoardState {
     /// Create a board state using the board given. The player-to-move will
     /// initially be white.
     pub fn new(board: Board) -> BoardState {
-        let checkmate = board.checkmate_state(Color::White);
-        BoardState {
+        BoardState::new_with_player(board, Color::White)
+    }
+
+    /// Create a board state using the board given, with `player` as the
+    /// player-to-move.
+    pub fn new_with_player(board: Board, player: Color) -> BoardState {
+        BoardState::new_with_rules(board, player, Arc::new(Standard))
+    }
+
+    /// Create a board state for a new game of `variant`, starting from the
+    /// variant's starting board.
+    pub fn new_with_variant(variant: Arc<dyn Variant>) -> BoardState {
+        BoardState::new_with_rules(variant.starting_board(), Color::White, variant)
+    }
+
+    /// Create a board state using the board given, with `player` as the
+    /// player-to-move, which is played using the rules of `variant`.
+    pub fn new_with_rules(board: Board, player: Color, variant: Arc<dyn Variant>) -> BoardState {
+        let hash = zobrist::hash(&board, player);
+        let standard_rules = variant.standard_rules();
+        let mut state = BoardState {
             board,
-            current_player: Color::White,
-            checkmate,
+            current_player: player,
+            checkmate: CheckmateState::Normal,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            history: vec![],
+            redo_stack: vec![],
+            hash,
+            position_history: vec![],
+            variant,
+            standard_rules,
+            pockets: [Pocket::default(); 2],
+            checks: [0; 2],
+            ended: None,
+        };
+        state.checkmate = state.variant.checkmate_state(&state);
+        state
+    }
+
+    /// The rules this game is played with.
+    pub fn variant(&self) -> &dyn Variant {
+        &*self.variant
+    }
+
+    /// The rules this game is played with, for sharing with another
+    /// `BoardState`.
+    pub(crate) fn shared_variant(&self) -> Arc<dyn Variant> {
+        self.variant.clone()
+    }
+
+    /// Return all of the legal moves of the player-to-move, including any
+    /// moves which are special to the variant being played.
+    pub fn legal_moves(&self) -> Vec<Move> {
+        let mut moves = self.board.get_all_moves(self.current_player);
+        if self.standard_rules {
+            return moves;
         }
+        let promotions = self.variant.promotion_pieces();
+        if promotions != PROMOTION_PIECES {
+            vary_promotions(&mut moves, promotions);
+        }
+        self.variant.extra_moves(self, &mut moves);
+        self.variant.filter_moves(self, &mut moves);
+        moves
     }
 
-    pub fn check_turn(&self, start: BoardCoord, end: BoardCoord) -> Result<(), &'static str> {
+    /// Return the winner of the game, or None if the game is drawn or is not
+    /// over yet.
+    pub fn winner(&self) -> Option<Color> {
+        self.outcome().and_then(|outcome| outcome.winner)
+    }
+
+    /// Return how the game ended, or None if it is not over yet. Unless the
+    /// game was ended by `set_outcome`, this comes from the checkmate state.
+    pub fn outcome(&self) -> Option<GameOutcome> {
+        self.ended
+            .or_else(|| GameOutcome::from_checkmate(self.checkmate, self.variant.winner(self)))
+    }
+
+    /// End the game with `outcome`, such as when a player resigns or runs out
+    /// of time. Undoing a move carries on the game.
+    pub fn set_outcome(&mut self, outcome: GameOutcome) {
+        self.ended = Some(outcome);
+    }
+
+    /// End the game with a win for the opponent of `color`.
+    pub fn resign(&mut self, color: Color) {
+        self.set_outcome(GameOutcome {
+            winner: Some(color.opposite()),
+            termination: Termination::Resignation,
+        });
+    }
+
+    /// End the game in a draw agreed to by both players.
+    pub fn agree_draw(&mut self) {
+        self.set_outcome(GameOutcome {
+            winner: None,
+            termination: Termination::AgreedDraw,
+        });
+    }
+
+    /// End the game because `color` ran out of time. Their opponent wins,
+    /// unless the opponent couldn't possibly win under the rules of the
+    /// variant (see `Variant::insufficient_to_win`), in which case the game
+    /// is drawn.
+    pub fn time_out(&mut self, color: Color) {
+        let opponent = color.opposite();
+        let outcome = if self.variant.insufficient_to_win(self, opponent) {
+            GameOutcome {
+                winner: None,
+                termination: Termination::TimeoutVsInsufficientMaterial,
+            }
+        } else {
+            GameOutcome {
+                winner: Some(opponent),
+                termination: Termination::Timeout,
+            }
+        };
+        self.set_outcome(outcome);
+    }
+
+    /// End the game with a result decided by someone other than the players,
+    /// such as when an engine match is stopped early.
+    pub fn adjudicate(&mut self, winner: Option<Color>) {
+        self.set_outcome(GameOutcome {
+            winner,
+            termination: Termination::Adjudication,
+        });
+    }
+
+    /// The pieces `color` has captured and may drop back onto the board. This
+    /// is always empty unless the variant uses pockets, such as Crazyhouse.
+    pub fn pocket(&self, color: Color) -> &Pocket {
+        &self.pockets[color as usize]
+    }
+
+    /// Replace the pocket of `color`, such as when setting up a position.
+    pub fn set_pocket(&mut self, color: Color, pocket: Pocket) {
+        self.hash ^= zobrist::pocket_hash(color, &self.pockets[color as usize]);
+        self.pockets[color as usize] = pocket;
+        self.hash ^= zobrist::pocket_hash(color, &pocket);
+        self.checkmate = self.variant.checkmate_state(self);
+    }
+
+    /// Add `piece` to the pocket of `color`, keeping the hash up to date.
+    fn add_to_pocket(&mut self, color: Color, piece: PieceType) {
+        let pocket = &mut self.pockets[color as usize];
+        let count = pocket.count(piece);
+        self.hash ^= zobrist::pocket_key(color, piece, count);
+        self.hash ^= zobrist::pocket_key(color, piece, count + 1);
+        pocket.add(piece);
+    }
+
+    /// Remove `piece` from the pocket of `color`, keeping the hash up to date.
+    fn remove_from_pocket(&mut self, color: Color, piece: PieceType) {
+        let pocket = &mut self.pockets[color as usize];
+        let count = pocket.count(piece);
+        self.hash ^= zobrist::pocket_key(color, piece, count);
+        self.hash ^= zobrist::pocket_key(color, piece, count - 1);
+        pocket.remove(piece);
+    }
+
+    /// The number of times `color` has put the other player in check. This is
+    /// always 0 unless the variant counts checks, such as Three-check.
+    pub fn checks_given(&self, color: Color) -> u8 {
+        self.checks[color as usize]
+    }
+
+    /// Set the number of checks `color` has given, such as when setting up a
+    /// position.
+    pub fn set_checks_given(&mut self, color: Color, checks: u8) {
+        self.hash ^= zobrist::checks_key(color, self.checks[color as usize]);
+        self.checks[color as usize] = checks;
+        self.hash ^= zobrist::checks_key(color, checks);
+        self.checkmate = self.variant.checkmate_state(self);
+    }
+
+    /// Set the halfmove clock and fullmove number, such as when setting up a
+    /// position. A halfmove clock of 150 or more ends the game by the 75-move
+    /// rule.
+    pub fn set_clocks(&mut self, halfmove_clock: u32, fullmove_number: u32) {
+        self.halfmove_clock = halfmove_clock;
+        self.fullmove_number = fullmove_number;
+        self.update_checkmate();
+    }
+
+    /// Compute the hash of the current position from scratch. This is used to
+    /// check that the incrementally updated hash is correct.
+    fn full_hash(&self) -> u64 {
+        zobrist::hash(&self.board, self.current_player)
+            ^ zobrist::pocket_hash(Color::White, self.pocket(Color::White))
+            ^ zobrist::pocket_hash(Color::Black, self.pocket(Color::Black))
+            ^ zobrist::checks_key(Color::White, self.checks_given(Color::White))
+            ^ zobrist::checks_key(Color::Black, self.checks_given(Color::Black))
+    }
+
+    /// Check if `mv` is legal for the player-to-move. A move of a pawn to the
+    /// last rank must have a promotion, and any other move must not. The move
+    /// must also be allowed by the variant being played.
+    pub fn check_turn(&self, mv: Move) -> Result<(), MoveError> {
+        match self.check_standard_turn(mv) {
+            Ok(()) => self.variant.check_move(self, mv),
+            Err(err) => {
+                let mut extra_moves = vec![];
+                self.variant.extra_moves(self, &mut extra_moves);
+                if extra_moves.contains(&mv) {
+                    self.variant.check_move(self, mv)
+                } else {
+                    Err(err)
+                }
+            }
+        }
+    }
+
+    /// Check if `mv` is legal for the player-to-move under the rules of
+    /// standard chess.
+    fn check_standard_turn(&self, mv: Move) -> Result<(), MoveError> {
         use MoveType::*;
+        let Move {
+            start,
+            end,
+            promotion,
+            ..
+        } = mv;
+
+        if let Some(square) = self.board.pawn_needs_promotion() {
+            return Err(MoveError::PromotionPending { square });
+        }
 
-        if self.board.pawn_needs_promotion().is_some() {
-            return Err("A pawn needs to be promoted");
+        if mv.is_drop() {
+            return Err(MoveError::DropsNotAllowed);
         }
 
         match move_type(&self.board, start, end) {
             Castle(color, side) => self.board.can_castle(color, side),
             Normal | Lunge | Capture => self.board.check_move(self.current_player, start, end),
             EnPassant(side) => self.board.check_enpassant(self.current_player, start, side),
+            Drop => unreachable!("Expected drops to be checked above"),
+        }?;
+
+        match (self.board.is_promotion(start, end), promotion) {
+            (true, None) => Err(MoveError::PromotionRequired { square: end }),
+            (true, Some(piece)) if !self.variant.promotion_pieces().contains(&piece) => {
+                Err(MoveError::InvalidPromotionPiece { piece })
+            }
+            (false, Some(_)) => Err(MoveError::UnexpectedPromotion { square: end }),
+            _ => Ok(()),
         }
     }
 
-    /// Move the piece located at `start` to `end`. This function panics if the
-    /// move would be illegal, so you should check the move first with `check_turn`
+    /// Parse a move in UCI long algebraic notation, such as "e2e4", "e7e8q",
+    /// "e1g1" for castling, or "N@f3" for a drop, and check that it is legal for the player-to-move.
+    /// A promotion piece must be given exactly when a pawn reaches the last rank.
+    pub fn parse_uci_move(&self, uci: &str) -> Result<Move, MoveError> {
+        let mv: Move = uci.parse().map_err(MoveError::InvalidNotation)?;
+        let mv = match mv.drop {
+            Some(_) => mv,
+            None => Move::new(&self.board, mv.start, mv.end, mv.promotion),
+        };
+        self.check_turn(mv)?;
+        Ok(mv)
+    }
+
+    /// Make the move `mv`, promoting the pawn if the move is a promotion. This
+    /// function panics if the move would be illegal, so you should check the
+    /// move first with `check_turn`
     /// It also sets `current_player` to the opposite color and handles the
     /// "just_lunged" pawn flags.
-    /// A pawn needs promotion then this function always fails. You should
-    /// call `promote` on the pawn.
+    /// The move is recorded in the move history, and any undone moves are
+    /// forgotten.
+    pub fn take_turn(&mut self, mv: Move) {
+        self.redo_stack.clear();
+        self.apply_turn(mv);
+    }
+
     #[cfg_attr(feature = "perf", flame)]
-    pub fn take_turn(&mut self, start: BoardCoord, end: BoardCoord) {
-        use Color::*;
+    fn apply_turn(&mut self, mv: Move) {
         use MoveType::*;
+        let Move {
+            start,
+            end,
+            promotion,
+            ..
+        } = mv;
 
-        debug_assert!(self.check_turn(start, end).is_ok());
+        debug_assert!(self.check_turn(mv).is_ok());
 
         #[cfg(feature = "perf")]
         let guard = fire::start_guard("move check + apply");
 
-        match move_type(&self.board, start, end) {
-            Castle(color, side) => {
-                // Clear the just lunged flags _after_ checking the move is valid
-                // That way, invalid moves don't try to clear the flag.
-                self.board.clear_just_lunged();
-                self.board.castle(color, side);
+        let move_type = match mv.drop {
+            Some(_) => Drop,
+            None => move_type(&self.board, start, end),
+        };
+
+        let move_coords = to_coords(&self.board, move_type, start, end);
+        let captured = match move_coords {
+            MoveTypeCoords::Capture { end, .. } => self.board.get(end).0,
+            MoveTypeCoords::EnPassant { captured_pawn, .. } => self.board.get(captured_pawn).0,
+            _ => None,
+        };
+        let moved = match mv.drop {
+            Some(piece) => dropped_piece(self.board.dimensions, self.current_player, piece, end),
+            None => self.board.get(start).0.expect("Expected a piece to move"),
+        };
+        let lunged_pawn = self.board.just_lunged_pawn();
+        let en_passant_pawn = self.board.en_passant_pawn(self.current_player);
+        self.position_history.push(self.hash);
+        self.history.push(HistoryEntry {
+            move_type: move_coords,
+            moved,
+            captured,
+            promotion,
+            lunged_pawn,
+            checkmate: self.checkmate,
+            halfmove_clock: self.halfmove_clock,
+            fullmove_number: self.fullmove_number,
+            hash: self.hash,
+            explosion: None,
+            checks: self.checks,
+        });
+
+        // XOR out the pieces which are about to move or be captured, and the
+        // old castling and en passant rights.
+        let castling_rights = self.board.castling_rights();
+        self.hash ^= zobrist::castling_key(castling_rights);
+        self.hash ^= zobrist::en_passant_key(en_passant_pawn);
+        match move_coords {
+            MoveTypeCoords::Normal { start, end }
+            | MoveTypeCoords::Lunge { start, end }
+            | MoveTypeCoords::Capture { start, end } => {
+                self.hash ^= zobrist::piece_key(moved, start) ^ zobrist::piece_key(moved, end);
+                if let Some(captured) = captured {
+                    self.hash ^= zobrist::piece_key(captured, end);
+                }
             }
-            Normal | Capture => {
-                self.board.clear_just_lunged();
-                self.board.move_piece(start, end)
+            MoveTypeCoords::EnPassant {
+                start,
+                end,
+                captured_pawn,
+            } => {
+                self.hash ^= zobrist::piece_key(moved, start) ^ zobrist::piece_key(moved, end);
+                if let Some(captured) = captured {
+                    self.hash ^= zobrist::piece_key(captured, captured_pawn);
+                }
             }
-            Lunge => {
-                // Clear the old lunge flag before the new one
-                self.board.clear_just_lunged();
-                self.board.lunge(start);
+            MoveTypeCoords::Castle {
+                king_start,
+                king_end,
+                rook_start,
+                rook_end,
+            } => {
+                let rook = self.board.get(rook_start).0.expect("Expected a rook");
+                self.hash ^=
+                    zobrist::piece_key(moved, king_start) ^ zobrist::piece_key(moved, king_end);
+                self.hash ^=
+                    zobrist::piece_key(rook, rook_start) ^ zobrist::piece_key(rook, rook_end);
             }
-            EnPassant(_) => {
-                self.board.enpassant(start, end);
-                // Don't clear the lunge flag until _after_ we check for enpassant
-                // (otherwise we will never be able to :P)
-                self.board.clear_just_lunged();
+            MoveTypeCoords::Drop { end } => {
+                self.hash ^= zobrist::piece_key(moved, end);
+            }
+        }
+
+        // Pawn moves and captures reset the halfmove clock.
+        let is_pawn_move = matches!(moved.piece, PieceType::Pawn { .. });
+        let is_capture = matches!(move_type, Capture | EnPassant(_));
+        if is_pawn_move || is_capture {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+
+        if let Drop = move_type {
+            self.board.clear_just_lunged();
+            self.board.set(end, Tile(Some(moved)));
+            self.remove_from_pocket(moved.color, moved.piece);
+        } else {
+            self.board.make_move_type(move_type, start, end);
+        }
+
+        // A captured piece goes into the capturer's pocket. Promoted pieces
+        // turn back into pawns.
+        if let Some(captured) = captured {
+            if !self.standard_rules && self.variant.uses_pockets() {
+                self.add_to_pocket(moved.color, captured.demoted());
             }
         }
 
+        if let Some(piece) = promotion {
+            self.board.promote_pawn(end, piece);
+            let promoted = self.board.get(end).0.expect("Expected a promoted piece");
+            self.hash ^= zobrist::piece_key(moved, end) ^ zobrist::piece_key(promoted, end);
+        }
+
+        // In Atomic chess, the capturing piece blows up along with everything
+        // around it other than pawns.
+        if captured.is_some() && !self.standard_rules && self.variant.captures_explode() {
+            let capturer = self.board.get(end).0.expect("Expected a capturing piece");
+            self.hash ^= zobrist::piece_key(capturer, end);
+            let explosion = self.board.explode(end);
+            for (coord, piece) in explosion.pieces() {
+                self.hash ^= zobrist::piece_key(piece, coord);
+            }
+            self.history.last_mut().unwrap().explosion = Some(explosion);
+        }
+
+        // XOR in the new castling and en passant rights. Castling rights can
+        // only be lost, so there's no need to look for them once they're gone.
+        let castling_rights = match castling_rights {
+            0 => 0,
+            _ => self.board.castling_rights(),
+        };
+        self.hash ^= zobrist::castling_key(castling_rights);
+        if let Lunge = move_type {
+            let opponent = moved.color.opposite();
+            self.hash ^= zobrist::en_passant_key(self.board.en_passant_pawn(opponent));
+        }
+
         #[cfg(feature = "perf")]
         drop(guard);
 
-        if self.need_promote().is_none() {
-            self.current_player = match self.current_player {
-                White => Black,
-                Black => White,
-            };
+        self.switch_player();
+        if !self.standard_rules
+            && self.variant.counts_checks()
+            && self.board.is_in_check(self.current_player)
+        {
+            let checks = &mut self.checks[moved.color as usize];
+            self.hash ^= zobrist::checks_key(moved.color, *checks);
+            *checks += 1;
+            self.hash ^= zobrist::checks_key(moved.color, *checks);
         }
+        debug_assert_eq!(self.hash, self.full_hash());
 
         #[cfg(feature = "perf")]
         flame::start("checkmate update");
 
-        // Update the checkmate status
-        self.checkmate = self.board.checkmate_state(self.current_player);
+        self.update_checkmate();
 
         #[cfg(feature = "perf")]
         flame::end("checkmate update");
     }
 
-    pub fn need_promote(&self) -> Option<BoardCoord> {
-        self.board.pawn_needs_promotion()
+    /// Update the checkmate status. In addition to the states given by the
+    /// variant, this ends the game in a draw on a fivefold repetition or
+    /// after 75 moves without a capture or pawn move. Checkmate and stalemate
+    /// take priority over these draws.
+    fn update_checkmate(&mut self) {
+        self.checkmate = if self.standard_rules {
+            self.board.checkmate_state(self.current_player)
+        } else {
+            self.variant.checkmate_state(self)
+        };
+        if let CheckmateState::Normal | CheckmateState::Check = self.checkmate {
+            // The halfmove clock counts halfmoves, so 75 moves is 150 halfmoves.
+            if self.repetition_count() >= 5 {
+                self.checkmate = CheckmateState::FivefoldRepetition;
+            } else if self.halfmove_clock >= 150 {
+                self.checkmate = CheckmateState::SeventyFiveMoveRule;
+            }
+        }
+    }
+
+    /// Make the other player the player-to-move, incrementing the fullmove
+    /// number after Black's move.
+    fn switch_player(&mut self) {
+        self.current_player = match self.current_player {
+            Color::White => Color::Black,
+            Color::Black => {
+                self.fullmove_number += 1;
+                Color::White
+            }
+        };
+        self.hash ^= zobrist::black_to_move_key();
+    }
+
+    /// The Zobrist hash of the current position. Two positions with the same
+    /// pieces on the same squares, the same player to move, and the same
+    /// castling and en passant rights have the same hash.
+    pub fn position_hash(&self) -> u64 {
+        self.hash
     }
 
-    /// Checks if the promotion is legal. This function returns Err if there is
-    /// no piece to promote to or if the promotion would be illegal.
-    pub fn check_promote(&self, coord: BoardCoord, piece: PieceType) -> Result<(), &'static str> {
-        // TODO: remove this if?
-        if self.need_promote().is_none() {
-            return Err("No pawn needs to be promoted at this time");
+    /// Return how many times the current position has occurred this game,
+    /// including the current occurrence.
+    pub fn repetition_count(&self) -> usize {
+        // Captures and pawn moves can't be undone, so positions from before the
+        // last one can't repeat.
+        let repeats = self
+            .position_history
+            .iter()
+            .rev()
+            .take(self.halfmove_clock as usize)
+            .filter(|&&other| other == self.hash)
+            .count();
+        repeats + 1
+    }
+
+    /// Returns the draw the player-to-move may claim, if any. This is either
+    /// `ThreefoldRepetition`, if the current position has occurred at least
+    /// three times, or `FiftyMoveRule`, if the last 50 moves by each player
+    /// have not had a capture or pawn move.
+    pub fn claimable_draw(&self) -> Option<CheckmateState> {
+        if self.game_over() {
+            return None;
         }
 
-        self.board.check_promote(coord, piece)
+        if self.repetition_count() >= 3 {
+            Some(CheckmateState::ThreefoldRepetition)
+        } else if self.halfmove_clock >= 100 {
+            Some(CheckmateState::FiftyMoveRule)
+        } else {
+            None
+        }
     }
 
-    /// Promotes the pawn. This function panics if the promotion is illegal. This
-    /// function also handles updating the checkmate state and current player
-    pub fn promote(&mut self, coord: BoardCoord, piece: PieceType) {
-        debug_assert!(self.check_promote(coord, piece).is_ok());
+    /// Claim a draw for the player-to-move, ending the game. Returns false
+    /// (and does nothing) if no draw can currently be claimed.
+    pub fn claim_draw(&mut self) -> bool {
+        match self.claimable_draw() {
+            Some(draw) => {
+                self.checkmate = draw;
+                true
+            }
+            None => false,
+        }
+    }
 
-        self.board.promote_pawn(coord, piece);
+    /// Take back the most recently made move, restoring the board exactly as it
+    /// was before the move (including any captured piece, castling rook,
+    /// `has_moved` and `just_lunged` flags, pockets, and exploded pieces). If the move was a
+    /// promotion, the promotion is undone as well. Returns the undone move, or None if there
+    /// are no moves to undo. Undone moves can be replayed with `redo`.
+    pub fn undo(&mut self) -> Option<HistoryEntry> {
+        let entry = self.history.pop()?;
+        self.position_history.pop();
+        self.ended = None;
+        let color = entry.moved.color;
+
+        match entry.move_type {
+            MoveTypeCoords::Normal { start, end }
+            | MoveTypeCoords::Lunge { start, end }
+            | MoveTypeCoords::Capture { start, end } => {
+                self.board.set(start, Tile(Some(entry.moved)));
+                self.board.set(end, Tile(entry.captured));
+            }
+            MoveTypeCoords::EnPassant {
+                start,
+                end,
+                captured_pawn,
+            } => {
+                self.board.set(start, Tile(Some(entry.moved)));
+                self.board.set(end, Tile::blank());
+                self.board.set(captured_pawn, Tile(entry.captured));
+            }
+            MoveTypeCoords::Castle {
+                king_start,
+                king_end,
+                rook_start,
+                rook_end,
+            } => {
+                // Castling is only legal if neither piece has moved, so the rook
+                // is always restored as an unmoved rook.
+                self.board.set(king_end, Tile::blank());
+                self.board.set(rook_end, Tile::blank());
+                self.board.set(king_start, Tile(Some(entry.moved)));
+                self.board
+                    .set(rook_start, Tile::new(color, PieceType::Rook));
+            }
+            MoveTypeCoords::Drop { end } => {
+                self.board.set(end, Tile::blank());
+                self.pockets[color as usize].add(entry.moved.piece);
+            }
+        }
 
-        use Color::*;
-        self.current_player = match self.current_player {
-            White => Black,
-            Black => White,
-        };
+        if let Some(captured) = entry.captured {
+            if self.variant.uses_pockets() {
+                self.pockets[color as usize].remove(captured.demoted());
+            }
+        }
 
-        // Update the checkmate status
-        self.checkmate = self.board.checkmate_state(self.current_player);
+        if let Some(explosion) = entry.explosion {
+            for (coord, piece) in explosion.pieces() {
+                self.board.set(coord, Tile(Some(piece)));
+            }
+        }
+
+        self.board.clear_just_lunged();
+        if let Some(coord) = entry.lunged_pawn {
+            if let Some(Piece {
+                piece: PieceType::Pawn { just_lunged },
+                ..
+            }) = &mut self.board.get_mut(coord).0
+            {
+                *just_lunged = true;
+            }
+        }
+
+        self.current_player = color;
+        self.checkmate = entry.checkmate;
+        self.halfmove_clock = entry.halfmove_clock;
+        self.fullmove_number = entry.fullmove_number;
+        self.checks = entry.checks;
+        self.hash = entry.hash;
+        debug_assert_eq!(self.hash, self.full_hash());
+
+        self.redo_stack.push(entry);
+        Some(entry)
+    }
+
+    /// Replay the most recently undone move. Returns the replayed move, or None
+    /// if there are no moves to redo.
+    pub fn redo(&mut self) -> Option<HistoryEntry> {
+        let entry = self.redo_stack.pop()?;
+        self.apply_turn(entry.as_move());
+        Some(entry)
+    }
+
+    /// The moves made so far this game, oldest first.
+    pub fn history(&self) -> &[HistoryEntry] {
+        &self.history
     }
 
     /// Return the list of valid moves for current player at the coordinate
     pub fn get_move_list(&self, coord: BoardCoord) -> Vec<BoardCoord> {
-        self.board.get_move_list(coord, self.current_player)
+        // The variant may allow moves that standard chess doesn't, or forbid
+        // some that it does, so the list comes from the legal moves. Each
+        // promotion is a separate move, but the square should only be listed
+        // once.
+        let mut list = vec![];
+        for mv in self.legal_moves() {
+            if mv.start == coord && !mv.is_drop() && !list.contains(&mv.end) {
+                list.push(mv.end);
+            }
+        }
+        list
     }
 
     pub fn game_over(&self) -> bool {
-        match self.board.checkmate_state(self.current_player) {
+        if self.ended.is_some() {
+            return true;
+        }
+        match self.checkmate {
             CheckmateState::Normal | CheckmateState::Check => false,
             CheckmateState::Checkmate
             | CheckmateState::Stalemate
-            | CheckmateState::InsuffientMaterial => true,
+            | CheckmateState::InsuffientMaterial
+            | CheckmateState::ThreefoldRepetition
+            | CheckmateState::FivefoldRepetition
+            | CheckmateState::FiftyMoveRule
+            | CheckmateState::SeventyFiveMoveRule
+            | CheckmateState::VariantEnd { .. } => true,
         }
     }
 
@@ -164,6 +731,59 @@ impl BoardState {
     }
 }
 
+/// A move recorded in the history of a `BoardState`. This contains everything
+/// needed to undo the move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistoryEntry {
+    /// The kind of move made and the squares it involved.
+    pub move_type: MoveTypeCoords,
+    /// The piece that moved (the king, when castling, or the dropped piece),
+    /// as it was before moving.
+    pub moved: Piece,
+    /// The piece that was captured, as it was before being captured.
+    pub captured: Option<Piece>,
+    /// The piece a pawn was promoted to, if this move was a promotion.
+    pub promotion: Option<PieceType>,
+    /// The pawn that had `just_lunged` set before this move, if any.
+    pub lunged_pawn: Option<BoardCoord>,
+    /// The checkmate state before the move.
+    pub checkmate: CheckmateState,
+    /// The halfmove clock before the move.
+    pub halfmove_clock: u32,
+    /// The fullmove number before the move.
+    pub fullmove_number: u32,
+    /// The Zobrist hash of the position before the move.
+    pub hash: u64,
+    /// The pieces blown up by this move, if it was a capture in a variant
+    /// where captures explode, such as Atomic chess.
+    pub explosion: Option<Explosion>,
+    /// The number of checks each player had given before the move.
+    pub checks: [u8; 2],
+}
+
+impl HistoryEntry {
+    /// Return the move that was made.
+    pub fn as_move(&self) -> Move {
+        let (start, end) = match self.move_type {
+            MoveTypeCoords::Castle {
+                king_start,
+                king_end,
+                rook_start,
+                ..
+            } => (king_start, castle_end(king_start, king_end, rook_start)),
+            MoveTypeCoords::Drop { end } => return Move::drop(self.moved.piece, end),
+            move_type => move_type.coords(),
+        };
+        Move {
+            start,
+            end,
+            promotion: self.promotion,
+            drop: None,
+            flags: MoveFlags::from_coords(&self.move_type),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CheckmateState {
     Normal,
@@ -171,23 +791,129 @@ pub enum CheckmateState {
     Checkmate,
     Stalemate,
     InsuffientMaterial,
+    /// A draw claimed by a player after the same position occurred three times.
+    ThreefoldRepetition,
+    /// An automatic draw after the same position occurred five times.
+    FivefoldRepetition,
+    /// A draw claimed by a player after 50 moves by each player without a
+    /// capture or pawn move.
+    FiftyMoveRule,
+    /// An automatic draw after 75 moves by each player without a capture or
+    /// pawn move.
+    SeventyFiveMoveRule,
+    /// The game ended by a rule of the variant being played. `winner` is None
+    /// if the game is drawn, and `reason` describes the rule, such as "King of
+    /// the Hill".
+    VariantEnd {
+        winner: Option<Color>,
+        reason: &'static str,
+    },
 }
 
-/// Wrapper struct around an 8x8 array of Tiles. This represents the state of
+impl CheckmateState {
+    /// Returns true if this is a draw that only happens when claimed by a
+    /// player, rather than automatically.
+    pub fn is_claimed_draw(&self) -> bool {
+        matches!(
+            self,
+            CheckmateState::ThreefoldRepetition | CheckmateState::FiftyMoveRule
+        )
+    }
+}
+
+/// Wrapper struct around an array of Tiles. This represents the state of
 /// pieces on the board. Note that Boards are arranged internally so that white
 /// is on the bottom and black is on the top. Hence, `board[0][0]` is the bottom
-/// left of the board, and is white's leftmost square, while `board[7][7]` is
-/// the top right of the board.
+/// left of the board, and is white's leftmost square. The array is indexed by
+/// rank and then by file, and is large enough for the largest board allowed.
+/// Only the squares within `dimensions` are ever used.
+/// The board also keeps bitboards of where each color and kind of piece is,
+/// which are used for fast move generation. See the `bitboard` module.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Board {
-    board: [[Tile; 8]; 8],
+    board: [[Tile; bitboard::FILES]; bitboard::RANKS],
+    /// The number of files and ranks of the board
+    dimensions: Dimensions,
+    /// Every square of the board. The attack tables don't know how large the
+    /// board is, so moves are masked with this to keep them on the board.
+    area: Bitboard,
+    /// The squares occupied by each color, indexed by `Color as usize`
+    colors: [Bitboard; 2],
+    /// The squares occupied by each kind of piece of either color, indexed by
+    /// `PAWNS`, `KNIGHTS`, and so on. Every fairy piece is in `FAIRIES`.
+    pieces: [Bitboard; 7],
+    /// The pieces which have not moved yet
+    unmoved: Bitboard,
+    /// The pawn which has just lunged, if there is one
+    just_lunged: Bitboard,
+}
+
+const PAWNS: usize = 0;
+const KNIGHTS: usize = 1;
+const BISHOPS: usize = 2;
+const ROOKS: usize = 3;
+const QUEENS: usize = 4;
+const KINGS: usize = 5;
+const FAIRIES: usize = 6;
+
+/// Return the index of `piece` in `Board::pieces`.
+fn kind_index(piece: PieceType) -> usize {
+    use PieceType::*;
+    match piece {
+        Pawn { .. } => PAWNS,
+        Knight => KNIGHTS,
+        Bishop => BISHOPS,
+        Rook => ROOKS,
+        Queen => QUEENS,
+        King => KINGS,
+        Fairy(_) => FAIRIES,
+    }
+}
+
+/// Which squares `player`'s pieces are restricted to because of checks and
+/// pins. See `Board::move_masks`.
+struct MoveMasks {
+    /// The location of `player`'s king, if they have one.
+    king: Option<usize>,
+    /// The enemy pieces giving check to the king
+    checkers: Bitboard,
+    /// The squares a piece other than the king may move to. If the king is in
+    /// check by one piece, this is the checking piece and the squares between
+    /// it and the king (so the check is captured or blocked). If the king is
+    /// in double check, only the king may move, so this is empty. Otherwise,
+    /// this is every square.
+    check_mask: Bitboard,
+    /// The pieces which are pinned to the king. A pinned piece may only move
+    /// along the line between the king and the pinning piece.
+    pinned: Bitboard,
+    /// The squares between the king and the pieces pinning `pinned`, and the
+    /// pinning pieces themselves. A pinned piece which hops, such as the
+    /// Grasshopper, could otherwise stay on the line by hopping past the
+    /// piece pinning it.
+    pin_lines: Bitboard,
+    /// Whether the other player has fairy pieces which aren't only blocked
+    /// along lines, such as the Grasshopper (see `Movement::blocked_on_lines`).
+    /// Their checks and pins aren't in `check_mask` and `pinned`, so each move
+    /// has to be tried to see if it leaves the king attacked.
+    hoppers: bool,
 }
 
 impl Board {
-    /// Create a chessboard with no pieces on it.
+    /// Create a standard 8x8 chessboard with no pieces on it.
     pub fn blank() -> Board {
+        Board::with_dimensions(Dimensions::STANDARD)
+    }
+
+    /// Create a chessboard of the given size with no pieces on it.
+    pub fn with_dimensions(dimensions: Dimensions) -> Board {
         Board {
-            board: [[Tile(None); 8]; 8],
+            board: [[Tile(None); bitboard::FILES]; bitboard::RANKS],
+            dimensions,
+            area: dimensions.area(),
+            colors: [bitboard::EMPTY; 2],
+            pieces: [bitboard::EMPTY; 7],
+            unmoved: bitboard::EMPTY,
+            just_lunged: bitboard::EMPTY,
         }
     }
 
@@ -207,10 +933,77 @@ impl Board {
         Board::from_string_vec(setup)
     }
 
-    /// Create a board from a string array. The array assumes that each string
-    /// can be split into exactly 8 two character substrings, each either being
-    /// "B" or "W" in the first character and a P, N, B, R, Q, or K in the
-    /// second character. Anything else is treated as a blank Tile.
+    /// Create the Chess960 starting board numbered `index`. Positions are
+    /// numbered from 0 to 959 using the standard numbering scheme, where 518
+    /// is the standard starting position. See
+    /// https://en.wikipedia.org/wiki/Fischer_random_chess_numbering_scheme
+    pub fn chess960(index: u32) -> Result<Board, &'static str> {
+        use PieceType::*;
+        if index >= 960 {
+            return Err("Chess960 positions are numbered from 0 to 959");
+        }
+
+        // The squares of the first rank which are still empty
+        fn empty_files(first_rank: &[Option<PieceType>; 8]) -> Vec<usize> {
+            (0..8).filter(|&file| first_rank[file].is_none()).collect()
+        }
+
+        let mut first_rank = [None; 8];
+        let mut index = index as usize;
+        // The bishops go on a light square and a dark square.
+        first_rank[2 * (index % 4) + 1] = Some(Bishop);
+        index /= 4;
+        first_rank[2 * (index % 4)] = Some(Bishop);
+        index /= 4;
+
+        let queen = empty_files(&first_rank)[index % 6];
+        first_rank[queen] = Some(Queen);
+        index /= 6;
+
+        // The ten ways to place the knights on the five remaining squares
+        const KNIGHTS: [(usize, usize); 10] = [
+            (0, 1),
+            (0, 2),
+            (0, 3),
+            (0, 4),
+            (1, 2),
+            (1, 3),
+            (1, 4),
+            (2, 3),
+            (2, 4),
+            (3, 4),
+        ];
+        let (first, second) = KNIGHTS[index];
+        let files = empty_files(&first_rank);
+        first_rank[files[first]] = Some(Knight);
+        first_rank[files[second]] = Some(Knight);
+
+        // The king always goes between the rooks.
+        let files = empty_files(&first_rank);
+        for (&file, &piece) in files.iter().zip([Rook, King, Rook].iter()) {
+            first_rank[file] = Some(piece);
+        }
+
+        let mut board = Board::blank();
+        for (file, piece) in first_rank.iter().enumerate() {
+            let piece = piece.expect("Expected every square of the first rank to be filled");
+            let pawn = Pawn { just_lunged: false };
+            let file = file as i8;
+            board.set(BoardCoord(file, 0), Tile::new(Color::White, piece));
+            board.set(BoardCoord(file, 1), Tile::new(Color::White, pawn));
+            board.set(BoardCoord(file, 6), Tile::new(Color::Black, pawn));
+            board.set(BoardCoord(file, 7), Tile::new(Color::Black, piece));
+        }
+        Ok(board)
+    }
+
+    /// Create a standard 8x8 board from a string array. The array assumes that
+    /// each string can be split into exactly 8 two character substrings, each
+    /// either being "B" or "W" in the first character and a P, N, B, R, Q, or
+    /// K in the second character. Anything else is treated as a blank Tile, and
+    /// anything which would be off the board is ignored.
+    /// Note that the board isn't checked to be a legal position. Use
+    /// `PositionBuilder` to set up a position which is checked.
     pub fn from_string_vec(str_board: Vec<&str>) -> Board {
         let mut board = Board::blank();
         for (i, row) in str_board.iter().enumerate() {
@@ -238,7 +1031,9 @@ impl Board {
                     "WK" => Tile::new(White, King),
                     _ => Tile::blank(),
                 };
-                board.set(coord, tile)
+                if board.contains(coord) {
+                    board.set(coord, tile)
+                }
             }
         }
         board
@@ -272,39 +1067,122 @@ impl Board {
         self.set(start, Tile(None));
     }
 
+    /// Make the move `mv`, including any promotion, without checking that it
+    /// is legal. Unlike `BoardState::take_turn`, nothing but the board is
+    /// updated, so this is useful for looking ahead at the position a move
+    /// leads to. Drops aren't supported, since the board has no pockets.
+    pub(crate) fn make_move(&mut self, mv: Move) {
+        self.make_move_type(move_type(self, mv.start, mv.end), mv.start, mv.end);
+        if let Some(piece) = mv.promotion {
+            self.promote_pawn(mv.end, piece);
+        }
+    }
+
+    /// Move the pieces for a move of `move_type` from `start` to `end`, and
+    /// clear the `just_lunged` flag of the pawn which lunged last turn.
+    fn make_move_type(&mut self, move_type: MoveType, start: BoardCoord, end: BoardCoord) {
+        use MoveType::*;
+        match move_type {
+            Castle(color, side) => {
+                // Clear the just lunged flags _after_ checking the move is valid
+                // That way, invalid moves don't try to clear the flag.
+                self.clear_just_lunged();
+                self.castle(color, side);
+            }
+            Normal | Capture => {
+                self.clear_just_lunged();
+                self.move_piece(start, end)
+            }
+            Lunge => {
+                // Clear the old lunge flag before the new one
+                self.clear_just_lunged();
+                self.lunge(start);
+            }
+            EnPassant(_) => {
+                self.enpassant(start, end);
+                // Don't clear the lunge flag until _after_ we check for enpassant
+                // (otherwise we will never be able to :P)
+                self.clear_just_lunged();
+            }
+            Drop => panic!("Expected a move on the board, not a drop"),
+        }
+    }
+
+    /// Blow up the piece at `center` and every piece next to it other than a
+    /// pawn, as happens after a capture in Atomic chess. Returns the pieces
+    /// next to `center` which were blown up.
+    pub(crate) fn explode(&mut self, center: BoardCoord) -> Explosion {
+        let mut explosion = Explosion {
+            center,
+            pieces: [None; 8],
+        };
+        let neighbours = bitboard::king_attacks(bitboard::square(center))
+            & self.occupied()
+            & !self.pieces[PAWNS];
+        for (i, square) in bitboard::squares(neighbours).enumerate() {
+            let coord = bitboard::coord(square);
+            explosion.pieces[i] = self.get(coord).0.map(|piece| (coord, piece));
+            self.set(coord, Tile::blank());
+        }
+        self.set(center, Tile::blank());
+        explosion
+    }
+
     /// Check if the piece located at `start` can be legally be moved to
     /// `end`. This function assumes the player-to-move is whatever `player` is.
-    /// This function returns `Ok(())` if the move is valid and `Err(&str)` if
-    /// the move is invalid.
+    /// This function returns `Ok(())` if the move is valid and the reason it
+    /// isn't if the move is invalid.
     pub fn check_move(
         &self,
         player: Color,
         start: BoardCoord,
         end: BoardCoord,
-    ) -> Result<(), &'static str> {
-        let start_piece = self.get(start);
-
-        if start_piece.0.is_none() {
-            return Err("Can't move an empty tile");
+    ) -> Result<(), MoveError> {
+        let start_piece = match self.get(start).0 {
+            Some(piece) => piece,
+            None => return Err(MoveError::NoPieceAtSource { square: start }),
+        };
+        if start_piece.color != player {
+            return Err(MoveError::NotYourPiece { square: start });
         }
 
-        let start_piece = start_piece.0.unwrap();
-        if start_piece.color != player {
-            return Err("Can't move a piece that isn't yours");
+        let (start_square, end_bit) = (bitboard::square(start), bitboard::bit(end));
+        if !self.contains(end)
+            || self.pseudo_legal_targets(start_square) & end_bit == bitboard::EMPTY
+        {
+            return Err(MoveError::InvalidDestination { start, end });
         }
 
-        // TODO: use threatens/write a "can_move_to" function for this, which is
-        // way better than doing a ton of allocations & redundant checks.
-        let mut valid_end_spots = MoveList::reserved();
-        get_move_list_full(self, player, start, &mut valid_end_spots);
+        let masks = self.move_masks(player);
+        if self.legal_targets(player, start_square, &masks) & end_bit != bitboard::EMPTY {
+            return Ok(());
+        }
 
-        if valid_end_spots.0.contains(&end) {
-            Ok(())
+        // The move is only illegal because of a check, so find the piece
+        // which would give it.
+        let mut after = self.clone();
+        after.move_piece(start, end);
+        let king = after
+            .get_king(player)
+            .expect("Expected a king to be in check");
+        let by = after
+            .attacker_of(king, player.opposite())
+            .expect("Expected the king to be attacked");
+        if masks.pinned & bitboard::bit(start) != bitboard::EMPTY {
+            Err(MoveError::Pinned { square: start, by })
         } else {
-            Err("Can't move a piece there")
+            Err(MoveError::LeavesKingInCheck { king, by })
         }
     }
 
+    /// Return one of `color`'s pieces which attacks `square`, if there is one.
+    pub(crate) fn attacker_of(&self, square: BoardCoord, color: Color) -> Option<BoardCoord> {
+        let attackers = self.attackers_to(bitboard::square(square), self.occupied());
+        bitboard::squares(attackers & self.colors[color as usize])
+            .next()
+            .map(bitboard::coord)
+    }
+
     /// Return the list of valid places the piece at `coord` can move for the
     /// given `player`. Note that the returned vector is empty if any of the
     /// following are true.
@@ -315,8 +1193,9 @@ impl Board {
     /// Also note that this function DOES check if the move would place the
     /// king into check.
     /// This function also DOES check if the King can castle.
+    #[cfg(test)]
     fn get_move_list(&self, coord: BoardCoord, player: Color) -> Vec<BoardCoord> {
-        if !on_board(coord) {
+        if !self.contains(coord) {
             return vec![];
         }
 
@@ -336,84 +1215,270 @@ impl Board {
             return vec![];
         }
 
-        let mut list = MoveList::reserved();
-        get_move_list_full(&self, player, coord, &mut list);
+        let masks = self.move_masks(player);
+        let targets = self.legal_targets(player, bitboard::square(coord), &masks);
+        let mut list: Vec<BoardCoord> = bitboard::squares(targets).map(bitboard::coord).collect();
         match piece.piece {
             PieceType::King => {
-                list.0.append(&mut self.castle_locations(player));
+                list.append(&mut self.castle_locations(player));
             }
-            PieceType::Pawn { .. } => list.0.append(&mut self.enpassant_locations(player, coord)),
+            PieceType::Pawn { .. } => list.append(&mut self.enpassant_locations(player, coord)),
             _ => {}
         }
-
-        list.0
+        list
     }
 
-    /// Return the list of all valid moves that can be made by a player
+    /// Return the list of all valid moves that can be made by a player. A pawn
+    /// reaching the last rank has one move for each piece it can promote to.
     #[cfg_attr(feature = "perf", flame)]
-    pub fn get_all_moves(&self, player: Color) -> Vec<(BoardCoord, BoardCoord)> {
-        // TODO: this is probably hilariously inefficent
-        let mut moves = vec![];
+    pub fn get_all_moves(&self, player: Color) -> Vec<Move> {
+        let mut moves = Vec::with_capacity(64);
+        if self.pawn_needs_promotion().is_some() {
+            return moves;
+        }
 
-        for i in ROWS {
-            for j in COLS {
-                let start = BoardCoord(i, j);
-                let this_move_list = self.get_move_list(start, player);
-                let mut move_pairs = this_move_list.into_iter().map(|end| (start, end)).collect();
-                moves.append(&mut move_pairs);
+        let masks = self.move_masks(player);
+        self.push_moves(player, &masks, &mut moves);
+
+        // En passant isn't found by `legal_targets`
+        let pawns = self.pieces[PAWNS] & self.colors[player as usize];
+        if let Some(lunged_pawn) = self.just_lunged_pawn() {
+            for start in bitboard::squares(pawns & bitboard::rank(lunged_pawn.1)) {
+                let start = bitboard::coord(start);
+                for end in self.enpassant_locations(player, start) {
+                    moves.push(Move::new(self, start, end, None));
+                }
             }
         }
         moves
     }
 
+    /// Return every move `player` could make if their king could be left in
+    /// check, as in variants where the king isn't protected by the usual
+    /// rules, such as Atomic chess. Castling still follows the usual rules.
+    pub fn pseudo_legal_moves(&self, player: Color) -> Vec<Move> {
+        let mut moves = Vec::with_capacity(64);
+        if self.pawn_needs_promotion().is_some() {
+            return moves;
+        }
+
+        // Without a king, nothing is checked or pinned.
+        let masks = MoveMasks {
+            king: None,
+            checkers: bitboard::EMPTY,
+            check_mask: !bitboard::EMPTY,
+            pinned: bitboard::EMPTY,
+            pin_lines: bitboard::EMPTY,
+            hoppers: false,
+        };
+        self.push_moves(player, &masks, &mut moves);
+
+        // Any pawn beside the pawn which just lunged may capture it en passant.
+        if let Some(lunged_pawn) = self.just_lunged_pawn() {
+            let end = BoardCoord(lunged_pawn.0, lunged_pawn.1 + player.direction());
+            for &file in [lunged_pawn.0 - 1, lunged_pawn.0 + 1].iter() {
+                let start = BoardCoord(file, lunged_pawn.1);
+                if self.contains(start)
+                    && self
+                        .get(start)
+                        .is(player, PieceType::Pawn { just_lunged: false })
+                {
+                    moves.push(Move::new(self, start, end, None));
+                }
+            }
+        }
+        moves
+    }
+
+    /// Push the moves of `player`'s pieces given the checks and pins in
+    /// `masks`, including castling but not en passant, onto `moves`.
+    fn push_moves(&self, player: Color, masks: &MoveMasks, moves: &mut Vec<Move>) {
+        let enemies = self.colors[player.opposite() as usize];
+        let pawns = self.pieces[PAWNS] & self.colors[player as usize];
+        let last_ranks = bitboard::rank(0) | bitboard::rank(self.dimensions.ranks - 1);
+        for start in bitboard::squares(self.colors[player as usize]) {
+            let targets = self.legal_targets(player, start, masks);
+            let is_pawn = pawns & 1 << start != bitboard::EMPTY;
+            for end in bitboard::squares(targets) {
+                let mut mv = Move {
+                    start: bitboard::coord(start),
+                    end: bitboard::coord(end),
+                    promotion: None,
+                    drop: None,
+                    flags: MoveFlags::NONE,
+                };
+                if enemies & 1 << end != bitboard::EMPTY {
+                    mv.flags = MoveFlags::CAPTURE;
+                }
+                if is_pawn && (start as i32 - end as i32).abs() == 2 * bitboard::FILES as i32 {
+                    mv.flags = MoveFlags::DOUBLE_PUSH;
+                }
+
+                if is_pawn && last_ranks & 1 << end != bitboard::EMPTY {
+                    for &piece in PROMOTION_PIECES.iter() {
+                        moves.push(Move {
+                            promotion: Some(piece),
+                            ..mv
+                        });
+                    }
+                } else {
+                    moves.push(mv);
+                }
+            }
+        }
+
+        // Castling isn't found by `legal_targets`
+        if let Some(king) = self.get_king(player) {
+            for end in self.castle_locations(player) {
+                moves.push(Move {
+                    start: king,
+                    end,
+                    promotion: None,
+                    drop: None,
+                    flags: MoveFlags::CASTLE,
+                });
+            }
+        }
+    }
+
+    /// Returns true if moving the piece at `start` to `end` is a pawn reaching
+    /// the last rank, which means the pawn must be promoted.
+    pub fn is_promotion(&self, start: BoardCoord, end: BoardCoord) -> bool {
+        match self.get(start).0 {
+            Some(Piece {
+                piece: PieceType::Pawn { .. },
+                color,
+                ..
+            }) => end.1 == self.dimensions.back_rank(color.opposite()),
+            _ => false,
+        }
+    }
+
     /// Castle the King of `color`. This function does not check if
     /// doing so would actually be legal to do so in a real game, so you should
     /// check the castle first with `can_castle`
     fn castle(&mut self, color: Color, side: BoardSide) {
-        use BoardSide::*;
-        use Color::*;
-        let first_rank = match color {
-            White => 0,
-            Black => 7,
-        };
+        let king_start = self.castling_king(color).expect("Expected an unmoved king");
+        let rook_start = self
+            .castling_rook(color, side)
+            .expect("Expected an unmoved rook");
+        let (king_end, rook_end) = self.castle_destinations(color, side);
+
+        // In Chess960, the king may end up where the rook started (or the
+        // other way around), so both are picked up before either is put down.
+        let mut king = *self.get(king_start);
+        let mut rook = *self.get(rook_start);
+        king.set_moved(true);
+        rook.set_moved(true);
+        self.set(king_start, Tile::blank());
+        self.set(rook_start, Tile::blank());
+        self.set(king_end, king);
+        self.set(rook_end, rook);
+    }
 
-        let king_start = BoardCoord(4, first_rank);
-        let king_end = match side {
-            Queenside => BoardCoord(2, first_rank),
-            Kingside => BoardCoord(6, first_rank),
-        };
+    /// Return a list of locations that the king may castle to. See
+    /// `castle_move` for which location is used for each castle.
+    pub fn castle_locations(&self, color: Color) -> Vec<BoardCoord> {
+        self.castle_locations_checking(color, true)
+    }
 
-        let rook_start = match side {
-            Queenside => BoardCoord(0, first_rank),
-            Kingside => BoardCoord(7, first_rank),
-        };
+    /// Like `castle_locations`, but the king may castle out of, through, or
+    /// into check. This is for variants without check, such as Fog of War.
+    pub fn castle_locations_ignoring_attacks(&self, color: Color) -> Vec<BoardCoord> {
+        self.castle_locations_checking(color, false)
+    }
 
-        let rook_end = match side {
-            Queenside => BoardCoord(3, first_rank),
-            Kingside => BoardCoord(5, first_rank),
+    fn castle_locations_checking(&self, color: Color, check_attacks: bool) -> Vec<BoardCoord> {
+        let mut castle_locs = vec![];
+        let king = match self.castling_king(color) {
+            Some(king) => king,
+            None => return castle_locs,
         };
+        for &side in [BoardSide::Queenside, BoardSide::Kingside].iter() {
+            if self.check_castle(color, side, check_attacks).is_ok() {
+                let rook = self.castling_rook(color, side).expect("Expected a rook");
+                let (king_end, _) = self.castle_destinations(color, side);
+                castle_locs.push(castle_end(king, king_end, rook));
+            }
+        }
+        castle_locs
+    }
 
-        self.move_piece(king_start, king_end);
-        self.move_piece(rook_start, rook_end);
+    /// Return the move which castles the king of `color` on `side`, or None if
+    /// `color` has no right to castle there. The move is written as the king
+    /// moving to its destination (the c or g file) if that is at least two
+    /// squares away, like in standard chess. Otherwise (which only happens in
+    /// Chess960) it would look like a normal king move, so it is written as
+    /// the king taking its own rook. Note that this does not check that
+    /// castling is legal right now.
+    pub fn castle_move(&self, color: Color, side: BoardSide) -> Option<Move> {
+        let king = self.castling_king(color)?;
+        let rook = self.castling_rook(color, side)?;
+        let (king_end, _) = self.castle_destinations(color, side);
+        Some(Move::new(
+            self,
+            king,
+            castle_end(king, king_end, rook),
+            None,
+        ))
     }
 
-    /// Return a list of locations that the king may castle to.
-    pub fn castle_locations(&self, color: Color) -> Vec<BoardCoord> {
-        let mut castle_locs = vec![];
-        let first_rank = match color {
-            Color::White => 0,
-            Color::Black => 7,
+    /// Returns true if the king and a rook on `side` have not moved, meaning
+    /// `color` still has the right to castle on that side (though they might
+    /// not be able to castle right now).
+    pub fn has_castling_right(&self, color: Color, side: BoardSide) -> bool {
+        self.castling_rook(color, side).is_some()
+    }
+
+    /// Return the king of `color` if it is unmoved and on its first rank.
+    fn castling_king(&self, color: Color) -> Option<BoardCoord> {
+        let kings = self.unmoved & self.pieces[KINGS] & self.colors[color as usize];
+        bitboard::squares(kings & self.first_rank(color))
+            .next()
+            .map(bitboard::coord)
+    }
+
+    /// Return the rook that `color` may castle with on `side`. This is the
+    /// outermost unmoved rook on `side` of the king, on the king's rank. The
+    /// king must also be unmoved. In standard chess, this can only be the rook
+    /// in the corner, but in Chess960 the rooks may start on any file.
+    pub fn castling_rook(&self, color: Color, side: BoardSide) -> Option<BoardCoord> {
+        let (queenside, kingside) = self.castling_rooks(color)?;
+        let rook = match side {
+            BoardSide::Queenside => bitboard::squares(queenside).next(),
+            BoardSide::Kingside => bitboard::squares(kingside).last(),
         };
+        rook.map(bitboard::coord)
+    }
 
-        if self.can_castle(color, BoardSide::Queenside).is_ok() {
-            castle_locs.push(BoardCoord(2, first_rank));
-        }
+    /// Return the unmoved rooks of `color` on the queenside and kingside of
+    /// their unmoved king, or None if the king has moved.
+    fn castling_rooks(&self, color: Color) -> Option<(Bitboard, Bitboard)> {
+        let king = bitboard::square(self.castling_king(color)?);
+        let rooks = self.unmoved
+            & self.pieces[ROOKS]
+            & self.colors[color as usize]
+            & self.first_rank(color);
+        let below_king = (1 << king) - 1;
+        Some((rooks & below_king, rooks & !below_king & !(1 << king)))
+    }
 
-        if self.can_castle(color, BoardSide::Kingside).is_ok() {
-            castle_locs.push(BoardCoord(6, first_rank));
+    /// Returns the castling rights of both players as a bitmask. Bit `i` is set
+    /// if the `i`th of White kingside, White queenside, Black kingside, and
+    /// Black queenside has the right to castle.
+    pub(crate) fn castling_rights(&self) -> u8 {
+        let mut rights = 0;
+        for (i, &color) in [Color::White, Color::Black].iter().enumerate() {
+            if let Some((queenside, kingside)) = self.castling_rooks(color) {
+                if kingside != bitboard::EMPTY {
+                    rights |= 1 << (2 * i);
+                }
+                if queenside != bitboard::EMPTY {
+                    rights |= 1 << (2 * i + 1);
+                }
+            }
         }
-
-        castle_locs
+        rights
     }
 
     /// Returns true if the king can castle to the rook indicated
@@ -429,78 +1494,60 @@ impl Board {
     ///   R . . . K . . R
     ///   0 1 2 3 4 5 6 7
     /// queenside kingside
-    fn can_castle(&self, color: Color, side: BoardSide) -> Result<(), &'static str> {
-        let first_rank = match color {
-            Color::White => 0,
-            Color::Black => 7,
-        };
-
-        // if the king hasn't moved, we expect it to be at (4, 0) or (4, 7)
-        let king_coord = BoardCoord(4, first_rank);
+    /// In Chess960, the king and rook may start anywhere on the first rank,
+    /// but they always end up in the same places as in standard chess. Every
+    /// square either of them moves through must be empty (other than the king
+    /// and rook themselves), and every square the king moves through must not
+    /// be attacked.
+    fn can_castle(&self, color: Color, side: BoardSide) -> Result<(), MoveError> {
+        self.check_castle(color, side, true)
+    }
 
-        // King is actually a king and has not moved
-        let king = self.get(king_coord);
-        match king.0 {
-            Some(Piece {
-                piece: PieceType::King,
-                color: c,
-                has_moved: false,
-            }) if c == color => (),
-            _ => {
-                return Err("Can't castle, king is not an unmoved king");
-            }
-        }
+    /// Like `can_castle`, but the squares the king moves through are only
+    /// checked for attacks if `check_attacks` is true.
+    fn check_castle(
+        &self,
+        color: Color,
+        side: BoardSide,
+        check_attacks: bool,
+    ) -> Result<(), MoveError> {
+        let (king, rook) = match (self.castling_king(color), self.castling_rook(color, side)) {
+            (Some(king), Some(rook)) => (king, rook),
+            _ => return Err(MoveError::NoCastlingRight { side }),
+        };
 
-        let king_is_safe = self.is_square_safe(color, &king_coord);
+        let king_is_safe = !check_attacks || self.is_square_safe(color, &king);
         if !king_is_safe {
-            return Err("Can't castle, king is in check");
+            return Err(MoveError::CastleOutOfCheck { king });
         }
 
-        let side = match side {
-            BoardSide::Queenside => 0,
-            BoardSide::Kingside => 7,
-        };
-        let rook_coord = BoardCoord(side, first_rank);
-
-        // rook_coord is actually a rook that has not moved
-        let rook = self.get(rook_coord);
-        match rook.0 {
-            Some(Piece {
-                piece: PieceType::Rook,
-                color: c,
-                has_moved: false,
-            }) if c == color => (),
-            _ => {
-                return Err("Can't castle, rook is not an unmoved rook");
-            }
-        }
+        let (king_end, rook_end) = self.castle_destinations(color, side);
+        let (king, rook) = (bitboard::square(king), bitboard::square(rook));
+        let (king_end, rook_end) = (bitboard::square(king_end), bitboard::square(rook_end));
 
-        let king_passes_through = match rook_coord.0 {
-            // Queenside castle
-            0 => vec![BoardCoord(2, first_rank), BoardCoord(3, first_rank)],
-            // Kingside castle
-            7 => vec![BoardCoord(5, first_rank), BoardCoord(6, first_rank)],
-            _ => unreachable!(),
-        };
+        // The king and rook don't block each other, since they may pass
+        // through each other's squares.
+        let occupied = self.occupied() & !(1 << king) & !(1 << rook);
+        let king_passes_through = bitboard::between(king, king_end) | 1 << king_end;
+        let rook_passes_through = bitboard::between(rook, rook_end) | 1 << rook_end;
 
         // All interveening tiles that the king passes through are empty and not
         // under attack.
-        for square in king_passes_through {
-            let tile_safe = self.is_square_safe(color, &square);
-            let tile_empty = self.get(square).0.is_none();
-            if !tile_safe || !tile_empty {
-                return Err("Can't castle, at least one square not empty or safe");
-            }
+        let blocked = (king_passes_through | rook_passes_through) & occupied;
+        if let Some(square) = bitboard::squares(blocked).next() {
+            return Err(MoveError::CastleBlocked {
+                square: bitboard::coord(square),
+            });
         }
-
-        // Additionally, if we are castling queenside, we need the tile just
-        // to the left of the rook to be empty.
-        let is_queenside = rook_coord == BoardCoord(0, first_rank);
-        let queenside_space_empty = self.get(BoardCoord(1, first_rank)).0.is_none();
-        if is_queenside && !queenside_space_empty {
-            return Err("Can't castle, queenside rook space not empty");
+        let enemies = self.colors[color.opposite() as usize];
+        let attacked = bitboard::squares(king_passes_through)
+            .find(|&square| self.attackers_to(square, occupied) & enemies != bitboard::EMPTY);
+        match attacked {
+            Some(square) if check_attacks => Err(MoveError::CastleThroughCheck {
+                square: bitboard::coord(square),
+            }),
+            _ => Ok(()),
         }
-        Ok(())
     }
 
     /// Returns Ok if the move is a valid en passant. A player may en passant
@@ -515,17 +1562,23 @@ impl Board {
         player: Color,
         capturing_pawn: BoardCoord,
         direction: BoardSide,
-    ) -> Result<(), &'static str> {
+    ) -> Result<(), MoveError> {
         use BoardSide::*;
         // We expect that start and end are diagonal from each other
         // and that the captured pawn is "one rank behind" the the end location
         // where "behind" is relative to the player capturing.
 
         let captured_pawn_coord = match direction {
-            Queenside => (capturing_pawn.0 - 1, capturing_pawn.1),
-            Kingside => (capturing_pawn.0 + 1, capturing_pawn.1),
+            Queenside => BoardCoord(capturing_pawn.0 - 1, capturing_pawn.1),
+            Kingside => BoardCoord(capturing_pawn.0 + 1, capturing_pawn.1),
+        };
+        let not_en_passant = MoveError::InvalidEnPassant {
+            square: captured_pawn_coord,
         };
-        let captured_pawn_coord = BoardCoord::new(captured_pawn_coord)?;
+        if !self.contains(captured_pawn_coord) {
+            return Err(not_en_passant);
+        }
+        let start = capturing_pawn;
 
         let capturing_pawn = self.get(capturing_pawn);
         let captured_pawn = self.get(captured_pawn_coord);
@@ -534,19 +1587,38 @@ impl Board {
             Some(Piece {
                 color: c,
                 piece: PieceType::Pawn { .. },
-                has_moved: _,
+                ..
             }) if c == player => (),
-            _ => return Err("Capturing piece must be a pawn of the player's color"),
+            Some(piece) if piece.color != player => {
+                return Err(MoveError::NotYourPiece { square: start })
+            }
+            Some(_) => return Err(not_en_passant),
+            None => return Err(MoveError::NoPieceAtSource { square: start }),
         }
 
         match captured_pawn.0 {
             Some(Piece {
                 color: c,
                 piece: PieceType::Pawn { just_lunged: true },
-                has_moved: _,
+                ..
             }) if c != player => (),
-            _ => {
-                return Err("Captured piece must be a pawn that just lunged of the opposite color");
+            _ => return Err(not_en_passant),
+        }
+
+        // Both pawns leave their ranks at once, which may expose the king to an
+        // attack along the rank.
+        if let Some(king) = self.get_king(player) {
+            let end = BoardCoord(captured_pawn_coord.0, start.1 + player.direction());
+            let occupied =
+                self.occupied() ^ bitboard::bit(start) ^ bitboard::bit(captured_pawn_coord)
+                    | bitboard::bit(end);
+            let attackers = self.attackers_to(bitboard::square(king), occupied);
+            let enemies = self.colors[player.opposite() as usize];
+            if let Some(by) = bitboard::squares(attackers & enemies).next() {
+                return Err(MoveError::LeavesKingInCheck {
+                    king,
+                    by: bitboard::coord(by),
+                });
             }
         }
 
@@ -591,368 +1663,1068 @@ impl Board {
         locations
     }
 
+    /// Return the location of the pawn that lunged on the previous turn, if
+    /// there is one.
+    pub fn just_lunged_pawn(&self) -> Option<BoardCoord> {
+        bitboard::squares(self.just_lunged)
+            .next()
+            .map(bitboard::coord)
+    }
+
+    /// Return the location of the pawn that lunged on the previous turn, if
+    /// `player` can capture it en passant. Otherwise, the position is the same
+    /// as if the pawn had moved one space at a time, so the Zobrist hash only
+    /// includes the lunged pawn when this is Some.
+    pub fn en_passant_pawn(&self, player: Color) -> Option<BoardCoord> {
+        let lunged_pawn = self.just_lunged_pawn()?;
+        let pawns = self.pieces[PAWNS] & self.colors[player as usize];
+        bitboard::squares(pawns & bitboard::rank(lunged_pawn.1))
+            .any(|start| {
+                !self
+                    .enpassant_locations(player, bitboard::coord(start))
+                    .is_empty()
+            })
+            .then_some(lunged_pawn)
+    }
+
     fn clear_just_lunged(&mut self) {
-        for i in ROWS {
-            for j in COLS {
-                if let Some(Piece {
-                    piece: PieceType::Pawn { just_lunged },
-                    ..
-                }) = &mut self.get_mut(BoardCoord(j, i)).0
-                {
-                    *just_lunged = false;
-                }
+        if let Some(coord) = self.just_lunged_pawn() {
+            if let Some(Piece {
+                piece: PieceType::Pawn { just_lunged },
+                ..
+            }) = &mut self.get_mut(coord).0
+            {
+                *just_lunged = false;
             }
         }
     }
 
-    #[cfg_attr(feature = "perf", flame)]
     /// Returns true if no piece of the opposite color threatens the square.
     fn is_square_safe(&self, color: Color, target: &BoardCoord) -> bool {
-        // TODO: this is hilariously inefficient
-        // Instead of checking for if a piece threatens the square, instead
-        // check that the square has no pieces that could threaten it
-        // AKA: fan out in a queen shape (+ and x) and check for {rook, bishop, queen}
-        // then also check surroudning squares for knights, kings, pawns
-
-        fn first_nonempty(board: &Board, iter: impl Iterator<Item = BoardCoord>) -> Option<Piece> {
-            for coord in iter {
-                if !on_board(coord) {
-                    break;
-                }
+        let attackers = self.attackers_to(bitboard::square(*target), self.occupied());
+        attackers & self.colors[color.opposite() as usize] == bitboard::EMPTY
+    }
+
+    /// Returns if the player is currently in checkmate, under the rules of
+    /// standard chess
+    #[cfg_attr(feature = "perf", flame)]
+    pub fn checkmate_state(&self, player: Color) -> CheckmateState {
+        use CheckmateState::*;
+        let masks = self.move_masks(player);
+        match (
+            self.any_legal_moves(player, &masks),
+            masks.checkers != bitboard::EMPTY,
+            self.insufficient_material(),
+        ) {
+            (false, false, false) => Stalemate,
+            (false, true, false) => Checkmate,
+            (true, false, false) => Normal,
+            (true, true, false) => Check,
+            (_, _, true) => InsuffientMaterial,
+        }
+    }
+
+    /// Returns true if neither player could possibly checkmate the other, no
+    /// matter how the game goes on (a "dead position"). This is the case if
+    /// neither player has enough material to mate (see `insufficient_to_win`),
+    /// or if the pawns are locked so that nothing can ever be captured.
+    /// See https://en.wikipedia.org/wiki/Draw_(chess)
+    fn insufficient_material(&self) -> bool {
+        (self.insufficient_material_to_win(Color::White)
+            && self.insufficient_material_to_win(Color::Black))
+            || self.pawns_locked()
+    }
+
+    /// Return true if `color` could never checkmate their opponent, even if
+    /// the opponent helps. This is the default `Variant::insufficient_to_win`,
+    /// used to decide if running out of time loses the game, and if it's true
+    /// for both players the game is drawn.
+    pub fn insufficient_to_win(&self, color: Color) -> bool {
+        self.insufficient_material_to_win(color) || self.pawns_locked()
+    }
+
+    /// Return true if `color`'s pieces could never checkmate, no matter where
+    /// they are. This is the case if `color` has:
+    ///     only a king
+    ///     a king and a knight, against a lone king
+    ///     a king and any number of bishops on the same color of square,
+    ///     against a king and possibly bishops on that same color
+    /// Anything else can mate, if only because the opponent's pieces block
+    /// their own king in. For example, a king and two knights can mate a lone
+    /// king, and a king and bishop can mate a king and knight.
+    fn insufficient_material_to_win(&self, color: Color) -> bool {
+        let ours = self.colors[color as usize] & !self.pieces[KINGS];
+        let theirs = self.colors[color.opposite() as usize] & !self.pieces[KINGS];
+        let bishops = self.pieces[BISHOPS];
+        if ours == bitboard::EMPTY {
+            true
+        } else if ours == ours & self.pieces[KNIGHTS] {
+            ours.count_ones() == 1 && theirs == bitboard::EMPTY
+        } else if ours == ours & bishops && theirs == theirs & bishops {
+            // Every bishop on the board, of either color, has to be on the
+            // same color of square.
+            bishops & bitboard::DARK_SQUARES == bitboard::EMPTY
+                || bishops & !bitboard::DARK_SQUARES == bitboard::EMPTY
+        } else {
+            false
+        }
+    }
+
+    /// Return true if the board has only kings and pawns, and the pawns are
+    /// locked together so that no pawn can ever move or be captured. This is
+    /// conservative, so it may return false for some positions where nothing
+    /// can happen. The pawns are locked if:
+    ///     every pawn is blocked by the pawn in front of it
+    ///     no pawn can capture another pawn
+    ///     neither king is in check
+    ///     neither king can reach a square next to an enemy pawn, without
+    ///     passing through squares the enemy pawns attack
+    /// Since the pawns can never move, the kings are the only pieces that can
+    /// ever move, and they can't ever give check.
+    fn pawns_locked(&self) -> bool {
+        let pawns = self.pieces[PAWNS];
+        if pawns == bitboard::EMPTY
+            || self.occupied() != pawns | self.pieces[KINGS]
+            || self.just_lunged != bitboard::EMPTY
+        {
+            return false;
+        }
+
+        let mut attacks = [bitboard::EMPTY; 2];
+        for square in bitboard::squares(pawns) {
+            let color = self.get(bitboard::coord(square)).0.unwrap().color;
+            let ahead = match color {
+                Color::White => 1 << square << bitboard::FILES,
+                Color::Black => 1 << square >> bitboard::FILES,
+            };
+            if ahead & pawns == bitboard::EMPTY {
+                return false;
+            }
+            attacks[color as usize] |= bitboard::pawn_attacks(color, square);
+        }
 
-                if let Some(piece) = board.get(coord).0 {
-                    return Some(piece);
+        for &color in &[Color::White, Color::Black] {
+            let enemy_pawns = pawns & self.colors[color.opposite() as usize];
+            let enemy_attacks = attacks[color.opposite() as usize];
+            // A pawn could capture, or a king is in check.
+            if enemy_attacks & self.colors[color as usize] != bitboard::EMPTY {
+                return false;
+            }
+
+            // Find every square the king could ever walk to. The other king
+            // is ignored, since it may move out of the way.
+            let king = match self.get_king(color) {
+                Some(king) => bitboard::square(king),
+                None => return false,
+            };
+            let walkable = self.area & !pawns & !enemy_attacks;
+            let mut reachable = bitboard::EMPTY;
+            let mut frontier = 1 << king;
+            while frontier != bitboard::EMPTY {
+                reachable |= frontier;
+                let mut next = bitboard::EMPTY;
+                for square in bitboard::squares(frontier) {
+                    next |= bitboard::king_attacks(square);
+                }
+                if next & enemy_pawns != bitboard::EMPTY {
+                    return false;
                 }
+                frontier = next & walkable & !reachable;
             }
-            None
         }
+        true
+    }
+
+    pub(crate) fn is_in_check(&self, player: Color) -> bool {
+        match self.get_king(player) {
+            Some(king) => !self.is_square_safe(player, &king),
+            None => false,
+        }
+    }
+
+    #[cfg_attr(feature = "perf", flame)]
+    pub(crate) fn has_legal_moves(&self, player: Color) -> bool {
+        self.any_legal_moves(player, &self.move_masks(player))
+    }
+
+    /// Return the empty squares `player` could drop a piece on without leaving
+    /// their king in check. If the king is in check, a drop must block the
+    /// check, so this is only the squares between the king and a single
+    /// checking slider.
+    pub fn drop_targets(&self, player: Color) -> Bitboard {
+        let masks = self.move_masks(player);
+        let targets = !self.occupied() & masks.check_mask & self.area;
+        if !masks.hoppers {
+            return targets;
+        }
+
+        // A dropped piece may also give a hopping fairy piece something to hop
+        // over, so try each drop.
+        let mut safe = bitboard::EMPTY;
+        for end in bitboard::squares(targets) {
+            let mut board = self.clone();
+            board.set(
+                bitboard::coord(end),
+                Tile::new(player, PieceType::Pawn { just_lunged: false }),
+            );
+            if !board.is_in_check(player) {
+                safe |= 1 << end;
+            }
+        }
+        safe
+    }
+
+    /// Returns true if `player` has a legal move, given the checks and pins on
+    /// their king.
+    fn any_legal_moves(&self, player: Color, masks: &MoveMasks) -> bool {
+        // Castling is checked last since it is rarely the only legal move. (In
+        // standard chess it never is, since the king could instead move one
+        // square towards the rook, but in Chess960 the king might not move.)
+        bitboard::squares(self.colors[player as usize]).any(|start| {
+            self.legal_targets(player, start, masks) != bitboard::EMPTY
+                || (self.pieces[PAWNS] & 1 << start != bitboard::EMPTY
+                    && !self
+                        .enpassant_locations(player, bitboard::coord(start))
+                        .is_empty())
+        }) || !self.castle_locations(player).is_empty()
+    }
+
+    /// Returns Some(BoardCoord) if there is a pawn in the last rank that needs
+    /// to be promoted. Otherwise, this functino returns None.
+    pub fn pawn_needs_promotion(&self) -> Option<BoardCoord> {
+        let black_pawns = self.pieces[PAWNS] & self.colors[Color::Black as usize];
+        let white_pawns = self.pieces[PAWNS] & self.colors[Color::White as usize];
+        let promotable = (black_pawns & self.first_rank(Color::White))
+            | (white_pawns & self.first_rank(Color::Black));
+        bitboard::squares(promotable).next().map(bitboard::coord)
+    }
+
+    /// Checks if the Tile at coord can be promoted to piece.
+    /// A pawn may be promoted if it is
+    /// - a pawn
+    /// - on the last rank of its side
+    /// - being promoted to a piece that is not a pawn or a king
+    pub fn check_promote(&self, coord: BoardCoord, piece: PieceType) -> Result<(), MoveError> {
+        let pawn = self.get(coord);
+        let color = if coord.1 == self.dimensions.back_rank(Color::White) {
+            Color::Black
+        } else if coord.1 == self.dimensions.back_rank(Color::Black) {
+            Color::White
+        } else {
+            return Err(MoveError::UnexpectedPromotion { square: coord });
+        };
+
+        if !pawn.is(color, PieceType::Pawn { just_lunged: false }) {
+            return Err(MoveError::UnexpectedPromotion { square: coord });
+        }
+
+        match piece {
+            PieceType::Pawn { .. } | PieceType::King => {
+                Err(MoveError::InvalidPromotionPiece { piece })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Promote the pawn located at coord to the piece of PieceType. The piece is
+    // marked as promoted, so it turns back into a pawn if captured in Crazyhouse.
+    // Note that this function does not actually check if the promotion would be
+    // valid.
+    pub fn promote_pawn(&mut self, coord: BoardCoord, piece: PieceType) {
+        let color = self.get(coord).0.unwrap().color;
+        self.set(
+            coord,
+            Tile(Some(Piece {
+                color,
+                piece,
+                has_moved: true,
+                promoted: true,
+            })),
+        );
+    }
+
+    /// Gets the piece located at the coordinates. Squares off the edge of the
+    /// board are always empty.
+    pub fn get(&self, BoardCoord(x, y): BoardCoord) -> &Tile {
+        static BLANK: Tile = Tile(None);
+        // Negative coordinates wrap around to huge indicies, so they're off
+        // the grid too.
+        self.board
+            .get(y as usize)
+            .and_then(|rank| rank.get(x as usize))
+            .unwrap_or(&BLANK)
+    }
+
+    /// Gets mutably the piece located at the coordinates. The changes are
+    /// written back to the board when the returned `TileMut` is dropped.
+    pub fn get_mut(&mut self, coord: BoardCoord) -> TileMut<'_> {
+        let tile = *self.get(coord);
+        TileMut {
+            board: self,
+            coord,
+            tile,
+        }
+    }
+
+    /// Sets the piece located at the coordinates, which must be on the grid.
+    fn set(&mut self, coord: BoardCoord, piece: Tile) {
+        let bit = bitboard::bit(coord);
+        if let Some(old) = self.get(coord).0 {
+            self.colors[old.color as usize] &= !bit;
+            self.pieces[kind_index(old.piece)] &= !bit;
+        }
+        self.unmoved &= !bit;
+        self.just_lunged &= !bit;
+        if let Some(new) = piece.0 {
+            self.colors[new.color as usize] |= bit;
+            self.pieces[kind_index(new.piece)] |= bit;
+            if !new.has_moved {
+                self.unmoved |= bit;
+            }
+            if let PieceType::Pawn { just_lunged: true } = new.piece {
+                self.just_lunged |= bit;
+            }
+        }
+        let BoardCoord(x, y) = coord;
+        self.board[y as usize][x as usize] = piece;
+    }
+
+    /// The number of files and ranks of the board.
+    pub fn dimensions(&self) -> Dimensions {
+        self.dimensions
+    }
+
+    /// Return every square of the board.
+    pub fn area(&self) -> Bitboard {
+        self.area
+    }
+
+    /// Return true if `coord` is on the board.
+    pub fn contains(&self, coord: BoardCoord) -> bool {
+        self.dimensions.contains(coord)
+    }
+
+    /// Return every square of the first rank of `color`, which is where its
+    /// king and rooks start.
+    fn first_rank(&self, color: Color) -> Bitboard {
+        bitboard::rank(self.dimensions.back_rank(color)) & self.area
+    }
+
+    /// Return where the king and rook end up after `color` castles on `side`.
+    /// These are the same in Chess960 as in standard chess: the king goes to
+    /// the c file or the second file from the right (the g file on a standard
+    /// board), and the rook goes next to it on the inside.
+    fn castle_destinations(&self, color: Color, side: BoardSide) -> (BoardCoord, BoardCoord) {
+        let rank = self.dimensions.back_rank(color);
+        let right = self.dimensions.files - 1;
+        match side {
+            BoardSide::Queenside => (BoardCoord(2, rank), BoardCoord(3, rank)),
+            BoardSide::Kingside => (BoardCoord(right - 1, rank), BoardCoord(right - 2, rank)),
+        }
+    }
+
+    /// Return an iterator over every piece on the board and its location.
+    pub fn iter_pieces(&self) -> impl Iterator<Item = (BoardCoord, Piece)> + '_ {
+        bitboard::squares(self.occupied()).map(move |square| {
+            let coord = bitboard::coord(square);
+            (coord, self.get(coord).0.unwrap())
+        })
+    }
+
+    /// Attempts to return the coordinates the king of the specified color
+    pub fn get_king(&self, color: Color) -> Option<BoardCoord> {
+        let kings = self.pieces[KINGS] & self.colors[color as usize];
+        bitboard::squares(kings).next().map(bitboard::coord)
+    }
+
+    /// Return the squares occupied by any piece.
+    pub(crate) fn occupied(&self) -> Bitboard {
+        self.colors[0] | self.colors[1]
+    }
+
+    /// Return the squares occupied by `color`'s pieces.
+    pub fn pieces_of(&self, color: Color) -> Bitboard {
+        self.colors[color as usize]
+    }
+
+    /// The squares a Rook on `square` attacks, given the `occupied` squares.
+    /// Standard boards use the magic lookups, which only work on 8x8 boards.
+    pub(crate) fn rook_attacks(&self, square: usize, occupied: Bitboard) -> Bitboard {
+        if self.dimensions == Dimensions::STANDARD {
+            bitboard::standard_rook_attacks(square, occupied)
+        } else {
+            bitboard::rook_attacks(square, occupied)
+        }
+    }
+
+    /// The squares a Bishop on `square` attacks. See `rook_attacks`.
+    pub(crate) fn bishop_attacks(&self, square: usize, occupied: Bitboard) -> Bitboard {
+        if self.dimensions == Dimensions::STANDARD {
+            bitboard::standard_bishop_attacks(square, occupied)
+        } else {
+            bitboard::bishop_attacks(square, occupied)
+        }
+    }
+
+    /// The squares a Queen on `square` attacks. See `rook_attacks`.
+    pub(crate) fn queen_attacks(&self, square: usize, occupied: Bitboard) -> Bitboard {
+        self.rook_attacks(square, occupied) | self.bishop_attacks(square, occupied)
+    }
+
+    /// Return the pieces of either color which attack `square`, if the pieces
+    /// on the board were only those in `occupied`. Changing `occupied` lets us
+    /// check for attacks "through" a piece that is about to move.
+    pub(crate) fn attackers_to(&self, square: usize, occupied: Bitboard) -> Bitboard {
+        let white_pawns = self.pieces[PAWNS] & self.colors[Color::White as usize];
+        let black_pawns = self.pieces[PAWNS] & self.colors[Color::Black as usize];
+        let rooks = self.pieces[ROOKS] | self.pieces[QUEENS];
+        let bishops = self.pieces[BISHOPS] | self.pieces[QUEENS];
+        // A white pawn attacks this square if a black pawn here would attack
+        // the white pawn, and vice versa.
+        let attackers = (bitboard::pawn_attacks(Color::Black, square) & white_pawns)
+            | (bitboard::pawn_attacks(Color::White, square) & black_pawns)
+            | (bitboard::knight_attacks(square) & self.pieces[KNIGHTS])
+            | (bitboard::king_attacks(square) & self.pieces[KINGS])
+            | (self.rook_attacks(square, occupied) & rooks)
+            | (self.bishop_attacks(square, occupied) & bishops);
+        attackers & occupied | self.fairy_attackers_to(square, occupied)
+    }
+
+    /// Return the fairy pieces which attack `square`, like `attackers_to`.
+    /// Fairy pieces move in too many ways to look their attacks up backwards
+    /// from `square`, so each one is checked in turn.
+    fn fairy_attackers_to(&self, square: usize, occupied: Bitboard) -> Bitboard {
+        let mut attackers = bitboard::EMPTY;
+        for (start, fairy) in self.fairies_on(occupied) {
+            if fairy.movement().attacks(start, occupied) & 1 << square != bitboard::EMPTY {
+                attackers |= 1 << start;
+            }
+        }
+        attackers
+    }
+
+    /// Return the squares and kinds of the fairy pieces on `squares`.
+    fn fairies_on(&self, squares: Bitboard) -> impl Iterator<Item = (usize, Fairy)> + '_ {
+        bitboard::squares(self.pieces[FAIRIES] & squares).filter_map(move |square| {
+            match self.get(bitboard::coord(square)).0 {
+                Some(Piece {
+                    piece: PieceType::Fairy(fairy),
+                    ..
+                }) => Some((square, fairy)),
+                _ => None,
+            }
+        })
+    }
 
-        fn is_enemy_rook_or_queen(color: Color, piece: Option<Piece>) -> bool {
-            match piece {
-                Some(piece) => {
-                    piece.color == color.opposite()
-                        && (piece.piece == PieceType::Rook || piece.piece == PieceType::Queen)
+    /// Find the checks and pins on `player`'s king.
+    fn move_masks(&self, player: Color) -> MoveMasks {
+        let king = match self.get_king(player) {
+            Some(king) => bitboard::square(king),
+            None => {
+                return MoveMasks {
+                    king: None,
+                    checkers: bitboard::EMPTY,
+                    check_mask: !bitboard::EMPTY,
+                    pinned: bitboard::EMPTY,
+                    pin_lines: bitboard::EMPTY,
+                    hoppers: false,
                 }
-                None => false,
             }
+        };
+        let occupied = self.occupied();
+        let enemies = self.colors[player.opposite() as usize];
+
+        let checkers = self.attackers_to(king, occupied) & enemies;
+        if self
+            .fairies_on(enemies)
+            .any(|(_, fairy)| !fairy.movement().blocked_on_lines())
+        {
+            return MoveMasks {
+                king: Some(king),
+                checkers,
+                check_mask: !bitboard::EMPTY,
+                pinned: bitboard::EMPTY,
+                pin_lines: bitboard::EMPTY,
+                hoppers: true,
+            };
         }
+        let check_mask = match checkers.count_ones() {
+            0 => !bitboard::EMPTY,
+            1 => checkers | bitboard::between(king, checkers.trailing_zeros() as usize),
+            _ => bitboard::EMPTY,
+        };
 
-        fn is_enemy_bishop_or_queen(color: Color, piece: Option<Piece>) -> bool {
-            match piece {
-                Some(piece) => {
-                    piece.color == color.opposite()
-                        && (piece.piece == PieceType::Bishop || piece.piece == PieceType::Queen)
-                }
-                None => false,
+        // An enemy slider pins a piece if that piece is the only thing between
+        // the slider and the king.
+        let rooks = (self.pieces[ROOKS] | self.pieces[QUEENS]) & enemies;
+        let bishops = (self.pieces[BISHOPS] | self.pieces[QUEENS]) & enemies;
+        let snipers = (self.rook_attacks(king, bitboard::EMPTY) & rooks)
+            | (self.bishop_attacks(king, bitboard::EMPTY) & bishops);
+        let own = self.colors[player as usize];
+        let mut pinned = bitboard::EMPTY;
+        let mut pin_lines = bitboard::EMPTY;
+        for sniper in bitboard::squares(snipers) {
+            let blockers = bitboard::between(king, sniper) & occupied;
+            if blockers.count_ones() == 1 && blockers & own != bitboard::EMPTY {
+                pinned |= blockers;
+                pin_lines |= bitboard::between(king, sniper) | 1 << sniper;
             }
         }
 
-        // Check for rooks, queens (+)
-        let up_los = (1..8).map(|i| BoardCoord(target.0, target.1 + i));
-        if is_enemy_rook_or_queen(color, first_nonempty(self, up_los)) {
-            return false;
-        }
-        let down_los = (1..8).map(|i| BoardCoord(target.0, target.1 - i));
-        if is_enemy_rook_or_queen(color, first_nonempty(self, down_los)) {
-            return false;
-        }
-        let right_los = (1..8).map(|i| BoardCoord(target.0 + i, target.1));
-        if is_enemy_rook_or_queen(color, first_nonempty(self, right_los)) {
-            return false;
-        }
-        let left_los = (1..8).map(|i| BoardCoord(target.0 - i, target.1));
-        if is_enemy_rook_or_queen(color, first_nonempty(self, left_los)) {
-            return false;
+        // A fairy piece pins a piece on a line in the same way, if it would
+        // attack the king were that piece gone. Riders with a limited range
+        // might not reach that far.
+        let lines = self.queen_attacks(king, bitboard::EMPTY);
+        for (sniper, fairy) in self.fairies_on(enemies & lines) {
+            let blockers = bitboard::between(king, sniper) & occupied;
+            if blockers.count_ones() == 1
+                && blockers & own != bitboard::EMPTY
+                && fairy.movement().attacks(sniper, occupied ^ blockers) & 1 << king
+                    != bitboard::EMPTY
+            {
+                pinned |= blockers;
+                pin_lines |= bitboard::between(king, sniper) | 1 << sniper;
+            }
         }
 
-        // Check for bishops, queens (x)
-        let up_right_los = (1..8).map(|i| BoardCoord(target.0 + i, target.1 + i));
-        if is_enemy_bishop_or_queen(color, first_nonempty(self, up_right_los)) {
-            return false;
-        }
-        let up_left_los = (1..8).map(|i| BoardCoord(target.0 - i, target.1 + i));
-        if is_enemy_bishop_or_queen(color, first_nonempty(self, up_left_los)) {
-            return false;
-        }
-        let down_right_los = (1..8).map(|i| BoardCoord(target.0 + i, target.1 - i));
-        if is_enemy_bishop_or_queen(color, first_nonempty(self, down_right_los)) {
-            return false;
-        }
-        let down_left_los = (1..8).map(|i| BoardCoord(target.0 - i, target.1 - i));
-        if is_enemy_bishop_or_queen(color, first_nonempty(self, down_left_los)) {
-            return false;
+        MoveMasks {
+            king: Some(king),
+            checkers,
+            check_mask,
+            pinned,
+            pin_lines,
+            hoppers: false,
         }
+    }
 
-        // Check for kings
-        let delta_coords = [
-            (1, 0),
-            (-1, 0),
-            (1, 1),
-            (1, -1),
-            (-1, 1),
-            (-1, -1),
-            (0, 1),
-            (0, -1),
-        ];
-        for delta in delta_coords.iter() {
-            if let Ok(check_coord) = BoardCoord::new((target.0 + delta.0, target.1 + delta.1)) {
-                if self.get(check_coord).is(color.opposite(), PieceType::King) {
-                    return false;
+    /// Return the squares the piece at `start` could move to if checks and
+    /// pins are ignored. This does not include castling or en passant.
+    fn pseudo_legal_targets(&self, start: usize) -> Bitboard {
+        let piece = match self.get(bitboard::coord(start)).0 {
+            Some(piece) => piece,
+            None => return bitboard::EMPTY,
+        };
+        let occupied = self.occupied();
+        let enemies = self.colors[piece.color.opposite() as usize];
+        use PieceType::*;
+        let targets = match piece.piece {
+            Pawn { .. } => {
+                let forwards = |squares: Bitboard| match piece.color {
+                    Color::White => squares << bitboard::FILES,
+                    Color::Black => squares >> bitboard::FILES,
+                };
+                let mut moves = forwards(1 << start) & !occupied;
+                // A pawn which hasn't moved may also move two spaces, as long
+                // as both spaces are empty.
+                if !piece.has_moved {
+                    moves |= forwards(moves) & !occupied;
                 }
+                moves | bitboard::pawn_attacks(piece.color, start) & enemies
             }
-        }
+            Knight => bitboard::knight_attacks(start),
+            Bishop => self.bishop_attacks(start, occupied),
+            Rook => self.rook_attacks(start, occupied),
+            Queen => self.queen_attacks(start, occupied),
+            King => bitboard::king_attacks(start),
+            Fairy(fairy) => {
+                let movement = fairy.movement();
+                movement.moves(start, occupied) | movement.attacks(start, occupied) & enemies
+            }
+        };
+        targets & !self.colors[piece.color as usize] & self.area
+    }
 
-        // Check for knights
-        let delta_coords = [
-            (1, 2),
-            (1, -2),
-            (-1, 2),
-            (-1, -2),
-            (2, 1),
-            (2, -1),
-            (-2, 1),
-            (-2, -1),
-        ];
-        for delta in delta_coords.iter() {
-            if let Ok(check_coord) = BoardCoord::new((target.0 + delta.0, target.1 + delta.1)) {
-                if self
-                    .get(check_coord)
-                    .is(color.opposite(), PieceType::Knight)
-                {
-                    return false;
+    /// Return the squares `player`'s piece at `start` may legally move to, not
+    /// including castling or en passant.
+    fn legal_targets(&self, player: Color, start: usize, masks: &MoveMasks) -> Bitboard {
+        let targets = self.pseudo_legal_targets(start);
+        let king = match masks.king {
+            Some(king) => king,
+            None => return targets,
+        };
+
+        if start == king {
+            // The king can't stay on a line a slider is checking along, so look
+            // for attacks as if the king was already gone.
+            let occupied = self.occupied() & !(1 << king);
+            let enemies = self.colors[player.opposite() as usize];
+            let mut safe = bitboard::EMPTY;
+            for end in bitboard::squares(targets) {
+                if self.attackers_to(end, occupied) & enemies == bitboard::EMPTY {
+                    safe |= 1 << end;
                 }
             }
+            return safe;
         }
 
-        // Check for pawn
-        let delta_coords = match color {
-            Color::White => [(1, 1), (-1, 1)],
-            Color::Black => [(1, -1), (-1, -1)],
-        };
-        for delta in delta_coords.iter() {
-            if let Ok(check_coord) = BoardCoord::new((target.0 + delta.0, target.1 + delta.1)) {
-                if self
-                    .get(check_coord)
-                    .is(color.opposite(), PieceType::Pawn { just_lunged: false })
-                {
-                    return false;
+        if masks.hoppers {
+            let mut safe = bitboard::EMPTY;
+            for end in bitboard::squares(targets) {
+                let mut board = self.clone();
+                board.move_piece(bitboard::coord(start), bitboard::coord(end));
+                if !board.is_in_check(player) {
+                    safe |= 1 << end;
                 }
             }
+            return safe;
         }
 
-        true
+        let mut targets = targets & masks.check_mask;
+        if masks.pinned & 1 << start != bitboard::EMPTY {
+            targets &= bitboard::line(king, start) & masks.pin_lines;
+        }
+        targets
     }
+}
 
-    /// Returns if the player is currently in checkmate
-    #[cfg_attr(feature = "perf", flame)]
-    fn checkmate_state(&self, player: Color) -> CheckmateState {
-        use CheckmateState::*;
-        match (
-            self.has_legal_moves(player),
-            self.is_in_check(player),
-            self.insuffient_material(),
-        ) {
-            (false, false, false) => Stalemate,
-            (false, true, false) => Checkmate,
-            (true, false, false) => Normal,
-            (true, true, false) => Check,
-            (_, _, true) => InsuffientMaterial,
-        }
+/// A mutable reference to a `Tile` on a `Board`, returned by `Board::get_mut`.
+/// The tile is written back to the board (keeping the bitboards in sync) when
+/// this is dropped.
+pub struct TileMut<'a> {
+    board: &'a mut Board,
+    coord: BoardCoord,
+    tile: Tile,
+}
+
+impl std::ops::Deref for TileMut<'_> {
+    type Target = Tile;
+
+    fn deref(&self) -> &Tile {
+        &self.tile
+    }
+}
+
+impl std::ops::DerefMut for TileMut<'_> {
+    fn deref_mut(&mut self) -> &mut Tile {
+        &mut self.tile
     }
+}
 
-    fn insuffient_material(&self) -> bool {
-        // TODO: you should implement the one harder cases where checkmate is impossible
-        /*
-        From https://en.wikipedia.org/wiki/Draw_(chess)
-        Impossibility of checkmate – if a position arises in which neither
-        player could possibly give checkmate by a series of legal moves, the
-        game is a draw ("dead position"). This is usually because there is
-        insufficient material left, but it is possible in other positions too.
-        Combinations with insufficient material to checkmate are:
-            king versus king
-            king and bishop versus king
-            king and knight versus king
-            // TODO: this one i haven't implemented this yet beause it is hard
-            king and bishop versus king and bishop with the bishops on the same color.
-        */
-        let mut num_knights = 0;
-        let mut num_bishops = 0;
-        // let mut black_has_black_square_bishops = 0;
-        // let mut num_white_square_bishops
+impl Drop for TileMut<'_> {
+    fn drop(&mut self) {
+        self.board.set(self.coord, self.tile);
+    }
+}
 
-        use PieceType::*;
-        for i in ROWS {
-            for j in COLS {
-                let coord = BoardCoord(i, j);
-                let tile = self.get(coord).0;
-                match tile {
-                    None => continue,
-                    Some(piece) => match piece.piece {
-                        Pawn { .. } | Rook | Queen => return false,
-                        Knight => num_bishops += 1,
-                        Bishop => num_knights += 1,
-                        // we will assume that the board always has two kings
-                        // if it does not have two kings then there are probably
-                        // bigger problems going on
-                        King => continue,
-                    },
-                }
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "         BLACK")?;
+        for rank in (0..self.dimensions.ranks).rev() {
+            for file in 0..self.dimensions.files {
+                write!(f, "{} ", self.get(BoardCoord(file, rank)))?;
             }
+            writeln!(f)?;
         }
+        writeln!(f, "         WHITE")?;
+        Ok(())
+    }
+}
+
+/// A board space coordinate. The origin is at the bottom left and (7, 7) is at
+/// the top right of a standard board. This is in line with how rank-file
+/// notation works~~, and also is how graphics should work~~
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct BoardCoord(pub i8, pub i8);
 
-        matches!((num_knights, num_bishops), (0, 0) | (0, 1) | (1, 0))
+impl BoardCoord {
+    /// Create a coordinate, checking that it is on the largest board allowed.
+    /// Use `Board::contains` to check that it is on a particular board.
+    pub fn new((x, y): (i8, i8)) -> Result<BoardCoord, &'static str> {
+        if 0 <= x && x < bitboard::FILES as i8 && 0 <= y && y < bitboard::RANKS as i8 {
+            Ok(BoardCoord(x, y))
+        } else {
+            Err("Expected coordinates to be in range 0-15 for files and 0-7 for ranks")
+        }
     }
+}
+
+/// The number of files and ranks of a board. Standard chess is played on an
+/// 8x8 board, but some variants use other sizes, such as 10x8 in Capablanca
+/// chess or 6x6 in Los Alamos chess. Boards may have at most `bitboard::FILES`
+/// files and `bitboard::RANKS` ranks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Dimensions {
+    pub files: i8,
+    pub ranks: i8,
+}
 
-    fn is_in_check(&self, player: Color) -> bool {
-        !self.is_square_safe(player, &self.get_king(player).unwrap())
+impl Dimensions {
+    pub const STANDARD: Dimensions = Dimensions { files: 8, ranks: 8 };
+
+    /// Create the dimensions of a board with `files` files and `ranks` ranks.
+    /// A board needs at least one file and two ranks, so that each player has
+    /// their own first rank.
+    pub fn new(files: i8, ranks: i8) -> Result<Dimensions, &'static str> {
+        if files < 1 || files > bitboard::FILES as i8 {
+            return Err("Expected between 1 and 16 files");
+        }
+        if ranks < 2 || ranks > bitboard::RANKS as i8 {
+            return Err("Expected between 2 and 8 ranks");
+        }
+        Ok(Dimensions { files, ranks })
     }
 
-    #[cfg_attr(feature = "perf", flame)]
-    fn has_legal_moves(&self, player: Color) -> bool {
-        // TODO: this is also HILARIOUSLY INEFFICENT
-        for i in ROWS {
-            for j in COLS {
-                let coord = BoardCoord(j, i);
-                let tile = self.get(coord);
-
-                if tile.is_color(player) && self.piece_has_legal_moves(player, coord) {
-                    return true;
-                }
-            }
+    /// Return true if `coord` is on a board of this size.
+    pub fn contains(self, BoardCoord(file, rank): BoardCoord) -> bool {
+        0 <= file && file < self.files && 0 <= rank && rank < self.ranks
+    }
+
+    /// Return every square of a board of this size.
+    pub fn area(self) -> Bitboard {
+        let first_rank = (1 << self.files) - 1;
+        (0..self.ranks).fold(bitboard::EMPTY, |area, rank| {
+            area | first_rank << (bitboard::FILES * rank as usize)
+        })
+    }
+
+    /// Return the rank `color`'s pieces start on, which is the rank the other
+    /// player's pawns promote on.
+    pub fn back_rank(self, color: Color) -> i8 {
+        match color {
+            Color::White => 0,
+            Color::Black => self.ranks - 1,
         }
-        false
     }
+}
 
-    fn piece_has_legal_moves(&self, player: Color, coord: BoardCoord) -> bool {
-        // TODO just directly check if a piece can move, instead of calculating all possible moves (overkill)
-        let mut out = MoveList::reserved();
-        get_move_list_full(self, player, coord, &mut out);
-        !out.0.is_empty()
+impl Default for Dimensions {
+    fn default() -> Dimensions {
+        Dimensions::STANDARD
     }
+}
 
-    /// Returns Some(BoardCoord) if there is a pawn in the last rank that needs
-    /// to be promoted. Otherwise, this functino returns None.
-    pub fn pawn_needs_promotion(&self) -> Option<BoardCoord> {
-        for i in ROWS {
-            let black_coord = BoardCoord(i, 0);
-            if self
-                .get(black_coord)
-                .is(Color::Black, PieceType::Pawn { just_lunged: false })
-            {
-                return Some(black_coord);
-            }
+/// Displays the coordinate as an algebraic square name, such as "e4".
+impl fmt::Display for BoardCoord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let file = (b'a' + self.0 as u8) as char;
+        let rank = (b'1' + self.1 as u8) as char;
+        write!(f, "{}{}", file, rank)
+    }
+}
 
-            let white_coord = BoardCoord(i, 7);
-            if self
-                .get(white_coord)
-                .is(Color::White, PieceType::Pawn { just_lunged: false })
-            {
-                return Some(white_coord);
-            }
+/// Parses an algebraic square name, such as "e4".
+impl FromStr for BoardCoord {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<BoardCoord, Self::Err> {
+        let bytes = s.as_bytes();
+        if bytes.len() != 2 {
+            return Err("Expected a square name such as \"e4\"");
         }
-        None
+        let file = bytes[0].wrapping_sub(b'a') as i8;
+        let rank = bytes[1].wrapping_sub(b'1') as i8;
+        BoardCoord::new((file, rank))
     }
+}
 
-    /// Checks if the Tile at coord can be promoted to piece.
-    /// A pawn may be promoted if it is
-    /// - a pawn
-    /// - on the last rank of its side
-    /// - being promoted to a piece that is not a pawn or a king
-    pub fn check_promote(&self, coord: BoardCoord, piece: PieceType) -> Result<(), &'static str> {
-        let pawn = self.get(coord);
-        let color = match coord.1 {
-            0 => Color::Black,
-            7 => Color::White,
-            _ => {
-                return Err("Can only promote a pawn at the last rank of the board");
+/// The reasons a move can be illegal, as returned by `BoardState::check_turn`.
+/// Squares are given so that the move (or the pieces stopping it) can be
+/// pointed out to the player.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveError {
+    /// The move isn't well formed UCI notation. See `Move::from_str`.
+    InvalidNotation(&'static str),
+    /// There is no piece on `square` to move.
+    NoPieceAtSource { square: BoardCoord },
+    /// The piece on `square` belongs to the other player.
+    NotYourPiece { square: BoardCoord },
+    /// The piece on `start` can't move to `end`, even ignoring checks.
+    InvalidDestination { start: BoardCoord, end: BoardCoord },
+    /// The piece on `square` is pinned to its king by the piece on `by`, and
+    /// the move would take it off the line between them.
+    Pinned { square: BoardCoord, by: BoardCoord },
+    /// The move would leave the king on `king` attacked by the piece on `by`.
+    LeavesKingInCheck { king: BoardCoord, by: BoardCoord },
+    /// The king or the rook on `side` has already moved.
+    NoCastlingRight { side: BoardSide },
+    /// The king on `king` can't castle, since it is in check.
+    CastleOutOfCheck { king: BoardCoord },
+    /// The king would pass through (or land on) `square`, which is attacked.
+    CastleThroughCheck { square: BoardCoord },
+    /// The king or rook would pass through `square`, which isn't empty.
+    CastleBlocked { square: BoardCoord },
+    /// There is no pawn which just lunged on `square` to capture en passant.
+    InvalidEnPassant { square: BoardCoord },
+    /// The pawn on `square` has reached the last rank and must be promoted
+    /// before anything else happens.
+    PromotionPending { square: BoardCoord },
+    /// The pawn moving to `square` reaches the last rank, so the move must
+    /// say what to promote it to.
+    PromotionRequired { square: BoardCoord },
+    /// The pawn can't be promoted to `piece` in the variant being played.
+    InvalidPromotionPiece { piece: PieceType },
+    /// The move gives a piece to promote to, but isn't a pawn reaching the
+    /// last rank.
+    UnexpectedPromotion { square: BoardCoord },
+    /// Pieces can't be dropped in the variant being played.
+    DropsNotAllowed,
+    /// The move breaks a rule of the variant being played, other than the
+    /// rules of standard chess.
+    VariantRule(&'static str),
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use MoveError::*;
+        match self {
+            InvalidNotation(reason) => write!(f, "{}", reason),
+            NoPieceAtSource { square } => write!(f, "There is no piece on {}", square),
+            NotYourPiece { square } => write!(f, "The piece on {} isn't yours", square),
+            InvalidDestination { start, end } => {
+                write!(f, "The piece on {} can't move to {}", start, end)
             }
-        };
+            Pinned { square, by } => write!(f, "The piece on {} is pinned by {}", square, by),
+            LeavesKingInCheck { king, by } => {
+                write!(f, "The king on {} would be in check from {}", king, by)
+            }
+            NoCastlingRight { side } => {
+                let side = match side {
+                    BoardSide::Kingside => "kingside",
+                    BoardSide::Queenside => "queenside",
+                };
+                write!(f, "Can't castle {}, the king or rook has moved", side)
+            }
+            CastleOutOfCheck { king } => {
+                write!(f, "Can't castle, the king on {} is in check", king)
+            }
+            CastleThroughCheck { square } => {
+                write!(
+                    f,
+                    "Can't castle, the king would pass through check on {}",
+                    square
+                )
+            }
+            CastleBlocked { square } => write!(f, "Can't castle, {} is not empty", square),
+            InvalidEnPassant { square } => {
+                write!(f, "There is no pawn which just lunged on {}", square)
+            }
+            PromotionPending { square } => write!(f, "The pawn on {} must be promoted", square),
+            PromotionRequired { square } => {
+                write!(f, "A pawn reaching {} must be promoted", square)
+            }
+            InvalidPromotionPiece { piece } => write!(f, "Can not promote to {:?}", piece),
+            UnexpectedPromotion { square } => {
+                write!(
+                    f,
+                    "Only a pawn reaching the last rank can be promoted, not on {}",
+                    square
+                )
+            }
+            DropsNotAllowed => write!(f, "Pieces can't be dropped in this variant"),
+            VariantRule(reason) => write!(f, "{}", reason),
+        }
+    }
+}
 
-        if !pawn.is(color, PieceType::Pawn { just_lunged: false }) {
-            return Err("Can only promote a pawn of opposite color at this position.");
+impl std::error::Error for MoveError {}
+
+/// The pieces a pawn may be promoted to in standard chess, most valuable
+/// first. See `Variant::promotion_pieces`.
+pub const PROMOTION_PIECES: [PieceType; 4] = [
+    PieceType::Queen,
+    PieceType::Rook,
+    PieceType::Bishop,
+    PieceType::Knight,
+];
+
+/// Change the promotions in `moves`, which promote to each of
+/// `PROMOTION_PIECES`, to promote to each of `promotions` instead.
+fn vary_promotions(moves: &mut Vec<Move>, promotions: &[PieceType]) {
+    // Every promotion has a queen promotion to the same square
+    let extra: Vec<Move> = moves
+        .iter()
+        .filter(|mv| mv.promotion == Some(PieceType::Queen))
+        .flat_map(|&mv| {
+            promotions
+                .iter()
+                .filter(|piece| !PROMOTION_PIECES.contains(piece))
+                .map(move |&piece| Move {
+                    promotion: Some(piece),
+                    ..mv
+                })
+        })
+        .collect();
+    moves.retain(|mv| match mv.promotion {
+        Some(piece) => promotions.contains(&piece),
+        None => true,
+    });
+    moves.extend(extra);
+}
+
+/// A move of the piece on `start` to `end`. If the move is a pawn reaching the
+/// last rank, `promotion` is the piece the pawn is promoted to. Castling is
+/// written as the king moving two squares.
+/// In Crazyhouse, a move may instead drop the piece `drop` from the player's
+/// pocket onto `end`. `start` is the same as `end` for drops.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Move {
+    pub start: BoardCoord,
+    pub end: BoardCoord,
+    pub promotion: Option<PieceType>,
+    pub drop: Option<PieceType>,
+    /// What kind of move this is. These are derived from the board the move is
+    /// made on, see `Move::new`.
+    pub flags: MoveFlags,
+}
+
+impl Move {
+    /// Create the move from `start` to `end` on `board`, setting its flags
+    /// according to what kind of move it is. Note that this does not check if
+    /// the move is legal.
+    pub fn new(
+        board: &Board,
+        start: BoardCoord,
+        end: BoardCoord,
+        promotion: Option<PieceType>,
+    ) -> Move {
+        Move {
+            start,
+            end,
+            promotion,
+            drop: None,
+            flags: MoveFlags::from_coords(&move_type_coords(board, start, end)),
         }
+    }
 
-        match piece {
-            PieceType::Pawn { .. } => Err("Can not promote to a pawn"),
-            PieceType::King => Err("Can not promote to a king"),
-            _ => Ok(()),
+    /// Create the move which drops `piece` from the player's pocket onto
+    /// `square`. Note that this does not check if the move is legal.
+    pub fn drop(piece: PieceType, square: BoardCoord) -> Move {
+        Move {
+            start: square,
+            end: square,
+            promotion: None,
+            drop: Some(piece),
+            flags: MoveFlags::NONE,
         }
     }
 
-    // Promote the pawn located at coord to the piece of PieceType
-    // Note that this function does not actually check if the promotion would be
-    // valid.
-    pub fn promote_pawn(&mut self, coord: BoardCoord, piece: PieceType) {
-        let tile = self.get_mut(coord);
-        *tile = Tile(Some(Piece {
-            color: tile.0.unwrap().color,
-            piece,
-            has_moved: true,
-        }));
+    /// Returns true if this move captures a piece, including en passant.
+    pub fn is_capture(&self) -> bool {
+        self.flags.contains(MoveFlags::CAPTURE)
     }
 
-    /// Gets the piece located at the coordinates.
-    pub fn get(&self, BoardCoord(x, y): BoardCoord) -> &Tile {
-        // i promise very very hard that this i8 is, in fact, in the range 0-7
-        &self.board[(7 - y) as usize][x as usize]
+    pub fn is_castle(&self) -> bool {
+        self.flags.contains(MoveFlags::CASTLE)
     }
 
-    /// Gets mutably the piece located at the coordinates
-    pub fn get_mut(&mut self, BoardCoord(x, y): BoardCoord) -> &mut Tile {
-        // i promise very very hard that this i8 is, in fact, in the range 0-7
-        &mut self.board[(7 - y) as usize][x as usize]
+    pub fn is_en_passant(&self) -> bool {
+        self.flags.contains(MoveFlags::EN_PASSANT)
     }
 
-    /// Sets the piece located at the coordinates
-    fn set(&mut self, BoardCoord(x, y): BoardCoord, piece: Tile) {
-        // i promise very very hard that this i8 is, in fact, in the range 0-7
-        self.board[(7 - y) as usize][x as usize] = piece;
+    /// Returns true if this move is a pawn moving two squares forward.
+    pub fn is_double_push(&self) -> bool {
+        self.flags.contains(MoveFlags::DOUBLE_PUSH)
     }
 
-    /// Attempts to return the coordinates the king of the specified color
-    /// TODO: MAKE MORE EFFICENT?
-    pub fn get_king(&self, color: Color) -> Option<BoardCoord> {
-        for i in ROWS {
-            for j in COLS {
-                let coord = BoardCoord::new((j, 7 - i)).unwrap();
-                let tile = self.get(coord);
-                if tile.is(color, PieceType::King) {
-                    return Some(coord);
-                }
-            }
+    pub fn is_promotion(&self) -> bool {
+        self.promotion.is_some()
+    }
+
+    pub fn is_drop(&self) -> bool {
+        self.drop.is_some()
+    }
+}
+
+/// A set of flags describing what kind of move a `Move` is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct MoveFlags(u8);
+
+impl MoveFlags {
+    pub const NONE: MoveFlags = MoveFlags(0);
+    /// Set for captures, including en passant.
+    pub const CAPTURE: MoveFlags = MoveFlags(1);
+    pub const CASTLE: MoveFlags = MoveFlags(1 << 1);
+    pub const EN_PASSANT: MoveFlags = MoveFlags(1 << 2);
+    /// Set for a pawn moving two squares forward.
+    pub const DOUBLE_PUSH: MoveFlags = MoveFlags(1 << 3);
+
+    /// Returns true if every flag set in `other` is also set in `self`.
+    pub fn contains(self, other: MoveFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    fn from_coords(move_type: &MoveTypeCoords) -> MoveFlags {
+        match move_type {
+            MoveTypeCoords::Normal { .. } => MoveFlags::NONE,
+            MoveTypeCoords::Capture { .. } => MoveFlags::CAPTURE,
+            MoveTypeCoords::Lunge { .. } => MoveFlags::DOUBLE_PUSH,
+            MoveTypeCoords::Castle { .. } => MoveFlags::CASTLE,
+            MoveTypeCoords::EnPassant { .. } => MoveFlags::CAPTURE | MoveFlags::EN_PASSANT,
+            MoveTypeCoords::Drop { .. } => MoveFlags::NONE,
         }
-        None
     }
 }
 
-impl fmt::Display for Board {
+impl std::ops::BitOr for MoveFlags {
+    type Output = MoveFlags;
+
+    fn bitor(self, other: MoveFlags) -> MoveFlags {
+        MoveFlags(self.0 | other.0)
+    }
+}
+
+/// Displays the move in the long algebraic notation used by UCI, such as "e2e4"
+/// or "e7e8q". Drops are written as the piece and the square, such as "N@f3".
+impl fmt::Display for Move {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        writeln!(f, "         BLACK")?;
-        for row in &self.board {
-            for piece in row {
-                write!(f, "{} ", piece)?;
-            }
-            writeln!(f)?;
+        if let Some(piece) = self.drop {
+            return write!(f, "{}@{}", piece, self.end);
+        }
+        write!(f, "{}{}", self.start, self.end)?;
+        if let Some(promotion) = self.promotion {
+            write!(f, "{}", promotion.to_string().to_ascii_lowercase())?;
         }
-        writeln!(f, "         WHITE")?;
         Ok(())
     }
 }
 
-/// A board space coordinate. The origin is at the bottom left and (7, 7) is at
-/// the top right. This is in line with how rank-file notation works~~, and also
-/// is how graphics should work~~
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-#[non_exhaustive]
-pub struct BoardCoord(pub i8, pub i8);
+/// Parses a move in the long algebraic notation used by UCI, such as "e2e4",
+/// "e7e8q", or "N@f3". This only checks the notation and doesn't set the move's flags, so
+/// use `BoardState::parse_uci_move` to get a move which is legal in a
+/// particular position.
+impl FromStr for Move {
+    type Err = &'static str;
 
-impl BoardCoord {
-    pub fn new((x, y): (i8, i8)) -> Result<BoardCoord, &'static str> {
-        if on_board_i8((x, y)) {
-            Ok(BoardCoord(x, y))
-        } else {
-            Err("Expected coordinates to be in range 0-7")
+    fn from_str(s: &str) -> Result<Move, Self::Err> {
+        use PieceType::*;
+        if !s.is_ascii() || (s.len() != 4 && s.len() != 5) {
+            return Err("Expected a move such as \"e2e4\" or \"e7e8q\"");
+        }
+        if &s[1..2] == "@" {
+            let piece = match &s[0..1] {
+                "P" => Pawn { just_lunged: false },
+                "N" => Knight,
+                "B" => Bishop,
+                "R" => Rook,
+                "Q" => Queen,
+                letter => match crate::fairy::Fairy::from_letter(letter.chars().next().unwrap()) {
+                    Some(fairy) => Fairy(fairy),
+                    None => return Err("Expected the dropped piece to be one of P, N, B, R, or Q"),
+                },
+            };
+            return Ok(Move::drop(piece, s[2..].parse()?));
         }
+        let start = s[0..2].parse()?;
+        let end = s[2..4].parse()?;
+        let promotion = match s[4..].chars().next() {
+            None => None,
+            Some('n') => Some(Knight),
+            Some('b') => Some(Bishop),
+            Some('r') => Some(Rook),
+            Some('q') => Some(Queen),
+            Some(c) => match crate::fairy::Fairy::from_letter(c.to_ascii_uppercase()) {
+                Some(fairy) => Some(Fairy(fairy)),
+                None => return Err("Expected the promotion to be one of n, b, r, or q"),
+            },
+        };
+        Ok(Move {
+            start,
+            end,
+            promotion,
+            drop: None,
+            flags: MoveFlags::NONE,
+        })
     }
 }
 
@@ -960,6 +2732,7 @@ impl BoardCoord {
 pub struct MoveList(pub Vec<BoardCoord>);
 
 impl MoveList {
+    #[cfg(test)]
     fn reserved() -> MoveList {
         // A queen on an empty board has up to 28 possible moves it can make
         // (7 for each of its 4 lines of sight)
@@ -967,11 +2740,15 @@ impl MoveList {
     }
 }
 
+/// Displays the squares in the list on an 8x8 board, or a larger one if some
+/// of the squares are further out.
 impl fmt::Display for MoveList {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        for i in ROWS {
-            for j in COLS {
-                let coord = BoardCoord(j, 7 - i);
+        let files = self.0.iter().map(|coord| coord.0 + 1).fold(8, i8::max);
+        let ranks = self.0.iter().map(|coord| coord.1 + 1).fold(8, i8::max);
+        for rank in (0..ranks).rev() {
+            for file in 0..files {
+                let coord = BoardCoord(file, rank);
                 if self.0.contains(&coord) {
                     write!(f, "## ")?;
                 } else {
@@ -984,10 +2761,15 @@ impl fmt::Display for MoveList {
     }
 }
 
+// The functions below generate moves by walking over the board tile by tile.
+// The bitboard move generation in `Board` is much faster, but these are kept
+// around to check the bitboard move generation against.
+
 /// Return a MoveList of the piece located at `coord`.
 /// This function DOES check if a move made by the King would put the King into
 /// check and DOES NOT check if the King can castle. It also DOES NOT check if
 /// a pawn needs to be promoted.
+#[cfg(test)]
 #[cfg_attr(feature = "perf", flame)]
 fn get_move_list_full(board: &Board, player: Color, coord: BoardCoord, out: &mut MoveList) {
     get_move_list_ignore_check(&board, coord, out);
@@ -1023,6 +2805,7 @@ fn get_move_list_full(board: &Board, player: Color, coord: BoardCoord, out: &mut
 /// that the player to move is whatever the color of the piece at `coord` is.
 /// This function does NOT check if a move made by the King would put the King into
 /// check.
+#[cfg(test)]
 #[cfg_attr(feature = "perf", flame)]
 fn get_move_list_ignore_check(board: &Board, coord: BoardCoord, out: &mut MoveList) {
     let piece = board.get(coord).0;
@@ -1038,6 +2821,7 @@ fn get_move_list_ignore_check(board: &Board, coord: BoardCoord, out: &mut MoveLi
             Bishop | Rook | Queen => {
                 check_line_of_sight_piece(board, coord, piece.color, get_los(piece.piece), out)
             }
+            Fairy(fairy) => check_fairy_piece(board, coord, piece.color, fairy, out),
         },
     }
 }
@@ -1045,6 +2829,7 @@ fn get_move_list_ignore_check(board: &Board, coord: BoardCoord, out: &mut MoveLi
 /// Get a list of the locations the pawn at `pos` can move to. The pawn's color
 /// is assumed to be `color`. Note that this function doesn't actually check if
 /// there is a pawn at `pos`.
+#[cfg(test)]
 fn check_pawn(board: &Board, pos: BoardCoord, color: Color, out: &mut MoveList) {
     // Check forward space if it can be moved into.
     let forwards = BoardCoord::new((pos.0, pos.1 + color.direction()));
@@ -1089,6 +2874,7 @@ fn check_pawn(board: &Board, pos: BoardCoord, color: Color, out: &mut MoveList)
 /// there if the Tile is on the board and is either unoccupied (a move) or is a
 /// piece of the opposite color (a capture). Note that this function doesn't
 /// actually check the piece at `pos`.
+#[cfg(test)]
 fn check_jump_piece(
     board: &Board,
     pos: BoardCoord,
@@ -1098,7 +2884,7 @@ fn check_jump_piece(
 ) {
     for delta in move_deltas {
         let end_pos = BoardCoord(pos.0 + delta.0, pos.1 + delta.1 * color.direction());
-        if !on_board(end_pos) {
+        if !board.contains(end_pos) {
             continue;
         }
         // A piece may actually move to end_pos if the location is unoccupied
@@ -1117,6 +2903,7 @@ fn check_jump_piece(
 /// Rooks, the line of sight starts closest to the Rook, and goes away from it
 /// in an orthogonal direction. Lines of sight end on the first piece of the opposite
 /// color or just before the first piece of the same color.
+#[cfg(test)]
 fn check_line_of_sight_piece(
     board: &Board,
     pos: BoardCoord,
@@ -1126,11 +2913,10 @@ fn check_line_of_sight_piece(
 ) {
     for los in line_of_sights {
         for delta in los {
-            let end_pos =
-                match BoardCoord::new((pos.0 + delta.0, pos.1 + delta.1 * color.direction())) {
-                    Ok(end_pos) => end_pos,
-                    Err(_) => break,
-                };
+            let end_pos = BoardCoord(pos.0 + delta.0, pos.1 + delta.1 * color.direction());
+            if !board.contains(end_pos) {
+                break;
+            }
 
             match board.get(end_pos).0 {
                 None => out.0.push(end_pos),
@@ -1145,30 +2931,116 @@ fn check_line_of_sight_piece(
     }
 }
 
-type LosIterator = std::iter::Map<std::ops::Range<i8>, fn(i8) -> BoardCoord>;
+/// Get a list of valid locations the fairy piece may move or capture to. Each
+/// leg of its movement is checked as a "jump" piece if it is a leaper, or as
+/// a "LoS" piece if it is a rider, and then only the moves or captures it
+/// allows are kept.
+#[cfg(test)]
+fn check_fairy_piece(
+    board: &Board,
+    pos: BoardCoord,
+    color: Color,
+    fairy: Fairy,
+    out: &mut MoveList,
+) {
+    for leg in &fairy.movement().legs {
+        let mut leg_out = MoveList::reserved();
+        if leg.hops {
+            check_hopping_piece(board, pos, color, leg, &mut leg_out);
+        } else if leg.range == Some(1) {
+            check_jump_piece(board, pos, color, leg.steps.clone(), &mut leg_out);
+        } else {
+            let end = leg
+                .range
+                .map_or(bitboard::FILES as i8, |range| range as i8 + 1);
+            let los = leg.steps.iter().map(move |&step| line_of_sight(step, end));
+            check_line_of_sight_piece(board, pos, color, los, &mut leg_out);
+        }
+
+        for end_pos in leg_out.0 {
+            let allowed = match board.get(end_pos).0 {
+                None => leg.moves,
+                Some(_) => leg.captures,
+            };
+            if allowed && !out.0.contains(&end_pos) {
+                out.0.push(end_pos);
+            }
+        }
+    }
+}
+
+/// Get a list of valid locations a piece which hops along the lines of `leg`
+/// may move or capture to. The piece must hop over the first piece in a line
+/// (of either color) and lands on the square just past it.
+#[cfg(test)]
+fn check_hopping_piece(
+    board: &Board,
+    pos: BoardCoord,
+    color: Color,
+    leg: &crate::fairy::Leg,
+    out: &mut MoveList,
+) {
+    let range = leg.range.map_or(bitboard::FILES as i8, |range| range as i8);
+    for &step in &leg.steps {
+        let hurdle = (1..=range)
+            .map(|i| BoardCoord(pos.0 + i * step.0, pos.1 + i * step.1))
+            .take_while(|&coord| board.contains(coord))
+            .find(|&coord| board.get(coord).0.is_some());
+        if let Some(hurdle) = hurdle {
+            let end_pos = BoardCoord(hurdle.0 + step.0, hurdle.1 + step.1);
+            if !board.contains(end_pos) {
+                continue;
+            }
+            match board.get(end_pos).0 {
+                Some(piece) if piece.color == color => {}
+                _ => out.0.push(end_pos),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+type LosIterator = std::iter::Map<
+    std::iter::Zip<std::ops::Range<i8>, std::iter::Repeat<BoardCoord>>,
+    fn((i8, BoardCoord)) -> BoardCoord,
+>;
+
+/// Returns the line of sight going out from a piece in steps of `step`, up to
+/// (but not including) `end` steps away.
+#[cfg(test)]
+fn line_of_sight(step: BoardCoord, end: i8) -> LosIterator {
+    (1..end)
+        .zip(std::iter::repeat(step))
+        .map(boring(|(i, step)| BoardCoord(i * step.0, i * step.1)))
+}
 
 /// Returns LoS for Rooks, Bishops, and Queens. Panics on other PieceTypes.
+#[cfg(test)]
 fn get_los(piece: PieceType) -> Box<dyn Iterator<Item = LosIterator>> {
     use PieceType::*;
     match piece {
         Rook => Box::new(get_los_rook()),
         Bishop => Box::new(get_los_bishop()),
         Queen => Box::new(get_los_rook().chain(get_los_bishop())),
-        Pawn { .. } | Knight | King => panic!("Expected a Rook, Bishop, or Queen. Got {:?}", piece),
+        Pawn { .. } | Knight | King | Fairy(_) => {
+            panic!("Expected a Rook, Bishop, or Queen. Got {:?}", piece)
+        }
     }
 }
 
 // needed to force the iterators below to not be closures and instead be
 // boring function types
+#[cfg(test)]
 fn boring<T, U>(f: fn(T) -> U) -> fn(T) -> U {
     f
 }
 
+#[cfg(test)]
 fn get_los_rook() -> impl Iterator<Item = LosIterator> {
-    let los_right = (1..8).map(boring(|i| BoardCoord(i, 0)));
-    let los_left = (1..8).map(boring(|i: i8| BoardCoord(-i, 0)));
-    let los_up = (1..8).map(boring(|i| BoardCoord(0, i)));
-    let los_down = (1..8).map(boring(|i: i8| BoardCoord(0, -i)));
+    let los_right = line_of_sight(BoardCoord(1, 0), bitboard::FILES as i8);
+    let los_left = line_of_sight(BoardCoord(-1, 0), bitboard::FILES as i8);
+    let los_up = line_of_sight(BoardCoord(0, 1), bitboard::FILES as i8);
+    let los_down = line_of_sight(BoardCoord(0, -1), bitboard::FILES as i8);
     use std::iter::once;
     once(los_right)
         .chain(once(los_left))
@@ -1176,11 +3048,12 @@ fn get_los_rook() -> impl Iterator<Item = LosIterator> {
         .chain(once(los_down))
 }
 
+#[cfg(test)]
 fn get_los_bishop() -> impl Iterator<Item = LosIterator> {
-    let los_up_right = (1..8).map(boring(|i| BoardCoord(i, i)));
-    let los_up_left = (1..8).map(boring(|i: i8| BoardCoord(-i, i)));
-    let los_down_right = (1..8).map(boring(|i: i8| BoardCoord(i, -i)));
-    let los_down_left = (1..8).map(boring(|i: i8| BoardCoord(-i, -i)));
+    let los_up_right = line_of_sight(BoardCoord(1, 1), bitboard::FILES as i8);
+    let los_up_left = line_of_sight(BoardCoord(-1, 1), bitboard::FILES as i8);
+    let los_down_right = line_of_sight(BoardCoord(1, -1), bitboard::FILES as i8);
+    let los_down_left = line_of_sight(BoardCoord(-1, -1), bitboard::FILES as i8);
 
     use std::iter::once;
     once(los_up_right)
@@ -1192,6 +3065,7 @@ fn get_los_bishop() -> impl Iterator<Item = LosIterator> {
 /// Return a list of valid movement deltas (offsets from the piece) given a
 /// PieceType. Move deltas DO NOT take into account the piece's color.
 /// This function only works on Knights and Kings and panics on everything else.
+#[cfg(test)]
 fn get_move_deltas(piece: PieceType) -> Vec<BoardCoord> {
     use PieceType::*;
     match piece {
@@ -1215,28 +3089,29 @@ fn get_move_deltas(piece: PieceType) -> Vec<BoardCoord> {
             BoardCoord(-1, 0),
             BoardCoord(-1, 1),
         ],
-        Pawn { .. } | Rook | Bishop | Queen => {
+        Pawn { .. } | Rook | Bishop | Queen | Fairy(_) => {
             panic!("Expected a Knight or a King, got {:?}", piece)
         }
     }
 }
 
-/// Return true if `pos` would be actually on the board.
-pub fn on_board(pos: BoardCoord) -> bool {
-    0 <= pos.0 && pos.0 < 8 && 0 <= pos.1 && pos.1 < 8
-}
-
-/// Return true if `pos` would be actually on the board.
-pub fn on_board_i8(pos: (i8, i8)) -> bool {
-    0 <= pos.0 && pos.0 < 8 && 0 <= pos.1 && pos.1 < 8
-}
-
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub enum BoardSide {
     Queenside,
     Kingside,
 }
 
+/// Return the end square of the castling move where the king moves from
+/// `king_start` to `king_end` and castles with the rook on `rook_start`. See
+/// `Board::castle_move`.
+fn castle_end(king_start: BoardCoord, king_end: BoardCoord, rook_start: BoardCoord) -> BoardCoord {
+    if (king_end.0 - king_start.0).abs() >= 2 {
+        king_end
+    } else {
+        rook_start
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 enum MoveType {
     Normal,
@@ -1247,11 +3122,13 @@ enum MoveType {
     // queen's side of the board (the x coordinate decreases), and vice versa for
     // "kingside" enpassant
     EnPassant(BoardSide),
+    Drop,
 }
 
 /// Returns what kind of move this is, either normal, a castle, or an en passant
-/// Note that a castle is expected to be a move starting on the king, so these
-/// are the only following castles
+/// Note that a castle is expected to be a move starting on the king, either
+/// moving two or more squares along the first rank to the c or g file, or
+/// moving onto the rook it castles with. See `Board::castle_move`.
 fn move_type(board: &Board, start: BoardCoord, end: BoardCoord) -> MoveType {
     use BoardSide::*;
     use MoveType::*;
@@ -1268,9 +3145,18 @@ fn move_type(board: &Board, start: BoardCoord, end: BoardCoord) -> MoveType {
 
     let empty_end_pos = board.get(end).0.is_none();
 
+    if let PieceType::King = piece.piece {
+        for &side in [Queenside, Kingside].iter() {
+            let (king_end, _) = board.castle_destinations(piece.color, side);
+            let takes_rook = board.castling_rook(piece.color, side) == Some(end);
+            let moves_to_end = start.1 == king_end.1 && end == king_end && delta.0.abs() >= 2;
+            if takes_rook || moves_to_end {
+                return Castle(piece.color, side);
+            }
+        }
+    }
+
     match (piece.piece, delta, empty_end_pos) {
-        (PieceType::King, (-2, 0), _) => Castle(piece.color, Queenside),
-        (PieceType::King, (2, 0), _) => Castle(piece.color, Kingside),
         (PieceType::Pawn { .. }, (0, 2), _) => Lunge,
         // An enpassant move will always attempt to move into an empty square
         // while a capture will move onto a nonempty square
@@ -1281,7 +3167,7 @@ fn move_type(board: &Board, start: BoardCoord, end: BoardCoord) -> MoveType {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum MoveTypeCoords {
     Normal {
         start: BoardCoord,
@@ -1306,21 +3192,54 @@ pub enum MoveTypeCoords {
         end: BoardCoord,
         captured_pawn: BoardCoord,
     },
+    /// A piece dropped from a pocket onto `end`, in Crazyhouse.
+    Drop {
+        end: BoardCoord,
+    },
+}
+
+impl MoveTypeCoords {
+    /// Return the start and end coordinates of the moving piece. For castling,
+    /// this is the start and end of the king.
+    pub fn coords(&self) -> (BoardCoord, BoardCoord) {
+        use MoveTypeCoords::*;
+        match *self {
+            Normal { start, end }
+            | Capture { start, end }
+            | Lunge { start, end }
+            | EnPassant { start, end, .. } => (start, end),
+            Castle {
+                king_start,
+                king_end,
+                ..
+            } => (king_start, king_end),
+            Drop { end } => (end, end),
+        }
+    }
 }
 
-fn to_coords(move_type: MoveType, start: BoardCoord, end: BoardCoord) -> MoveTypeCoords {
+fn to_coords(
+    board: &Board,
+    move_type: MoveType,
+    start: BoardCoord,
+    end: BoardCoord,
+) -> MoveTypeCoords {
     match move_type {
         MoveType::Normal => MoveTypeCoords::Normal { start, end },
         MoveType::Lunge => MoveTypeCoords::Lunge { start, end },
         MoveType::Capture => MoveTypeCoords::Capture { start, end },
         MoveType::Castle(color, board_side) => {
-            let (king_start, king_end) = (start, end);
-            let (rook_start, rook_end) = match (color, board_side) {
-                (Color::White, BoardSide::Queenside) => (BoardCoord(0, 0), BoardCoord(3, 0)),
-                (Color::White, BoardSide::Kingside) => (BoardCoord(7, 0), BoardCoord(5, 0)),
-                (Color::Black, BoardSide::Queenside) => (BoardCoord(0, 7), BoardCoord(3, 7)),
-                (Color::Black, BoardSide::Kingside) => (BoardCoord(7, 7), BoardCoord(5, 7)),
-            };
+            let king_start = start;
+            let (king_end, rook_end) = board.castle_destinations(color, board_side);
+            // If there's no rook to castle with, the move is illegal anyway, so
+            // just assume the rook is in the corner like in standard chess.
+            let rook_start = board.castling_rook(color, board_side).unwrap_or_else(|| {
+                let file = match board_side {
+                    BoardSide::Queenside => 0,
+                    BoardSide::Kingside => board.dimensions.files - 1,
+                };
+                BoardCoord(file, king_end.1)
+            });
             MoveTypeCoords::Castle {
                 king_start,
                 king_end,
@@ -1339,11 +3258,15 @@ fn to_coords(move_type: MoveType, start: BoardCoord, end: BoardCoord) -> MoveTyp
                 captured_pawn,
             }
         }
+        MoveType::Drop => MoveTypeCoords::Drop { end },
     }
 }
 
+/// Return what kind of move moving the piece on `start` to `end` is, and the
+/// squares it involves. Drops can't be told apart from other moves by their
+/// squares, so this never returns `MoveTypeCoords::Drop`.
 pub fn move_type_coords(board: &Board, start: BoardCoord, end: BoardCoord) -> MoveTypeCoords {
-    to_coords(move_type(board, start, end), start, end)
+    to_coords(board, move_type(board, start, end), start, end)
 }
 
 /// Newtype wrapper for `Option<Piece>`. `Some(piece)` indicates that a piece is
@@ -1357,6 +3280,7 @@ impl Tile {
             color,
             piece,
             has_moved: false,
+            promoted: false,
         }))
     }
 
@@ -1376,6 +3300,18 @@ impl Tile {
         }
     }
 
+    /// Set the `promoted` field to `set`. This panics if the `Tile` is `None`.
+    pub fn set_promoted(&mut self, set: bool) {
+        if let Some(piece) = &mut self.0 {
+            piece.promoted = set;
+        } else {
+            panic!(
+                "Expected Tile to be Some piece, got None instead. set = {}",
+                set
+            );
+        }
+    }
+
     /// Returns true if the `Tile` actually has a piece and
     /// `color` and `piece_type` match. Note: the `just_lunged` flag on
     /// the Pawn piecetype is ignored.
@@ -1423,12 +3359,133 @@ pub struct Piece {
     pub color: Color,
     pub piece: PieceType,
     has_moved: bool,
+    promoted: bool,
 }
 
 impl Piece {
     pub fn as_str(&self) -> &'static str {
         self.piece.as_str()
     }
+
+    /// Returns true if this piece has moved at least once this game.
+    pub fn has_moved(&self) -> bool {
+        self.has_moved
+    }
+
+    /// Returns true if this piece was a pawn which was promoted.
+    pub fn is_promoted(&self) -> bool {
+        self.promoted
+    }
+
+    /// Return the kind of piece this becomes when captured in Crazyhouse.
+    /// Promoted pieces turn back into pawns, and every other piece stays the
+    /// same.
+    pub fn demoted(&self) -> PieceType {
+        if self.promoted {
+            PieceType::Pawn { just_lunged: false }
+        } else {
+            self.piece
+        }
+    }
+}
+
+/// Return the piece `color` gets by dropping `piece` on `square`. A pawn
+/// dropped on its starting rank may still move two squares, but any other
+/// dropped piece counts as having moved, so a dropped rook can't castle.
+fn dropped_piece(
+    dimensions: Dimensions,
+    color: Color,
+    piece: PieceType,
+    square: BoardCoord,
+) -> Piece {
+    let start_rank = dimensions.back_rank(color) + color.direction();
+    let mut tile = Tile::new(color, piece);
+    match piece {
+        PieceType::Pawn { .. } if square.1 == start_rank => (),
+        _ => tile.set_moved(true),
+    }
+    tile.0.unwrap()
+}
+
+/// The pieces a player has captured and may drop back onto the board, in
+/// variants such as Crazyhouse. Kings can't be captured, so every other kind
+/// of piece (including fairy pieces) may be in a pocket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Pocket([u8; 5 + Fairy::COUNT]);
+
+impl Pocket {
+    /// The kinds of piece which may be in a pocket, most valuable first.
+    pub const PIECES: [PieceType; 5 + Fairy::COUNT] = [
+        PieceType::Fairy(Fairy::Amazon),
+        PieceType::Queen,
+        PieceType::Fairy(Fairy::Chancellor),
+        PieceType::Fairy(Fairy::Archbishop),
+        PieceType::Rook,
+        PieceType::Bishop,
+        PieceType::Knight,
+        PieceType::Fairy(Fairy::Camel),
+        PieceType::Fairy(Fairy::Grasshopper),
+        PieceType::Pawn { just_lunged: false },
+    ];
+
+    /// Return how many of `piece` are in the pocket.
+    pub fn count(&self, piece: PieceType) -> u8 {
+        match piece {
+            PieceType::King => 0,
+            piece => self.0[Pocket::index(piece)],
+        }
+    }
+
+    /// Add one `piece` to the pocket. This panics if `piece` is a king.
+    pub fn add(&mut self, piece: PieceType) {
+        assert!(piece != PieceType::King, "Can't put a king in a pocket");
+        self.0[Pocket::index(piece)] += 1;
+    }
+
+    /// Remove one `piece` from the pocket. This panics if there isn't one.
+    pub fn remove(&mut self, piece: PieceType) {
+        assert!(self.count(piece) > 0, "Expected {:?} in the pocket", piece);
+        self.0[Pocket::index(piece)] -= 1;
+    }
+
+    /// The index of the count of `piece`. Each fairy piece has its own count,
+    /// after those of the standard pieces.
+    fn index(piece: PieceType) -> usize {
+        match piece {
+            PieceType::Fairy(fairy) => KINGS + fairy as usize,
+            piece => kind_index(piece),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|&count| count == 0)
+    }
+
+    /// Return each kind of piece in the pocket and how many there are, most
+    /// valuable first.
+    pub fn pieces(&self) -> impl Iterator<Item = (PieceType, u8)> + '_ {
+        Pocket::PIECES
+            .iter()
+            .map(move |&piece| (piece, self.count(piece)))
+            .filter(|&(_, count)| count > 0)
+    }
+}
+
+/// The pieces blown up by a capture in Atomic chess, other than the capturing
+/// piece itself, which is always blown up. See `Board::explode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Explosion {
+    /// The square the capture was made on.
+    pub center: BoardCoord,
+    pieces: [Option<(BoardCoord, Piece)>; 8],
+}
+
+impl Explosion {
+    /// Return each piece next to `center` which was blown up, and where it
+    /// was.
+    pub fn pieces(&self) -> impl Iterator<Item = (BoardCoord, Piece)> + '_ {
+        self.pieces.iter().flatten().copied()
+    }
 }
 
 /// The available player colors.
@@ -1441,7 +3498,7 @@ pub enum Color {
 impl Color {
     /// Returns 1 if White, -1 if Black. This is used to indicate the direction
     /// that pieces move in (particularly the Pawn)
-    fn direction(self) -> i8 {
+    pub(crate) fn direction(self) -> i8 {
         match self {
             Color::White => 1,
             Color::Black => -1,
@@ -1475,6 +3532,8 @@ impl fmt::Display for Color {
 /// An enum that describes the six possible pieces
 /// `Pawn` has a `bool` associated with that is true if the piece has just
 /// lunged (moved two spaces) on the previous turn.S
+/// `Fairy` is one of the pieces in `fairy.rs`, which don't appear in standard
+/// chess.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum PieceType {
     Pawn { just_lunged: bool },
@@ -1483,6 +3542,7 @@ pub enum PieceType {
     Rook,
     Queen,
     King,
+    Fairy(Fairy),
 }
 
 impl PieceType {
@@ -1495,6 +3555,24 @@ impl PieceType {
             Rook => ROOK_STR,
             Queen => QUEEN_STR,
             King => KING_STR,
+            Fairy(fairy) => fairy.as_str(),
+        }
+    }
+
+    /// The material value of the piece, in centipawns.
+    pub fn value(self) -> i32 {
+        use PieceType::*;
+        // Values here are also taken from https://www.chessprogramming.org/Simplified_Evaluation_Function
+        // It seems that this causes the bot to value the bishops a bit more than
+        // the knights.
+        match self {
+            Pawn { .. } => 100,
+            Knight => 320,
+            Bishop => 330,
+            Rook => 500,
+            Queen => 900,
+            King => 20000,
+            Fairy(fairy) => fairy.value(),
         }
     }
 }
@@ -1509,6 +3587,7 @@ impl fmt::Display for PieceType {
             Rook => write!(f, "R"),
             Queen => write!(f, "Q"),
             King => write!(f, "K"),
+            Fairy(fairy) => write!(f, "{}", fairy.letter()),
         }
     }
 }
@@ -1573,6 +3652,16 @@ mod tests {
         assert_eq!(board, expected);
     }
 
+    #[test]
+    fn test_get_off_the_grid() {
+        let board = Board::from_string_vec(vec!["WP"]);
+        assert!(board.get(BoardCoord(0, 0)).0.is_some());
+        assert_eq!(*board.get(BoardCoord(-1, 0)), Tile::blank());
+        assert_eq!(*board.get(BoardCoord(0, -1)), Tile::blank());
+        assert_eq!(*board.get(BoardCoord(16, 0)), Tile::blank());
+        assert_eq!(*board.get(BoardCoord(0, 8)), Tile::blank());
+    }
+
     // MOVEMENT
 
     #[test]
@@ -1953,6 +4042,121 @@ mod tests {
         assert!(board.can_castle(Color::Black, BoardSide::Kingside).is_err());
     }
 
+    #[test]
+    fn test_chess960_start_positions() {
+        assert_eq!(Board::chess960(518).unwrap(), Board::default());
+        assert!(Board::chess960(960).is_err());
+
+        let first_rank = |index| {
+            let board = Board::chess960(index).unwrap();
+            (0..8)
+                .map(|file| board.get(BoardCoord(file, 0)).0.unwrap().piece.to_string())
+                .collect::<String>()
+        };
+        assert_eq!(first_rank(0), "BBQNNRKR");
+        assert_eq!(first_rank(959), "RKRNNQBB");
+
+        let mut seen = std::collections::HashSet::new();
+        for index in 0..960 {
+            let board = Board::chess960(index).unwrap();
+            assert!(seen.insert(first_rank(index)));
+            let king = board.get_king(Color::White).unwrap();
+            assert!(board.has_castling_right(Color::White, BoardSide::Queenside));
+            assert!(board.has_castling_right(Color::White, BoardSide::Kingside));
+            assert_eq!(board.get_king(Color::Black), Some(BoardCoord(king.0, 7)));
+        }
+    }
+
+    #[test]
+    fn test_chess960_castle_without_moving_king() {
+        let mut state = BoardState::from_fen("1r4kr/8/8/8/8/8/8/1R4KR w KQkq - 0 1").unwrap();
+        // The king is already on the g file, so it takes the rook to castle.
+        let castle = state
+            .board
+            .castle_move(Color::White, BoardSide::Kingside)
+            .unwrap();
+        assert_eq!(castle.end, BoardCoord(7, 0));
+        assert!(castle.is_castle());
+        assert!(state
+            .get_move_list(BoardCoord(6, 0))
+            .contains(&BoardCoord(7, 0)));
+        // Moving the king one square is still a normal move
+        let king_move = Move::new(&state.board, BoardCoord(6, 0), BoardCoord(5, 0), None);
+        assert!(!king_move.is_castle());
+
+        let before = state.board.clone();
+        state.take_turn(castle);
+        assert!(state
+            .get(BoardCoord(6, 0))
+            .is(Color::White, PieceType::King));
+        assert!(state
+            .get(BoardCoord(5, 0))
+            .is(Color::White, PieceType::Rook));
+        assert!(state.get(BoardCoord(7, 0)).0.is_none());
+        state.undo();
+        assert_eq!(state.board, before);
+        assert_eq!(state.redo().unwrap().as_move(), castle);
+    }
+
+    #[test]
+    fn test_chess960_castle_onto_rook() {
+        // White's king castles over its rook, and Black's king castles onto
+        // the square its rook starts on.
+        let mut state = BoardState::from_fen("4k1r1/5p2/8/8/8/8/6P1/2KR4 w Dg - 0 1").unwrap();
+        let castle = state.parse_uci_move("c1d1").unwrap();
+        assert!(castle.is_castle());
+        state.take_turn(castle);
+        assert!(state
+            .get(BoardCoord(6, 0))
+            .is(Color::White, PieceType::King));
+        assert!(state
+            .get(BoardCoord(5, 0))
+            .is(Color::White, PieceType::Rook));
+
+        let castle = state.parse_uci_move("e8g8").unwrap();
+        assert!(castle.is_castle());
+        state.take_turn(castle);
+        assert!(state
+            .get(BoardCoord(6, 7))
+            .is(Color::Black, PieceType::King));
+        assert!(state
+            .get(BoardCoord(5, 7))
+            .is(Color::Black, PieceType::Rook));
+        assert_eq!(state.board.castling_rights(), 0);
+    }
+
+    #[test]
+    fn test_chess960_castle_attacked_through_rook() {
+        // After castling, the king on c1 would be attacked by the rook on a1,
+        // which the castling rook is blocking.
+        let state = BoardState::from_fen("4k3/8/8/8/8/8/8/rR1K4 w B - 0 1").unwrap();
+        assert!(state
+            .board
+            .can_castle(Color::White, BoardSide::Queenside)
+            .is_err());
+        let state = BoardState::from_fen("4k3/8/8/8/8/8/8/nR1K4 w B - 0 1").unwrap();
+        assert!(state
+            .board
+            .can_castle(Color::White, BoardSide::Queenside)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_king_takes_rook_castle() {
+        let mut state = BoardState::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        let castle = state.parse_uci_move("e1h1").unwrap();
+        assert!(castle.is_castle());
+        state.take_turn(castle);
+        assert!(state
+            .get(BoardCoord(6, 0))
+            .is(Color::White, PieceType::King));
+        assert!(state
+            .get(BoardCoord(5, 0))
+            .is(Color::White, PieceType::Rook));
+        // Castling is recorded the standard way
+        assert_eq!(state.history()[0].as_move().end, BoardCoord(6, 0));
+    }
+
     // EN PASSANT TESTS
     #[test]
     fn test_en_passant() {
@@ -2033,6 +4237,623 @@ mod tests {
             .is_err());
     }
 
+    // UNDO/REDO TESTS
+    #[test]
+    fn test_undo_castle() {
+        let board = vec![
+            "BR .. .. .. BK .. .. BR",
+            ".. .. .. .. .. .. .. ..",
+            ".. .. .. .. .. .. .. ..",
+            ".. .. .. .. .. .. .. ..",
+            ".. .. .. .. .. .. .. ..",
+            ".. .. .. .. .. .. .. ..",
+            ".. .. .. .. .. .. .. ..",
+            "WR .. .. .. WK .. .. WR",
+        ];
+        let board = Board::from_string_vec(board);
+        let mut state = BoardState::new(board.clone());
+        state.take_turn(Move::new(
+            &state.board,
+            BoardCoord(4, 0),
+            BoardCoord(2, 0),
+            None,
+        ));
+        assert!(state
+            .get(BoardCoord(3, 0))
+            .is(Color::White, PieceType::Rook));
+
+        state.undo();
+        assert_eq!(state.board, board);
+        assert_eq!(state.current_player, Color::White);
+        assert!(state
+            .board
+            .can_castle(Color::White, BoardSide::Queenside)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_undo_en_passant() {
+        let board = vec![
+            ".. .. .. .. BK .. .. ..",
+            ".. .. .. .. .. BP .. ..",
+            ".. .. .. .. .. .. .. ..",
+            ".. .. .. .. WP .. .. ..",
+            ".. .. .. .. .. .. .. ..",
+            ".. .. .. .. .. .. .. ..",
+            ".. .. .. .. .. .. .. ..",
+            ".. .. .. .. WK .. .. ..",
+        ];
+        let mut state = BoardState::new_with_player(Board::from_string_vec(board), Color::Black);
+        state.take_turn(Move::new(
+            &state.board,
+            BoardCoord(5, 6),
+            BoardCoord(5, 4),
+            None,
+        ));
+        let before_capture = state.board.clone();
+
+        state.take_turn(Move::new(
+            &state.board,
+            BoardCoord(4, 4),
+            BoardCoord(5, 5),
+            None,
+        ));
+        assert!(state.get(BoardCoord(5, 4)).0.is_none());
+
+        let entry = state.undo().unwrap();
+        assert_eq!(
+            entry.move_type,
+            MoveTypeCoords::EnPassant {
+                start: BoardCoord(4, 4),
+                end: BoardCoord(5, 5),
+                captured_pawn: BoardCoord(5, 4),
+            }
+        );
+        assert_eq!(state.board, before_capture);
+        assert!(state
+            .get_move_list(BoardCoord(4, 4))
+            .contains(&BoardCoord(5, 5)));
+    }
+
+    #[test]
+    fn test_undo_promotion() {
+        let board = vec![
+            ".. .. .. BN .. .. .. ..",
+            ".. .. WP .. .. .. .. ..",
+            ".. .. .. .. .. .. .. ..",
+            ".. .. .. .. .. .. .. BK",
+            ".. .. .. .. .. .. .. ..",
+            ".. .. .. .. .. .. .. ..",
+            ".. .. .. .. .. .. .. ..",
+            ".. .. .. .. WK .. .. ..",
+        ];
+        let board = Board::from_string_vec(board);
+        let mut state = BoardState::new(board.clone());
+        state.take_turn(Move::new(
+            &state.board,
+            BoardCoord(2, 6),
+            BoardCoord(3, 7),
+            Some(PieceType::Queen),
+        ));
+        assert_eq!(state.current_player, Color::Black);
+
+        let entry = state.undo().unwrap();
+        assert_eq!(entry.promotion, Some(PieceType::Queen));
+        assert_eq!(entry.captured, Tile::new(Color::Black, PieceType::Knight).0);
+        assert_eq!(state.board, board);
+        assert_eq!(state.current_player, Color::White);
+
+        state.redo();
+        assert!(state
+            .get(BoardCoord(3, 7))
+            .is(Color::White, PieceType::Queen));
+        assert_eq!(state.current_player, Color::Black);
+        assert!(state.redo().is_none());
+    }
+
+    #[test]
+    fn test_undo_redo_random_games() {
+        use rand::seq::SliceRandom;
+        use rand::SeedableRng;
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        for _ in 0..10 {
+            let mut state = BoardState::new(Board::default());
+            let mut positions = vec![];
+            for _ in 0..100 {
+                if state.game_over() {
+                    break;
+                }
+                positions.push(state.clone());
+                let moves = state.board.get_all_moves(state.current_player);
+                state.take_turn(*moves.choose(&mut rng).unwrap());
+            }
+            let final_state = state.clone();
+
+            for expected in positions.iter().rev() {
+                state.undo().unwrap();
+                assert_same_state(&state, expected);
+            }
+            assert!(state.undo().is_none());
+
+            while state.redo().is_some() {}
+            assert_same_state(&state, &final_state);
+        }
+    }
+
+    // CHECKS AND PINS
+    #[test]
+    fn test_pinned_piece() {
+        let board = vec![
+            ".. .. .. .. BR .. .. BK",
+            ".. .. .. .. .. .. .. ..",
+            ".. .. .. .. .. .. .. ..",
+            "BB .. .. .. .. .. .. ..",
+            ".. .. .. .. WR .. .. ..",
+            ".. .. .. .. .. .. .. ..",
+            ".. .. .. WB .. .. .. ..",
+            ".. .. .. .. WK .. .. ..",
+        ];
+        let board = Board::from_string_vec(board);
+        // Both pieces may only move along the line they are pinned on
+        let mut moves = board.get_move_list(BoardCoord(4, 3), Color::White);
+        moves.sort_by_key(|coord| coord.1);
+        let expected: Vec<BoardCoord> = (1..8)
+            .filter(|&rank| rank != 3)
+            .map(|rank| BoardCoord(4, rank))
+            .collect();
+        assert_eq!(moves, expected);
+
+        let mut moves = board.get_move_list(BoardCoord(3, 1), Color::White);
+        moves.sort_by_key(|coord| coord.1);
+        assert_eq!(
+            moves,
+            vec![BoardCoord(2, 2), BoardCoord(1, 3), BoardCoord(0, 4)]
+        );
+    }
+
+    #[test]
+    fn test_check_must_be_answered() {
+        let board = vec![
+            ".. .. .. .. BR .. .. BK",
+            ".. .. .. .. .. .. .. ..",
+            ".. .. .. .. .. .. .. ..",
+            ".. .. .. .. .. .. .. ..",
+            ".. .. .. .. .. .. .. ..",
+            ".. .. WN .. .. .. .. ..",
+            ".. .. .. .. .. .. .. ..",
+            "WR .. .. .. WK .. .. ..",
+        ];
+        let board = Board::from_string_vec(board);
+        assert!(board.is_in_check(Color::White));
+        // The knight may block on e2 or e4, the rook can't help, and the king
+        // can't stay on the e-file.
+        let moves = board.get_all_moves(Color::White);
+        let knight_moves: Vec<_> = moves
+            .iter()
+            .filter(|mv| mv.start == BoardCoord(2, 2))
+            .map(|mv| mv.end)
+            .collect();
+        assert_eq!(knight_moves, vec![BoardCoord(4, 1), BoardCoord(4, 3)]);
+        assert!(moves.iter().all(|mv| mv.start != BoardCoord(0, 0)));
+        assert!(moves
+            .iter()
+            .all(|mv| mv.end.0 != 4 || mv.start != BoardCoord(4, 0)));
+    }
+
+    #[test]
+    fn test_en_passant_discovered_check() {
+        // Capturing en passant would remove both pawns from the rank, leaving
+        // the king in check from the rook.
+        let state = BoardState::from_fen("8/8/8/K2pP2r/8/8/8/7k w - d6 0 1").unwrap();
+        let en_passant = Move::new(&state.board, BoardCoord(4, 4), BoardCoord(3, 5), None);
+        assert_eq!(
+            state.check_turn(en_passant),
+            Err(MoveError::LeavesKingInCheck {
+                king: BoardCoord(0, 4),
+                by: BoardCoord(7, 4),
+            })
+        );
+        assert!(!state
+            .get_move_list(BoardCoord(4, 4))
+            .contains(&BoardCoord(3, 5)));
+    }
+
+    #[test]
+    fn test_move_errors() {
+        use MoveError::*;
+        let error = |fen, uci| {
+            BoardState::from_fen(fen)
+                .unwrap()
+                .parse_uci_move(uci)
+                .unwrap_err()
+        };
+        let coord = |s: &str| s.parse::<BoardCoord>().unwrap();
+
+        let pinned = "4r1k1/8/8/8/8/8/4B3/4K3 w - - 0 1";
+        assert_eq!(
+            error(pinned, "a3a4"),
+            NoPieceAtSource {
+                square: coord("a3")
+            }
+        );
+        assert_eq!(
+            error(pinned, "g8g7"),
+            NotYourPiece {
+                square: coord("g8")
+            }
+        );
+        assert_eq!(
+            error(pinned, "e2e4"),
+            InvalidDestination {
+                start: coord("e2"),
+                end: coord("e4")
+            }
+        );
+        assert_eq!(
+            error(pinned, "e2d3"),
+            Pinned {
+                square: coord("e2"),
+                by: coord("e8")
+            }
+        );
+        assert_eq!(
+            error("4r1k1/8/8/8/8/8/3B4/4K3 w - - 0 1", "d2c3"),
+            LeavesKingInCheck {
+                king: coord("e1"),
+                by: coord("e8")
+            }
+        );
+        assert!(matches!(error(pinned, "e2"), InvalidNotation(_)));
+
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/R3K1NR w KQ - 0 1", "e1g1"),
+            CastleBlocked {
+                square: coord("g1")
+            }
+        );
+        assert_eq!(
+            error("4kr2/8/8/8/8/8/8/R3K2R w KQ - 0 1", "e1g1"),
+            CastleThroughCheck {
+                square: coord("f1")
+            }
+        );
+        assert_eq!(
+            error("4r1k1/8/8/8/8/8/8/R3K2R w KQ - 0 1", "e1g1"),
+            CastleOutOfCheck { king: coord("e1") }
+        );
+
+        let promotion = "4k3/P7/8/8/8/8/8/4K3 w - - 0 1";
+        assert_eq!(
+            error(promotion, "a7a8"),
+            PromotionRequired {
+                square: coord("a8")
+            }
+        );
+        assert_eq!(
+            error(promotion, "e1e2q"),
+            UnexpectedPromotion {
+                square: coord("e2")
+            }
+        );
+        let state = BoardState::from_fen(promotion).unwrap();
+        let mv = Move::new(
+            &state.board,
+            coord("a7"),
+            coord("a8"),
+            Some(PieceType::King),
+        );
+        assert_eq!(
+            state.check_turn(mv),
+            Err(InvalidPromotionPiece {
+                piece: PieceType::King
+            })
+        );
+
+        assert_eq!(
+            error(pinned, "e2d3").to_string(),
+            "The piece on e2 is pinned by e8"
+        );
+    }
+
+    #[test]
+    fn test_bitboard_moves_random_games() {
+        assert_bitboard_moves_random_games(Board::default());
+    }
+
+    #[test]
+    fn test_fairy_pins() {
+        // The Chancellor pins the knight along the file like a rook would
+        let state = BoardState::from_fen("4k3/8/8/8/4c3/8/4N3/4K3 w - - 0 1").unwrap();
+        let masks = state.board.move_masks(Color::White);
+        assert!(!masks.hoppers);
+        assert_eq!(masks.pinned, bitboard::bit(BoardCoord(4, 1)));
+        assert!(state.get_move_list(BoardCoord(4, 1)).is_empty());
+
+        // A Grasshopper pinned by a rook may not hop past it to e8
+        let state = BoardState::from_fen("3k4/4r3/8/8/8/4G3/8/4K3 w - - 0 1").unwrap();
+        assert!(state.get_move_list(BoardCoord(4, 2)).is_empty());
+    }
+
+    #[test]
+    fn test_fairy_moves_random_games() {
+        // Every fairy piece, on a Capablanca board
+        let fen = "rmagqklcnr/pppppppppp/10/10/10/10/PPPPPPPPPP/RNAGQKLCMR w KQkq - 0 1";
+        assert_bitboard_moves_random_games(BoardState::from_fen(fen).unwrap().board);
+    }
+
+    fn assert_bitboard_moves_random_games(board: Board) {
+        use rand::seq::SliceRandom;
+        use rand::SeedableRng;
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+        for _ in 0..20 {
+            let mut state = BoardState::new(board.clone());
+            for _ in 0..100 {
+                if state.game_over() {
+                    break;
+                }
+                // The bitboard moves should match the moves found by walking
+                // over the board and trying each move.
+                let player = state.current_player;
+                let masks = state.board.move_masks(player);
+                for start in bitboard::squares(state.board.colors[player as usize]) {
+                    let mut expected = MoveList::reserved();
+                    get_move_list_full(&state.board, player, bitboard::coord(start), &mut expected);
+                    let expected = expected.0.iter().map(|&end| bitboard::bit(end)).sum();
+                    assert_eq!(
+                        state.board.legal_targets(player, start, &masks),
+                        expected,
+                        "{}",
+                        state.board
+                    );
+                }
+
+                let moves = state.board.get_all_moves(state.current_player);
+                state.take_turn(*moves.choose(&mut rng).unwrap());
+            }
+        }
+    }
+
+    // DRAW TESTS
+    #[test]
+    fn test_repetition() {
+        let mut state = BoardState::new(Board::default());
+        let shuffle = [
+            (BoardCoord(6, 0), BoardCoord(5, 2)),
+            (BoardCoord(6, 7), BoardCoord(5, 5)),
+            (BoardCoord(5, 2), BoardCoord(6, 0)),
+            (BoardCoord(5, 5), BoardCoord(6, 7)),
+        ];
+        for _ in 0..2 {
+            assert_eq!(state.claimable_draw(), None);
+            for &(start, end) in &shuffle {
+                state.take_turn(Move::new(&state.board, start, end, None));
+            }
+        }
+        assert_eq!(state.repetition_count(), 3);
+        assert_eq!(
+            state.claimable_draw(),
+            Some(CheckmateState::ThreefoldRepetition)
+        );
+        assert!(!state.game_over());
+
+        for _ in 0..2 {
+            for &(start, end) in &shuffle {
+                state.take_turn(Move::new(&state.board, start, end, None));
+            }
+        }
+        assert_eq!(state.checkmate, CheckmateState::FivefoldRepetition);
+        assert!(state.game_over());
+
+        state.undo();
+        assert!(!state.game_over());
+        assert!(state.claim_draw());
+        assert_eq!(state.checkmate, CheckmateState::ThreefoldRepetition);
+        assert!(state.game_over());
+    }
+
+    #[test]
+    fn test_repetition_after_double_push() {
+        // The position after 1. e4 repeats after 3. Ng1 and 5. Ng1, even
+        // though only the first time could a pawn have just lunged.
+        let mut state = BoardState::new(Board::default());
+        for san in &["e4", "Nf6", "Nf3", "Ng8", "Ng1", "Nf6", "Nf3", "Ng8", "Ng1"] {
+            let mv = state.parse_san(san).unwrap();
+            state.take_turn(mv);
+        }
+        assert_eq!(state.repetition_count(), 3);
+        assert_eq!(
+            state.claimable_draw(),
+            Some(CheckmateState::ThreefoldRepetition)
+        );
+    }
+
+    #[test]
+    fn test_fifty_move_rule() {
+        let mut state = BoardState::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 99 80").unwrap();
+        assert_eq!(state.claimable_draw(), None);
+        state.take_turn(Move::new(
+            &state.board,
+            BoardCoord(0, 0),
+            BoardCoord(0, 1),
+            None,
+        ));
+        assert_eq!(state.claimable_draw(), Some(CheckmateState::FiftyMoveRule));
+
+        let mut state = BoardState::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 149 80").unwrap();
+        state.take_turn(Move::new(
+            &state.board,
+            BoardCoord(0, 0),
+            BoardCoord(0, 1),
+            None,
+        ));
+        assert_eq!(state.checkmate, CheckmateState::SeventyFiveMoveRule);
+
+        // Checkmate on the 75th move is still checkmate
+        let mut state = BoardState::from_fen("4k3/R7/8/8/8/8/8/1R2K3 w - - 149 80").unwrap();
+        state.take_turn(Move::new(
+            &state.board,
+            BoardCoord(1, 0),
+            BoardCoord(1, 7),
+            None,
+        ));
+        assert_eq!(state.checkmate, CheckmateState::Checkmate);
+
+        // A position set up after 75 moves is already drawn
+        let state = BoardState::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 150 100").unwrap();
+        assert_eq!(state.checkmate, CheckmateState::SeventyFiveMoveRule);
+        assert!(state.game_over());
+    }
+
+    #[test]
+    fn test_dimensions() {
+        assert!(Dimensions::new(17, 8).is_err());
+        assert!(Dimensions::new(8, 9).is_err());
+        assert!(Dimensions::new(8, 1).is_err());
+
+        let dimensions = Dimensions::new(10, 8).unwrap();
+        assert!(dimensions.contains(BoardCoord(9, 7)));
+        assert!(!dimensions.contains(BoardCoord(10, 0)));
+        assert_eq!(dimensions.back_rank(Color::Black), 7);
+        let board = Board::with_dimensions(dimensions);
+        assert_eq!(board.area().count_ones(), 80);
+        assert_eq!(Board::blank().dimensions(), Dimensions::STANDARD);
+    }
+
+    #[test]
+    fn test_board_coord_names() {
+        assert_eq!(BoardCoord(4, 3).to_string(), "e4");
+        assert_eq!("a1".parse(), Ok(BoardCoord(0, 0)));
+        assert_eq!("h8".parse(), Ok(BoardCoord(7, 7)));
+        // Wider boards have files past h.
+        assert_eq!("j1".parse(), Ok(BoardCoord(9, 0)));
+        assert!("q1".parse::<BoardCoord>().is_err());
+        assert!("a9".parse::<BoardCoord>().is_err());
+        assert!("e".parse::<BoardCoord>().is_err());
+    }
+
+    #[test]
+    fn test_uci_moves() {
+        let mv: Move = "e7e8q".parse().unwrap();
+        assert_eq!(
+            mv,
+            Move {
+                start: BoardCoord(4, 6),
+                end: BoardCoord(4, 7),
+                promotion: Some(PieceType::Queen),
+                drop: None,
+                flags: MoveFlags::NONE,
+            }
+        );
+        assert_eq!(mv.to_string(), "e7e8q");
+        let board = Board::default();
+        let double_push = Move::new(&board, BoardCoord(4, 1), BoardCoord(4, 3), None);
+        assert_eq!(double_push.to_string(), "e2e4");
+        assert!(double_push.is_double_push());
+        assert!("e7e8k".parse::<Move>().is_err());
+        assert!("e2e".parse::<Move>().is_err());
+
+        let state = BoardState::new(Board::default());
+        assert_eq!(state.parse_uci_move("e2e4"), Ok(double_push));
+        assert!(state.parse_uci_move("e2e5").is_err());
+        assert!(state.parse_uci_move("e7e5").is_err());
+        assert!(state.parse_uci_move("e2e4q").is_err());
+
+        let state = BoardState::from_fen("r3k2r/1P6/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        assert!(state.parse_uci_move("e1g1").unwrap().is_castle());
+        assert!(state.parse_uci_move("b7b8").is_err());
+        assert!(state.parse_uci_move("b7b8k").is_err());
+        assert!(state.parse_uci_move("b7b8n").unwrap().is_promotion());
+    }
+
+    #[test]
+    fn test_promotion_moves() {
+        let board = vec![
+            ".. BN .. .. BK .. .. ..",
+            "WP .. .. .. .. .. .. ..",
+            ".. .. .. .. .. .. .. ..",
+            ".. .. .. .. .. .. .. ..",
+            ".. .. .. .. .. .. .. ..",
+            ".. .. .. .. .. .. .. ..",
+            ".. .. .. .. .. .. .. ..",
+            ".. .. .. .. WK .. .. ..",
+        ];
+        let state = BoardState::new(Board::from_string_vec(board));
+        let pawn_moves: Vec<Move> = state
+            .board
+            .get_all_moves(Color::White)
+            .into_iter()
+            .filter(|mv| mv.start == BoardCoord(0, 6))
+            .collect();
+        // Both a8 and b8 with all four promotion pieces
+        assert_eq!(pawn_moves.len(), 8);
+        assert!(pawn_moves.iter().all(|mv| mv.is_promotion()));
+        assert_eq!(pawn_moves.iter().filter(|mv| mv.is_capture()).count(), 4);
+
+        // The promotion happens in the same turn as the move
+        let mut state = state;
+        let underpromotion = Move::new(
+            &state.board,
+            BoardCoord(0, 6),
+            BoardCoord(1, 7),
+            Some(PieceType::Knight),
+        );
+        state.take_turn(underpromotion);
+        assert!(state
+            .get(BoardCoord(1, 7))
+            .is(Color::White, PieceType::Knight));
+        assert_eq!(state.current_player, Color::Black);
+        assert_eq!(state.checkmate, CheckmateState::InsuffientMaterial);
+    }
+
+    #[test]
+    fn test_insufficient_material() {
+        let dead = |fen: &str| {
+            BoardState::from_fen(fen).unwrap().checkmate == CheckmateState::InsuffientMaterial
+        };
+        assert!(dead("4k3/8/8/8/8/8/8/4K3 w - - 0 1"));
+        assert!(dead("4k3/8/8/8/8/8/8/2B1K3 w - - 0 1"));
+        assert!(dead("4k3/8/8/8/8/8/8/1N2K3 w - - 0 1"));
+        // Bishops which are all on the same color of square
+        assert!(dead("4kb2/8/8/8/8/4B3/8/2B1K3 w - - 0 1"));
+        assert!(!dead("2b1k3/8/8/8/8/8/8/2B1K3 w - - 0 1"));
+        // Mates are possible with help from the opponent's pieces
+        assert!(!dead("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1"));
+        assert!(!dead("4k3/8/8/n7/8/8/8/2B1K3 w - - 0 1"));
+        assert!(!dead("4k3/8/8/n7/8/8/8/1N2K3 w - - 0 1"));
+
+        // Locked pawns which the kings can't get to
+        assert!(dead("8/8/4k3/p1p1p1p1/P1P1P1P1/4K3/8/8 w - - 0 1"));
+        assert!(!dead("8/8/4k3/p1p1p3/P1P1P1P1/4K3/8/8 w - - 0 1"));
+        assert!(!dead("8/8/4k3/p3p3/P3P3/4K3/8/8 w - - 0 1"));
+
+        // A knight can win on time against a pawn, but a lone king can't win
+        // against a rook
+        let board = BoardState::from_fen("4k3/p7/8/8/8/8/8/1N2K3 w - - 0 1")
+            .unwrap()
+            .board;
+        assert!(!board.insufficient_to_win(Color::White));
+        let board = BoardState::from_fen("r3k3/8/8/8/8/8/8/4K3 w - - 0 1")
+            .unwrap()
+            .board;
+        assert!(board.insufficient_to_win(Color::White));
+        assert!(!board.insufficient_to_win(Color::Black));
+        let board = BoardState::from_fen("4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1")
+            .unwrap()
+            .board;
+        assert!(board.insufficient_to_win(Color::White));
+    }
+
+    fn assert_same_state(actual: &BoardState, expected: &BoardState) {
+        assert_eq!(actual.board, expected.board);
+        assert_eq!(actual.current_player, expected.current_player);
+        assert_eq!(actual.checkmate, expected.checkmate);
+        assert_eq!(actual.halfmove_clock, expected.halfmove_clock);
+        assert_eq!(actual.fullmove_number, expected.fullmove_number);
+        assert_eq!(actual.history(), expected.history());
+    }
+
     fn assert_valid_movement(board: Vec<&str>, coord: (i8, i8), expected: Vec<&str>) {
         let board = Board::from_string_vec(board);
         assert_valid_movement_board(board, coord, expected);
@@ -2056,6 +4877,11 @@ mod tests {
             move_list_counts.insert(ele);
         }
 
+        // The bitboard move generation should agree
+        let targets = board.pseudo_legal_targets(bitboard::square(coord));
+        let bitboard_counts: HashSet<_> = bitboard::squares(targets).map(bitboard::coord).collect();
+        assert_eq!(move_list_counts, bitboard_counts);
+
         let mut expected_counts = HashSet::new();
         for ele in expected.0 {
             expected_counts.insert(ele);
@@ -0,0 +1,416 @@
+//! Fairy pieces, which move in ways that the pieces of standard chess don't,
+//! such as the Archbishop and Chancellor of Capablanca chess. How each piece
+//! moves is written in Betza notation, where a piece is made up of "atoms"
+//! naming a single leap (such as "N" for the knight's leap), so adding a piece
+//! only needs its movement, a letter, a glyph, and a material value. See
+//! https://www.chessvariants.com/piececlopedia.dir/betzanotation.html
+//! Only pieces which move the same way in every direction are supported. The
+//! supported notation is:
+//! - the atoms W, F, D, N, A, H, C, Z, and G, and the compounds K (WF), R (WW),
+//!   B (FF), and Q (RB)
+//! - riders, written by doubling an atom ("NN" is a Nightrider) or by giving
+//!   the most steps it may take ("W3")
+//! - the modifiers m (only moves), c (only captures), and g (hops over the
+//!   first piece in its way and lands just past it, as a Grasshopper does)
+
+use lazy_static::lazy_static;
+
+use crate::bitboard::{self, Bitboard};
+use crate::board::BoardCoord;
+
+/// The fairy pieces which may be played with. See `PieceType::Fairy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Fairy {
+    /// Moves as a Bishop or a Knight
+    Archbishop,
+    /// Moves as a Rook or a Knight
+    Chancellor,
+    /// Moves as a Queen or a Knight
+    Amazon,
+    /// Leaps three squares one way and one square the other, like a longer
+    /// Knight
+    Camel,
+    /// Moves along a Queen's lines, but must hop over the first piece in its
+    /// way and land on the square just past it
+    Grasshopper,
+}
+
+impl Fairy {
+    /// Every fairy piece, most valuable first.
+    pub const ALL: [Fairy; 5] = [
+        Fairy::Amazon,
+        Fairy::Chancellor,
+        Fairy::Archbishop,
+        Fairy::Camel,
+        Fairy::Grasshopper,
+    ];
+
+    /// The number of fairy pieces.
+    pub const COUNT: usize = Fairy::ALL.len();
+
+    /// How this piece moves, in Betza notation.
+    pub fn betza(self) -> &'static str {
+        match self {
+            Fairy::Archbishop => "BN",
+            Fairy::Chancellor => "RN",
+            Fairy::Amazon => "QN",
+            Fairy::Camel => "C",
+            Fairy::Grasshopper => "gQ",
+        }
+    }
+
+    /// The uppercase letter of this piece in FEN, SAN, and UCI moves.
+    pub fn letter(self) -> char {
+        match self {
+            Fairy::Archbishop => 'A',
+            Fairy::Chancellor => 'C',
+            Fairy::Amazon => 'M',
+            Fairy::Camel => 'L',
+            Fairy::Grasshopper => 'G',
+        }
+    }
+
+    /// Return the fairy piece with the uppercase letter `c`, if there is one.
+    pub fn from_letter(c: char) -> Option<Fairy> {
+        Fairy::ALL.iter().copied().find(|fairy| fairy.letter() == c)
+    }
+
+    /// The glyph the piece is drawn with.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Fairy::Archbishop => "\u{1FA53}",
+            Fairy::Chancellor => "\u{1FA52}",
+            Fairy::Amazon => "\u{1FA51}",
+            // A knight turned on its side, as the Camel is often drawn
+            Fairy::Camel => "\u{1FA13}",
+            // An upside down queen, as the Grasshopper is usually drawn
+            Fairy::Grasshopper => "\u{1FA25}",
+        }
+    }
+
+    /// The material value of the piece, in centipawns. See `PieceType::value`
+    /// for the other pieces.
+    pub fn value(self) -> i32 {
+        match self {
+            Fairy::Archbishop => 825,
+            Fairy::Chancellor => 875,
+            Fairy::Amazon => 1300,
+            Fairy::Camel => 250,
+            Fairy::Grasshopper => 200,
+        }
+    }
+
+    /// How this piece moves, parsed from `betza`.
+    pub fn movement(self) -> &'static Movement {
+        &MOVEMENTS[self as usize]
+    }
+}
+
+lazy_static! {
+    /// The movement of each fairy piece, indexed by `Fairy as usize`
+    static ref MOVEMENTS: Vec<Movement> = [
+        Fairy::Archbishop,
+        Fairy::Chancellor,
+        Fairy::Amazon,
+        Fairy::Camel,
+        Fairy::Grasshopper,
+    ]
+    .iter()
+    .map(|fairy| Movement::parse(fairy.betza()).expect("Expected valid Betza notation"))
+    .collect();
+}
+
+/// How a piece moves, as read from Betza notation. A piece may make a move of
+/// any one of its legs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movement {
+    pub legs: Vec<Leg>,
+}
+
+/// One kind of move a piece can make, such as the Knight leaps of an
+/// Archbishop.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Leg {
+    /// The offset of a single step in each direction the piece may go.
+    pub steps: Vec<BoardCoord>,
+    /// The most steps the piece may take in a line. This is Some(1) for a
+    /// leaper, and None for a rider which may go as far as it likes.
+    pub range: Option<u8>,
+    /// Whether the piece may move to an empty square.
+    pub moves: bool,
+    /// Whether the piece may capture.
+    pub captures: bool,
+    /// Whether the piece must hop over the first piece in its way, landing
+    /// on the square just past it.
+    pub hops: bool,
+}
+
+impl Movement {
+    /// Read a piece's movement from Betza notation, such as "BN" for the
+    /// Archbishop. See the module documentation for what is supported.
+    pub fn parse(betza: &str) -> Result<Movement, &'static str> {
+        let mut legs = vec![];
+        let (mut moves, mut captures, mut hops) = (false, false, false);
+        let mut chars = betza.chars().peekable();
+        while let Some(c) = chars.next() {
+            let (atoms, mut range): (&[char], _) = match c {
+                'm' => {
+                    moves = true;
+                    continue;
+                }
+                'c' => {
+                    captures = true;
+                    continue;
+                }
+                'g' => {
+                    hops = true;
+                    continue;
+                }
+                'f' | 'b' | 'l' | 'r' | 's' | 'v' => {
+                    return Err("Directional modifiers are not supported")
+                }
+                'K' => (&['W', 'F'], Some(1)),
+                'R' => (&['W'], None),
+                'B' => (&['F'], None),
+                'Q' => (&['W', 'F'], None),
+                'W' | 'F' | 'D' | 'N' | 'A' | 'H' | 'C' | 'Z' | 'G' => (&[c][..], Some(1)),
+                _ => return Err("Expected a piece letter such as N or a modifier such as m"),
+            };
+
+            if let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+                chars.next();
+                range = Some(digit as u8).filter(|&range| range > 0);
+            } else if range == Some(1) && chars.peek() == Some(&c) {
+                chars.next();
+                range = None;
+            }
+
+            // A piece without m or c may both move and capture
+            let (leg_moves, leg_captures) = if moves || captures {
+                (moves, captures)
+            } else {
+                (true, true)
+            };
+            for &atom in atoms {
+                legs.push(Leg {
+                    steps: symmetric_steps(atom),
+                    range,
+                    moves: leg_moves,
+                    captures: leg_captures,
+                    hops,
+                });
+            }
+            moves = false;
+            captures = false;
+            hops = false;
+        }
+
+        if moves || captures || hops {
+            return Err("Expected a piece letter after the modifiers");
+        }
+        if legs.is_empty() {
+            return Err("Expected at least one piece letter");
+        }
+        Ok(Movement { legs })
+    }
+
+    /// Return the empty squares the piece on `start` may move to, if the
+    /// pieces on the board are `occupied`.
+    pub fn moves(&self, start: usize, occupied: Bitboard) -> Bitboard {
+        self.legs
+            .iter()
+            .filter(|leg| leg.moves)
+            .fold(bitboard::EMPTY, |moves, leg| {
+                moves | leg.reach(start, occupied)
+            })
+            & !occupied
+    }
+
+    /// Return the squares the piece on `start` attacks, meaning the squares
+    /// it could capture a piece on, if the pieces on the board are `occupied`.
+    pub fn attacks(&self, start: usize, occupied: Bitboard) -> Bitboard {
+        self.legs
+            .iter()
+            .filter(|leg| leg.captures)
+            .fold(bitboard::EMPTY, |attacks, leg| {
+                attacks | leg.reach(start, occupied)
+            })
+    }
+
+    /// Returns true if this piece can only be blocked along ranks, files, and
+    /// diagonals, as the orthodox pieces can. Its checks can then be blocked
+    /// on the squares between it and the king, and it can only pin a piece
+    /// which is the only one on that line.
+    pub fn blocked_on_lines(&self) -> bool {
+        self.legs.iter().all(Leg::blocked_on_lines)
+    }
+}
+
+impl Leg {
+    /// See `Movement::blocked_on_lines`. A leap can't be blocked, but one
+    /// which jumps over squares of a line (such as the Dabbaba's) would seem
+    /// to be blockable on them. A rider which steps more than one square at a
+    /// time passes by squares off of its line, and a hopper needs a piece in
+    /// its way rather than being blocked by it.
+    fn blocked_on_lines(&self) -> bool {
+        self.steps.iter().all(|&BoardCoord(x, y)| {
+            let (x, y) = (x.abs(), y.abs());
+            let single_step = x.max(y) == 1;
+            let on_line = x == 0 || y == 0 || x == y;
+            match self.range {
+                Some(1) => single_step || !on_line,
+                _ => single_step,
+            }
+        }) && !self.hops
+    }
+    /// Return the squares this leg may end on from `start`, whether they are
+    /// occupied or not. A line ends on the first piece in the way, or just
+    /// past it for a piece which hops. Like the attack tables in `bitboard`,
+    /// this may include squares past the edges of the board.
+    fn reach(&self, start: usize, occupied: Bitboard) -> Bitboard {
+        let range = self.range.unwrap_or(bitboard::FILES as u8);
+        let mut reach = bitboard::EMPTY;
+        for &step in &self.steps {
+            let mut coord = bitboard::coord(start);
+            for _ in 0..range {
+                coord = BoardCoord(coord.0 + step.0, coord.1 + step.1);
+                if !on_grid(coord) {
+                    break;
+                }
+                if occupied & bitboard::bit(coord) == bitboard::EMPTY {
+                    if !self.hops {
+                        reach |= bitboard::bit(coord);
+                    }
+                    continue;
+                }
+
+                if !self.hops {
+                    reach |= bitboard::bit(coord);
+                } else {
+                    let landing = BoardCoord(coord.0 + step.0, coord.1 + step.1);
+                    if on_grid(landing) {
+                        reach |= bitboard::bit(landing);
+                    }
+                }
+                break;
+            }
+        }
+        reach
+    }
+}
+
+/// Return the offset of a single leap of `atom`, in one direction.
+fn atom_offset(atom: char) -> BoardCoord {
+    match atom {
+        'W' => BoardCoord(1, 0),
+        'F' => BoardCoord(1, 1),
+        'D' => BoardCoord(2, 0),
+        'N' => BoardCoord(2, 1),
+        'A' => BoardCoord(2, 2),
+        'H' => BoardCoord(3, 0),
+        'C' => BoardCoord(3, 1),
+        'Z' => BoardCoord(3, 2),
+        'G' => BoardCoord(3, 3),
+        _ => unreachable!("Expected an atom, got {}", atom),
+    }
+}
+
+/// Return the offsets of a leap of `atom` in every direction.
+fn symmetric_steps(atom: char) -> Vec<BoardCoord> {
+    let BoardCoord(x, y) = atom_offset(atom);
+    let mut steps = vec![];
+    for &(x, y) in &[(x, y), (y, x)] {
+        for &(sign_x, sign_y) in &[(1, 1), (1, -1), (-1, 1), (-1, -1)] {
+            let step = BoardCoord(x * sign_x, y * sign_y);
+            if !steps.contains(&step) {
+                steps.push(step);
+            }
+        }
+    }
+    steps
+}
+
+/// Returns true if `coord` is on the grid bitboards are laid out on.
+fn on_grid(BoardCoord(file, rank): BoardCoord) -> bool {
+    0 <= file && file < bitboard::FILES as i8 && 0 <= rank && rank < bitboard::RANKS as i8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let knight = Movement::parse("N").unwrap();
+        assert_eq!(knight.legs.len(), 1);
+        assert_eq!(knight.legs[0].steps.len(), 8);
+        assert_eq!(knight.legs[0].range, Some(1));
+
+        let queen = Movement::parse("Q").unwrap();
+        assert_eq!(queen.legs.len(), 2);
+        assert!(queen.legs.iter().all(|leg| leg.range.is_none()));
+        assert_eq!(Movement::parse("WW").unwrap().legs[0].range, None);
+        assert_eq!(Movement::parse("R2").unwrap().legs[0].range, Some(2));
+
+        let pawnlike = Movement::parse("mWcF").unwrap();
+        assert!(pawnlike.legs[0].moves && !pawnlike.legs[0].captures);
+        assert!(!pawnlike.legs[1].moves && pawnlike.legs[1].captures);
+
+        assert!(Movement::parse("").is_err());
+        assert!(Movement::parse("X").is_err());
+        assert!(Movement::parse("fN").is_err());
+        assert!(Movement::parse("Nm").is_err());
+        for &fairy in Fairy::ALL.iter() {
+            assert_eq!(Fairy::from_letter(fairy.letter()), Some(fairy));
+            assert!(Movement::parse(fairy.betza()).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_movement() {
+        let square = |file, rank| bitboard::square(BoardCoord(file, rank));
+        let bits = |coords: &[(i8, i8)]| {
+            coords.iter().fold(bitboard::EMPTY, |bits, &(file, rank)| {
+                bits | bitboard::bit(BoardCoord(file, rank))
+            })
+        };
+
+        // The Archbishop is a Bishop and a Knight in one
+        let archbishop = Fairy::Archbishop.movement();
+        let d4 = square(3, 3);
+        assert_eq!(
+            archbishop.attacks(d4, bitboard::EMPTY),
+            bitboard::bishop_attacks(d4, bitboard::EMPTY) | bitboard::knight_attacks(d4)
+        );
+
+        // A Grasshopper on a1 hops over the piece on c3 to d4. It can't go up
+        // the a-file or along the first rank, since there's nothing to hop.
+        let grasshopper = Fairy::Grasshopper.movement();
+        let occupied = bits(&[(2, 2), (0, 0)]);
+        assert_eq!(grasshopper.attacks(square(0, 0), occupied), bits(&[(3, 3)]));
+        // It lands on the square just past the first piece, even if there is
+        // a piece on it.
+        let occupied = bits(&[(0, 0), (0, 3), (0, 4)]);
+        assert_eq!(grasshopper.attacks(square(0, 0), occupied), bits(&[(0, 4)]));
+        assert_eq!(grasshopper.moves(square(0, 0), occupied), bitboard::EMPTY);
+
+        // A Camel in the corner only has two leaps.
+        let camel = Fairy::Camel.movement();
+        assert_eq!(
+            camel.moves(square(0, 0), bitboard::EMPTY),
+            bits(&[(1, 3), (3, 1)])
+        );
+
+        // Pieces which only move or only capture
+        let pawnlike = Movement::parse("mWcF").unwrap();
+        let occupied = bits(&[(3, 4), (4, 4)]);
+        assert_eq!(
+            pawnlike.moves(d4, occupied),
+            bits(&[(2, 3), (4, 3), (3, 2)])
+        );
+        assert_eq!(
+            pawnlike.attacks(d4, occupied),
+            bits(&[(2, 2), (4, 2), (2, 4), (4, 4)])
+        );
+    }
+}
@@ -0,0 +1,919 @@
+use std::fmt;
+use std::sync::Arc;
+
+use crate::bitboard;
+use crate::board::*;
+use crate::fairy;
+use crate::variant::{Capablanca, Crazyhouse, LosAlamos, ThreeCheck};
+
+/// The FEN string for the standard starting position.
+pub const STARTING_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+/// The six space separated fields of a FEN string, in order, and the checks
+/// field of a Three-check FEN string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FenField {
+    Placement,
+    ActiveColor,
+    Castling,
+    EnPassant,
+    HalfmoveClock,
+    FullmoveNumber,
+    Checks,
+}
+
+impl fmt::Display for FenField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            FenField::Placement => "piece placement",
+            FenField::ActiveColor => "active color",
+            FenField::Castling => "castling availability",
+            FenField::EnPassant => "en passant target",
+            FenField::HalfmoveClock => "halfmove clock",
+            FenField::FullmoveNumber => "fullmove number",
+            FenField::Checks => "checks",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// An error produced while parsing a FEN string. `field` is the field that
+/// could not be parsed and `reason` describes what was wrong with it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FenError {
+    pub field: FenField,
+    pub reason: String,
+}
+
+impl FenError {
+    fn new(field: FenField, reason: impl Into<String>) -> FenError {
+        FenError {
+            field,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid {} field: {}", self.field, self.reason)
+    }
+}
+
+impl std::error::Error for FenError {}
+
+impl BoardState {
+    /// Create a board state from a FEN string. All six fields are read. The
+    /// castling rights are stored by marking the relevant kings and rooks as
+    /// unmoved (every other king and rook is marked as moved) and the en passant
+    /// target is stored by setting `just_lunged` on the pawn that just moved.
+    /// The halfmove clock and fullmove number may be left off, in which case
+    /// they default to 0 and 1.
+    /// Crazyhouse positions are also accepted. These list the pieces in each
+    /// player's pocket after the piece placement, either in brackets (such as
+    /// "RNBQKBNR[Nn]") or as a ninth rank (such as "RNBQKBNR/Nn"), and mark
+    /// promoted pieces with a "~" (such as "Q~"). A board state read from such
+    /// a FEN string is played as Crazyhouse.
+    /// Three-check positions are also accepted. These give the number of checks
+    /// each player has left to give, either as an extra field after the en
+    /// passant target (such as "3+2" if Black has given a check) or the number
+    /// of checks each player has given at the end (such as "+0+1"). A board
+    /// state read from such a FEN string is played as Three-check.
+    /// Boards of other sizes are also accepted, such as "10" for an empty rank
+    /// of a 10x8 board. A 6x6 board is played as Los Alamos chess, and a 10x8
+    /// board as Capablanca chess. Fairy pieces are written with their letter
+    /// (see `Fairy::letter`), such as "A" for a white Archbishop.
+    pub fn from_fen(fen: &str) -> Result<BoardState, FenError> {
+        use FenField::*;
+        let mut fields = fen.split_whitespace();
+        let mut next_field = |field| {
+            fields
+                .next()
+                .ok_or_else(|| FenError::new(field, "field is missing"))
+        };
+
+        let (placement, pockets) = split_pockets(next_field(Placement)?)?;
+        let mut board = parse_placement(placement)?;
+
+        let current_player = match next_field(ActiveColor)? {
+            "w" => Color::White,
+            "b" => Color::Black,
+            other => {
+                return Err(FenError::new(
+                    ActiveColor,
+                    format!("expected \"w\" or \"b\", got \"{}\"", other),
+                ))
+            }
+        };
+
+        parse_castling(&mut board, next_field(Castling)?)?;
+        parse_en_passant(&mut board, current_player, next_field(EnPassant)?)?;
+
+        let mut rest: Vec<&str> = fields.collect();
+        let mut checks = None;
+        if rest.first().map_or(false, |field| {
+            field.contains('+') && !field.starts_with('+')
+        }) {
+            checks = Some(parse_checks(rest.remove(0), false)?);
+        }
+        if rest.last().map_or(false, |field| field.starts_with('+')) {
+            if checks.is_some() {
+                return Err(FenError::new(Checks, "the checks are given twice"));
+            }
+            checks = Some(parse_checks(rest.pop().unwrap(), true)?);
+        }
+        let mut fields = rest.into_iter();
+
+        let halfmove_clock = match fields.next() {
+            None => 0,
+            Some(clock) => parse_number(HalfmoveClock, clock)?,
+        };
+        let fullmove_number = match fields.next() {
+            None => 1,
+            Some(number) => parse_number(FullmoveNumber, number)?,
+        };
+        if fullmove_number == 0 {
+            return Err(FenError::new(FullmoveNumber, "must be at least 1"));
+        }
+
+        if let Some(extra) = fields.next() {
+            return Err(FenError::new(
+                FullmoveNumber,
+                format!("unexpected trailing text \"{}\"", extra),
+            ));
+        }
+
+        let mut state = match (pockets, checks) {
+            (None, None) if board.dimensions() == LosAlamos::DIMENSIONS => {
+                BoardState::new_with_rules(board, current_player, Arc::new(LosAlamos))
+            }
+            (None, None) if board.dimensions() == Capablanca::DIMENSIONS => {
+                BoardState::new_with_rules(board, current_player, Arc::new(Capablanca))
+            }
+            (None, None) => BoardState::new_with_player(board, current_player),
+            (Some([white, black]), None) => {
+                let mut state =
+                    BoardState::new_with_rules(board, current_player, Arc::new(Crazyhouse));
+                state.set_pocket(Color::White, white);
+                state.set_pocket(Color::Black, black);
+                state
+            }
+            (None, Some([white, black])) => {
+                let mut state =
+                    BoardState::new_with_rules(board, current_player, Arc::new(ThreeCheck));
+                state.set_checks_given(Color::White, white);
+                state.set_checks_given(Color::Black, black);
+                state
+            }
+            (Some(_), Some(_)) => {
+                return Err(FenError::new(
+                    Checks,
+                    "a position can't have both pockets and checks",
+                ))
+            }
+        };
+        state.set_clocks(halfmove_clock, fullmove_number);
+        Ok(state)
+    }
+
+    /// Return the FEN string describing this board state. Castling rights are
+    /// derived from the `has_moved` flags of the kings and rooks, and the en
+    /// passant target is derived from the `just_lunged` flag.
+    /// Castling rights are written as in X-FEN, which is the same as standard
+    /// FEN unless there is a Chess960 castling right using a rook which is not
+    /// the outermost rook on its side. That right is written as the file of
+    /// the rook instead of "K" or "Q".
+    /// If the variant uses pockets, the pockets are written in brackets after
+    /// the piece placement and promoted pieces are marked with a "~", as in
+    /// "RNBQKBNR[Nn]". If the variant counts checks, the number of checks each
+    /// player has left to give is written after the en passant target, as in
+    /// "3+3".
+    pub fn to_fen(&self) -> String {
+        self.write_fen(false)
+    }
+
+    /// Return the Shredder-FEN string describing this board state. This is the
+    /// same as `to_fen`, but every castling right is written as the file of
+    /// the rook, such as "HAha" for the standard starting position.
+    pub fn to_shredder_fen(&self) -> String {
+        self.write_fen(true)
+    }
+
+    fn write_fen(&self, shredder: bool) -> String {
+        let pockets = self.variant().uses_pockets();
+        let mut fen = String::new();
+        let dimensions = self.board.dimensions();
+        for rank in (0..dimensions.ranks).rev() {
+            let mut empty = 0;
+            for file in 0..dimensions.files {
+                match self.get(BoardCoord(file, rank)).0 {
+                    None => empty += 1,
+                    Some(piece) => {
+                        if empty > 0 {
+                            fen.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        fen.push(piece_to_char(piece));
+                        if pockets && piece.is_promoted() {
+                            fen.push('~');
+                        }
+                    }
+                }
+            }
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+            }
+            if rank > 0 {
+                fen.push('/');
+            }
+        }
+
+        if pockets {
+            fen.push('[');
+            for &color in &[Color::White, Color::Black] {
+                for (piece, count) in self.pocket(color).pieces() {
+                    let c = piece_to_char(Tile::new(color, piece).0.unwrap());
+                    for _ in 0..count {
+                        fen.push(c);
+                    }
+                }
+            }
+            fen.push(']');
+        }
+
+        fen.push(' ');
+        fen.push(match self.current_player {
+            Color::White => 'w',
+            Color::Black => 'b',
+        });
+
+        fen.push(' ');
+        let mut castling = String::new();
+        for &(color, side, c) in CASTLING_RIGHTS.iter() {
+            let rook = match self.board.castling_rook(color, side) {
+                Some(rook) => rook,
+                None => continue,
+            };
+            let king = self.board.get_king(color).expect("Expected a king");
+            if !shredder && outermost_rook(&self.board, color, king, side) == Some(rook) {
+                castling.push(c);
+            } else {
+                castling.push(file_to_char(rook.0, color));
+            }
+        }
+        if castling.is_empty() {
+            castling.push('-');
+        }
+        fen.push_str(&castling);
+
+        fen.push(' ');
+        match self.board.just_lunged_pawn() {
+            Some(pawn) => {
+                // The target is the square the pawn passed over.
+                let color = self.get(pawn).0.expect("Expected a pawn").color;
+                let behind = BoardCoord(pawn.0, pawn.1 - color.direction());
+                fen.push_str(&behind.to_string())
+            }
+            None => fen.push('-'),
+        }
+
+        if self.variant().counts_checks() {
+            let remaining =
+                |color| ThreeCheck::CHECKS_TO_WIN.saturating_sub(self.checks_given(color));
+            fen.push_str(&format!(
+                " {}+{}",
+                remaining(Color::White),
+                remaining(Color::Black)
+            ));
+        }
+
+        fen.push_str(&format!(
+            " {} {}",
+            self.halfmove_clock, self.fullmove_number
+        ));
+        fen
+    }
+}
+
+/// The castling rights in the order they appear in a FEN string.
+const CASTLING_RIGHTS: [(Color, BoardSide, char); 4] = [
+    (Color::White, BoardSide::Kingside, 'K'),
+    (Color::White, BoardSide::Queenside, 'Q'),
+    (Color::Black, BoardSide::Kingside, 'k'),
+    (Color::Black, BoardSide::Queenside, 'q'),
+];
+
+/// Parse the checks field of a Three-check FEN string into the number of
+/// checks White and Black have given. If `given` is true, the field is in the
+/// form "+0+1", giving the number of checks each player has given. Otherwise,
+/// it is in the form "3+2", giving the number of checks each player has left.
+fn parse_checks(field: &str, given: bool) -> Result<[u8; 2], FenError> {
+    let invalid = || {
+        FenError::new(
+            FenField::Checks,
+            format!(
+                "expected a count of checks such as \"3+3\", got \"{}\"",
+                field
+            ),
+        )
+    };
+    let counts = if given { &field[1..] } else { field };
+    let (white, black) = counts.split_once('+').ok_or_else(invalid)?;
+    let parse = |count: &str| match count.parse::<u8>() {
+        Ok(count) if count <= ThreeCheck::CHECKS_TO_WIN => Ok(count),
+        _ => Err(invalid()),
+    };
+    let (white, black) = (parse(white)?, parse(black)?);
+    if given {
+        Ok([white, black])
+    } else {
+        Ok([
+            ThreeCheck::CHECKS_TO_WIN - white,
+            ThreeCheck::CHECKS_TO_WIN - black,
+        ])
+    }
+}
+
+/// Split the piece placement field into the placement of the pieces on the
+/// board and the pockets of White and Black, if there are any. See `from_fen`.
+fn split_pockets(placement: &str) -> Result<(&str, Option<[Pocket; 2]>), FenError> {
+    use FenField::Placement;
+    let (placement, pockets_str) = if let Some(rest) = placement.strip_suffix(']') {
+        let open = rest
+            .find('[')
+            .ok_or_else(|| FenError::new(Placement, "unmatched ']' after the pockets"))?;
+        (&rest[..open], &rest[open + 1..])
+    } else if placement.matches('/').count() == 8 {
+        let slash = placement.rfind('/').unwrap();
+        (&placement[..slash], &placement[slash + 1..])
+    } else {
+        return Ok((placement, None));
+    };
+
+    let mut pockets = [Pocket::default(); 2];
+    for c in pockets_str.chars() {
+        match char_to_piece(c) {
+            Some(piece) if piece.piece != PieceType::King => {
+                pockets[piece.color as usize].add(piece.piece)
+            }
+            _ => {
+                return Err(FenError::new(
+                    Placement,
+                    format!("invalid piece '{}' in pockets", c),
+                ))
+            }
+        }
+    }
+    Ok((placement, Some(pockets)))
+}
+
+fn parse_placement(placement: &str) -> Result<Board, FenError> {
+    use FenField::Placement;
+    let rank_strs: Vec<&str> = placement.split('/').collect();
+
+    // FEN lists the ranks from the last rank down to the first. Each rank is
+    // read into a list of its squares first, so that the size of the board is
+    // known before any pieces are placed.
+    let mut ranks = vec![];
+    for (i, rank_str) in rank_strs.iter().enumerate() {
+        let rank = (rank_strs.len() - 1 - i) as i8;
+        let mut squares: Vec<Tile> = vec![];
+        // The number of empty squares being read, which may have more than one
+        // digit on wide boards, such as the "10" of an empty rank in
+        // Capablanca chess.
+        let mut empty = 0;
+        // The index of the piece just read, which a "~" may follow
+        let mut last_piece = None;
+        let mut chars = rank_str.chars().peekable();
+        while let Some(c) = chars.next() {
+            if let Some(digit) = c.to_digit(10) {
+                empty = 10 * empty + digit;
+                let more_digits = matches!(chars.peek(), Some(c) if c.is_ascii_digit());
+                // Checked before reading any more digits, so that a long run
+                // of them can't overflow.
+                if empty > bitboard::FILES as u32 || (empty == 0 && !more_digits) {
+                    return Err(FenError::new(
+                        Placement,
+                        format!(
+                            "invalid empty square count '{}' on rank {}",
+                            empty,
+                            rank + 1
+                        ),
+                    ));
+                }
+                if more_digits {
+                    continue;
+                }
+                squares.extend((0..empty).map(|_| Tile(None)));
+                empty = 0;
+                last_piece = None;
+            } else if c == '~' {
+                // In Crazyhouse, a "~" marks the piece before it as promoted.
+                let index: usize = last_piece.take().ok_or_else(|| {
+                    FenError::new(
+                        Placement,
+                        format!("'~' must follow a piece on rank {}", rank + 1),
+                    )
+                })?;
+                squares[index].set_promoted(true);
+            } else {
+                let piece = char_to_piece(c).ok_or_else(|| {
+                    FenError::new(
+                        Placement,
+                        format!("invalid piece '{}' on rank {}", c, rank + 1),
+                    )
+                })?;
+                last_piece = Some(squares.len());
+                squares.push(Tile::new(piece.color, piece.piece));
+            }
+            if squares.len() > bitboard::FILES {
+                return Err(FenError::new(
+                    Placement,
+                    format!(
+                        "rank {} has more than {} squares",
+                        rank + 1,
+                        bitboard::FILES
+                    ),
+                ));
+            }
+        }
+        ranks.push(squares);
+    }
+
+    let files = ranks[0].len();
+    if let Some(short) = ranks.iter().position(|squares| squares.len() != files) {
+        let rank = rank_strs.len() - short;
+        return Err(FenError::new(
+            Placement,
+            format!(
+                "rank {} has {} squares, but rank {} has {}",
+                rank,
+                ranks[short].len(),
+                rank_strs.len(),
+                files
+            ),
+        ));
+    }
+    let dimensions = Dimensions::new(files as i8, ranks.len() as i8)
+        .map_err(|err| FenError::new(Placement, err))?;
+
+    let mut board = Board::with_dimensions(dimensions);
+    for (i, squares) in ranks.into_iter().enumerate() {
+        let rank = dimensions.ranks - 1 - i as i8;
+        for (file, mut tile) in squares.into_iter().enumerate() {
+            // Only pawns on their starting rank may still double move.
+            if let Some(piece) = tile.0 {
+                let start_rank = match piece.color {
+                    Color::White => 1,
+                    Color::Black => dimensions.ranks - 2,
+                };
+                if piece.piece == (PieceType::Pawn { just_lunged: false }) && rank != start_rank {
+                    tile.set_moved(true);
+                }
+            }
+            *board.get_mut(BoardCoord(file as i8, rank)) = tile;
+        }
+    }
+
+    for &color in &[Color::White, Color::Black] {
+        let num_kings = board
+            .iter_pieces()
+            .filter(|&(_, piece)| piece.color == color && piece.piece == PieceType::King)
+            .count();
+        if num_kings != 1 {
+            return Err(FenError::new(
+                Placement,
+                format!("expected one {} king, found {}", color.as_str(), num_kings),
+            ));
+        }
+    }
+
+    Ok(board)
+}
+
+/// Apply the castling field to the board. Every king and rook starts off
+/// marked as moved, and then the pieces named by the castling rights are
+/// marked as unmoved. For Chess960, the Shredder-FEN and X-FEN forms are also
+/// accepted, where a castling right may be given as the file of the rook (such
+/// as "Hb"). "K" and "Q" then mean the outermost rook on that side of the king.
+fn parse_castling(board: &mut Board, castling: &str) -> Result<(), FenError> {
+    use FenField::Castling;
+    let castlers: Vec<BoardCoord> = board
+        .iter_pieces()
+        .filter(|(_, piece)| piece.piece == PieceType::King || piece.piece == PieceType::Rook)
+        .map(|(coord, _)| coord)
+        .collect();
+    for coord in castlers {
+        board.get_mut(coord).set_moved(true);
+    }
+
+    if castling == "-" {
+        return Ok(());
+    }
+
+    let mut seen = vec![];
+    for c in castling.chars() {
+        let color = if c.is_ascii_uppercase() {
+            Color::White
+        } else {
+            Color::Black
+        };
+        let files = board.dimensions().files;
+        let first_rank = board.dimensions().back_rank(color);
+        let invalid_right = || FenError::new(Castling, format!("invalid castling right '{}'", c));
+
+        let named_side = CASTLING_RIGHTS
+            .iter()
+            .find(|&&(_, _, right)| right == c)
+            .map(|&(_, side, _)| side);
+        let named_file = match c.to_ascii_lowercase() {
+            file @ 'a'..='p' => Some(file as i8 - 'a' as i8).filter(|&file| file < files),
+            _ => None,
+        };
+        let king = (0..files)
+            .map(|file| BoardCoord(file, first_rank))
+            .find(|&coord| board.get(coord).is(color, PieceType::King))
+            .ok_or_else(|| {
+                FenError::new(
+                    Castling,
+                    format!("castling right '{}' requires a king on the first rank", c),
+                )
+            })?;
+
+        let (side, rook) = if let Some(side) = named_side {
+            (side, outermost_rook(board, color, king, side))
+        } else if let Some(file) = named_file {
+            let rook = Some(BoardCoord(file, first_rank))
+                .filter(|&rook| board.get(rook).is(color, PieceType::Rook));
+            if file < king.0 {
+                (BoardSide::Queenside, rook)
+            } else if file > king.0 {
+                (BoardSide::Kingside, rook)
+            } else {
+                return Err(invalid_right());
+            }
+        } else {
+            return Err(invalid_right());
+        };
+        let rook = rook.ok_or_else(|| {
+            FenError::new(
+                Castling,
+                format!("castling right '{}' requires a rook on the first rank", c),
+            )
+        })?;
+
+        if seen.contains(&(color, side)) {
+            return Err(FenError::new(
+                Castling,
+                format!("castling right '{}' listed twice", c),
+            ));
+        }
+        seen.push((color, side));
+
+        board.get_mut(king).set_moved(false);
+        board.get_mut(rook).set_moved(false);
+    }
+    Ok(())
+}
+
+/// Apply the en passant field to the board by setting `just_lunged` on the
+/// pawn which just moved past `target`.
+fn parse_en_passant(board: &mut Board, player: Color, target: &str) -> Result<(), FenError> {
+    use FenField::EnPassant;
+    if target == "-" {
+        return Ok(());
+    }
+
+    let target = target
+        .parse::<BoardCoord>()
+        .map_err(|_| FenError::new(EnPassant, format!("invalid square \"{}\"", target)))?;
+
+    // The pawn that just lunged belongs to the player who is not to move.
+    let ranks = board.dimensions().ranks;
+    let (expected_rank, pawn_rank) = match player {
+        Color::White => (ranks - 3, ranks - 4),
+        Color::Black => (2, 3),
+    };
+    if !board.contains(target) {
+        return Err(FenError::new(
+            EnPassant,
+            format!("{} is not on the board", target),
+        ));
+    }
+    if target.1 != expected_rank {
+        return Err(FenError::new(
+            EnPassant,
+            format!(
+                "target must be on rank {} when {} is to move",
+                expected_rank + 1,
+                player.as_str()
+            ),
+        ));
+    }
+
+    let pawn_coord = BoardCoord(target.0, pawn_rank);
+    let start_coord = BoardCoord(target.0, target.1 + (target.1 - pawn_rank));
+    if board.get(target).0.is_some() || board.get(start_coord).0.is_some() {
+        return Err(FenError::new(
+            EnPassant,
+            "the squares the pawn lunged over must be empty",
+        ));
+    }
+
+    match &mut board.get_mut(pawn_coord).0 {
+        Some(Piece {
+            piece: PieceType::Pawn { just_lunged },
+            color,
+            ..
+        }) if *color == player.opposite() => *just_lunged = true,
+        _ => {
+            return Err(FenError::new(
+                EnPassant,
+                format!("no pawn to capture on {}", pawn_coord),
+            ))
+        }
+    }
+    Ok(())
+}
+
+fn parse_number(field: FenField, number: &str) -> Result<u32, FenError> {
+    number
+        .parse()
+        .map_err(|_| FenError::new(field, format!("\"{}\" is not a number", number)))
+}
+
+/// Return the outermost rook of `color` on `side` of `king`, which must be on
+/// the first rank. This is the rook that "K" or "Q" refers to.
+pub(crate) fn outermost_rook(
+    board: &Board,
+    color: Color,
+    king: BoardCoord,
+    side: BoardSide,
+) -> Option<BoardCoord> {
+    let files: Vec<i8> = match side {
+        BoardSide::Queenside => (0..king.0).collect(),
+        BoardSide::Kingside => (king.0 + 1..board.dimensions().files).rev().collect(),
+    };
+    files
+        .into_iter()
+        .map(|file| BoardCoord(file, king.1))
+        .find(|&coord| board.get(coord).is(color, PieceType::Rook))
+}
+
+/// Return the letter of `file`, which is uppercase for White and lowercase for
+/// Black. This is how Shredder-FEN and X-FEN write castling rights.
+fn file_to_char(file: i8, color: Color) -> char {
+    let c = (b'a' + file as u8) as char;
+    match color {
+        Color::White => c.to_ascii_uppercase(),
+        Color::Black => c,
+    }
+}
+
+fn piece_to_char(piece: Piece) -> char {
+    use PieceType::*;
+    let c = match piece.piece {
+        Pawn { .. } => 'p',
+        Knight => 'n',
+        Bishop => 'b',
+        Rook => 'r',
+        Queen => 'q',
+        King => 'k',
+        Fairy(fairy) => fairy.letter().to_ascii_lowercase(),
+    };
+    match piece.color {
+        Color::White => c.to_ascii_uppercase(),
+        Color::Black => c,
+    }
+}
+
+fn char_to_piece(c: char) -> Option<Piece> {
+    use PieceType::*;
+    let piece = match c.to_ascii_lowercase() {
+        'p' => Pawn { just_lunged: false },
+        'n' => Knight,
+        'b' => Bishop,
+        'r' => Rook,
+        'q' => Queen,
+        'k' => King,
+        c => Fairy(fairy::Fairy::from_letter(c.to_ascii_uppercase())?),
+    };
+    let color = if c.is_ascii_uppercase() {
+        Color::White
+    } else {
+        Color::Black
+    };
+    Tile::new(color, piece).0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+    #[test]
+    fn test_starting_position() {
+        let state = BoardState::from_fen(STARTING_FEN).unwrap();
+        assert_eq!(state.board, Board::default());
+        assert_eq!(state.current_player, Color::White);
+        assert_eq!(state.to_fen(), STARTING_FEN);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for &fen in &[
+            KIWIPETE,
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 b kq - 3 12",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1",
+            "r1b1k2r/ppp2ppp/2n5/3q4/1b6/5N2/PPP2PPP/RNBQKB1R[PPnp] w KQkq - 0 7",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 3+3 0 1",
+            "rnbqkb1r/pppp1ppp/5n2/4p2Q/4P3/8/PPPP1PPP/RNB1KBNR w KQkq - 3+1 2 3",
+        ] {
+            assert_eq!(BoardState::from_fen(fen).unwrap().to_fen(), fen);
+        }
+    }
+
+    #[test]
+    fn test_crazyhouse_pockets() {
+        let state = BoardState::from_fen("2Q~1k3/8/8/8/8/8/8/4K3[NPPq] b - - 0 30").unwrap();
+        assert_eq!(state.variant().name(), "Crazyhouse");
+        assert!(state.get(BoardCoord(2, 7)).0.unwrap().is_promoted());
+        assert_eq!(state.pocket(Color::White).count(PieceType::Knight), 1);
+        assert_eq!(
+            state
+                .pocket(Color::White)
+                .count(PieceType::Pawn { just_lunged: false }),
+            2
+        );
+        assert_eq!(state.pocket(Color::Black).count(PieceType::Queen), 1);
+        assert_eq!(state.to_fen(), "2Q~1k3/8/8/8/8/8/8/4K3[NPPq] b - - 0 30");
+
+        // The pockets may also be written as a ninth rank.
+        let ninth_rank = BoardState::from_fen("2Q~1k3/8/8/8/8/8/8/4K3/qPNP b - - 0 30").unwrap();
+        assert_eq!(ninth_rank.to_fen(), state.to_fen());
+        assert_eq!(ninth_rank.position_hash(), state.position_hash());
+    }
+
+    #[test]
+    fn test_three_check_checks() {
+        let state = BoardState::from_fen("4k3/8/8/8/8/8/8/4K2R b - - 2+3 0 1").unwrap();
+        assert_eq!(state.variant().name(), "Three-check");
+        assert_eq!(state.checks_given(Color::White), 1);
+        assert_eq!(state.checks_given(Color::Black), 0);
+
+        // The checks given may also be written at the end.
+        let given = BoardState::from_fen("4k3/8/8/8/8/8/8/4K2R b - - 0 1 +1+0").unwrap();
+        assert_eq!(given.to_fen(), state.to_fen());
+        assert_eq!(given.position_hash(), state.position_hash());
+        let none_given = BoardState::from_fen("4k3/8/8/8/8/8/8/4K2R b - - 0 1 +0+0").unwrap();
+        assert_ne!(none_given.position_hash(), state.position_hash());
+    }
+
+    #[test]
+    fn test_castling_rights() {
+        let state = BoardState::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w Kq - 0 1").unwrap();
+        assert!(!state.get(BoardCoord(4, 0)).0.unwrap().has_moved());
+        assert!(!state.get(BoardCoord(7, 0)).0.unwrap().has_moved());
+        assert!(state.get(BoardCoord(0, 0)).0.unwrap().has_moved());
+        assert!(state.get(BoardCoord(7, 7)).0.unwrap().has_moved());
+        assert!(!state.get(BoardCoord(0, 7)).0.unwrap().has_moved());
+        assert!(state
+            .get_move_list(BoardCoord(4, 0))
+            .contains(&BoardCoord(6, 0)));
+        assert!(!state
+            .get_move_list(BoardCoord(4, 0))
+            .contains(&BoardCoord(2, 0)));
+    }
+
+    #[test]
+    fn test_chess960_castling_rights() {
+        let shredder = "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9";
+        let x_fen = "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9";
+        let state = BoardState::from_fen(shredder).unwrap();
+        assert_eq!(state.board, BoardState::from_fen(x_fen).unwrap().board);
+        assert_eq!(state.to_fen(), x_fen);
+        assert_eq!(state.to_shredder_fen(), shredder);
+
+        let start = BoardState::new(Board::default());
+        assert_eq!(
+            start.to_shredder_fen(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1"
+        );
+
+        // The rook on the e file isn't the outermost rook, so X-FEN has to use
+        // the file of the rook.
+        let fen = "1k2r2r/8/8/8/8/8/8/1K2R2R w Ee - 0 1";
+        let state = BoardState::from_fen(fen).unwrap();
+        assert_eq!(state.to_fen(), fen);
+        assert!(state.get(BoardCoord(7, 0)).0.unwrap().has_moved());
+        assert_eq!(
+            state.board.castling_rook(Color::White, BoardSide::Kingside),
+            Some(BoardCoord(4, 0))
+        );
+    }
+
+    #[test]
+    fn test_en_passant() {
+        let fen = "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2";
+        let state = BoardState::from_fen(fen).unwrap();
+        assert_eq!(
+            state.get(BoardCoord(3, 4)).0.unwrap().piece,
+            PieceType::Pawn { just_lunged: true }
+        );
+        assert!(state
+            .get_move_list(BoardCoord(4, 4))
+            .contains(&BoardCoord(3, 5)));
+    }
+
+    #[test]
+    fn test_clocks_after_moves() {
+        let mut state = BoardState::from_fen(STARTING_FEN).unwrap();
+        state.take_turn(Move::new(
+            &state.board,
+            BoardCoord(4, 1),
+            BoardCoord(4, 3),
+            None,
+        ));
+        assert_eq!(
+            state.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+        state.take_turn(Move::new(
+            &state.board,
+            BoardCoord(6, 7),
+            BoardCoord(5, 5),
+            None,
+        ));
+        assert_eq!(
+            state.to_fen(),
+            "rnbqkb1r/pppppppp/5n2/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 1 2"
+        );
+        state.take_turn(Move::new(
+            &state.board,
+            BoardCoord(4, 0),
+            BoardCoord(4, 1),
+            None,
+        ));
+        assert_eq!(
+            state.to_fen(),
+            "rnbqkb1r/pppppppp/5n2/8/4P3/8/PPPPKPPP/RNBQ1BNR b kq - 2 2"
+        );
+    }
+
+    #[test]
+    fn test_board_sizes() {
+        // Capablanca chess is played on a 10x8 board.
+        let fen = "r3k4r/pppppppppp/10/10/4P5/10/PPPP1PPPPP/R3K4R b KQkq e3 0 1";
+        let state = BoardState::from_fen(fen).unwrap();
+        assert_eq!(state.board.dimensions(), Dimensions::new(10, 8).unwrap());
+        assert_eq!(state.variant().name(), "Capablanca");
+        assert_eq!(state.to_fen(), fen);
+        assert_eq!(
+            state.board.castling_rook(Color::White, BoardSide::Kingside),
+            Some(BoardCoord(9, 0))
+        );
+
+        let state = BoardState::from_fen(LosAlamos::STARTING_FEN).unwrap();
+        assert_eq!(state.board.dimensions(), LosAlamos::DIMENSIONS);
+        assert_eq!(state.variant().name(), "Los Alamos");
+        assert_eq!(state.to_fen(), LosAlamos::STARTING_FEN);
+
+        let field = |fen| BoardState::from_fen(fen).unwrap_err().field;
+        use FenField::*;
+        assert_eq!(field("4k3/8/8/8/8/8/8/4K4 w - - 0 1"), Placement);
+        assert_eq!(field("4k3/17/4K3 w - - 0 1"), Placement);
+        assert_eq!(field("k6K w - - 0 1"), Placement);
+        assert_eq!(field("2k3/6/6/6/6/2K3 w - e3 0 1"), EnPassant);
+    }
+
+    #[test]
+    fn test_errors() {
+        let field = |fen| BoardState::from_fen(fen).unwrap_err().field;
+        use FenField::*;
+        assert_eq!(field("8/8/8/8/8/8/8 w - - 0 1"), Placement);
+        assert_eq!(field("4k3/8/8/8/8/8/8/4K3x w - - 0 1"), Placement);
+        assert_eq!(field("4k3/8/8/8/8/8/8/8 w - - 0 1"), Placement);
+        assert_eq!(field("4k3/8/8/8/8/8/8/99999999999K w - - 0 1"), Placement);
+        assert_eq!(field("4k3/8/8/8/8/8/8/17 w - - 0 1"), Placement);
+        assert_eq!(field("4k3/8/8/8/8/8/8/4K3 x - - 0 1"), ActiveColor);
+        assert_eq!(field("4k3/8/8/8/8/8/8/4K3 w"), Castling);
+        assert_eq!(field("4k3/8/8/8/8/8/8/4K3 w K - 0 1"), Castling);
+        assert_eq!(field("4k3/8/8/8/8/8/8/R3K3 w KA - 0 1"), Castling);
+        assert_eq!(field("4k3/8/8/8/8/8/8/R3K3 w QA - 0 1"), Castling);
+        assert_eq!(field("4k3/8/8/8/8/8/8/R3K3 w E - 0 1"), Castling);
+        assert_eq!(field("4k3/8/8/8/8/8/8/4K3 w - e3 0 1"), EnPassant);
+        assert_eq!(field("4k3/8/8/8/8/8/8/4K3 w - e6 0 1"), EnPassant);
+        assert_eq!(field("4k3/8/8/8/8/8/8/4K3 w - - x 1"), HalfmoveClock);
+        assert_eq!(field("4k3/8/8/8/8/8/8/4K3 w - - 0 0"), FullmoveNumber);
+        assert_eq!(field("4k3/8/8/8/8/8/8/4K3[K] w - - 0 1"), Placement);
+        assert_eq!(field("4k3/8/8/8/8/8/8/4K3N] w - - 0 1"), Placement);
+        assert_eq!(field("4k3/8/8/8/8/8/8/~4K3[] w - - 0 1"), Placement);
+        assert_eq!(field("4k3/8/8/8/8/8/8/4K3 w - - 4+3 0 1"), Checks);
+        assert_eq!(field("4k3/8/8/8/8/8/8/4K3 w - - 3+3 0 1 +0+0"), Checks);
+        assert_eq!(field("4k3/8/8/8/8/8/8/4K3 w - - 0 1 +1"), Checks);
+        assert_eq!(field("4k3/8/8/8/8/8/8/4K3[] w - - 3+3 0 1"), Checks);
+    }
+}
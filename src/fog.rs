@@ -0,0 +1,149 @@
+//! What each player can see in variants which hide pieces, such as Fog of War.
+//! A player sees the squares their own pieces are on, every square one of
+//! their pieces could move to or capture on, and the squares directly in front
+//! of their pawns (so that they can tell why a pawn is blocked).
+//! `BoardState::fogged` gives a copy of the game with everything else removed,
+//! which is what the AI is given so that it doesn't see through the fog.
+
+use crate::bitboard::{self, Bitboard};
+use crate::board::*;
+
+impl Board {
+    /// Return the squares `color` can see. See the module documentation.
+    pub fn visible_squares(&self, color: Color) -> Bitboard {
+        let mut visible = bitboard::EMPTY;
+        for (coord, piece) in self.iter_pieces().filter(|(_, piece)| piece.color == color) {
+            visible |= bitboard::bit(coord);
+            if let PieceType::Pawn { .. } = piece.piece {
+                visible |= bitboard::pawn_attacks(color, bitboard::square(coord)) & self.area();
+                visible |= self.squares_ahead(coord, piece);
+            }
+        }
+
+        for mv in self.pseudo_legal_moves(color) {
+            visible |= bitboard::bit(mv.end);
+        }
+        visible
+    }
+
+    /// The square in front of the pawn on `coord`, and the one after it if
+    /// the pawn hasn't moved and the first is empty.
+    fn squares_ahead(&self, coord: BoardCoord, pawn: Piece) -> Bitboard {
+        let direction = pawn.color.direction();
+        let mut squares = bitboard::EMPTY;
+        let mut ahead = BoardCoord(coord.0, coord.1 + direction);
+        if self.contains(ahead) {
+            squares |= bitboard::bit(ahead);
+            if !pawn.has_moved() && self.get(ahead).0.is_none() {
+                ahead = BoardCoord(ahead.0, ahead.1 + direction);
+                if self.contains(ahead) {
+                    squares |= bitboard::bit(ahead);
+                }
+            }
+        }
+        squares
+    }
+}
+
+impl BoardState {
+    /// Return a copy of this game as `color` sees it, with the pieces of the
+    /// other player which `color` can't see removed. The copy has no history,
+    /// since that would show where the hidden pieces moved.
+    pub fn fogged(&self, color: Color) -> BoardState {
+        let mut board = self.board.clone();
+        let visible = self.board.visible_squares(color);
+        for (coord, piece) in self.board.iter_pieces() {
+            if piece.color != color && visible & bitboard::bit(coord) == bitboard::EMPTY {
+                board.get_mut(coord).0 = None;
+            }
+        }
+
+        let mut state =
+            BoardState::new_with_rules(board, self.current_player, self.shared_variant());
+        state.halfmove_clock = self.halfmove_clock;
+        state.fullmove_number = self.fullmove_number;
+        state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::STARTING_FEN;
+    use crate::variant::FogOfWar;
+    use std::sync::Arc;
+
+    fn fog_of_war(fen: &str) -> BoardState {
+        let state = BoardState::from_fen(fen).unwrap();
+        BoardState::new_with_rules(state.board, state.current_player, Arc::new(FogOfWar))
+    }
+
+    fn play(state: &mut BoardState, moves: &[&str]) {
+        for uci in moves {
+            let mv = state.parse_uci_move(uci).unwrap();
+            state.take_turn(mv);
+        }
+    }
+
+    #[test]
+    fn test_visible_squares() {
+        // At the start, each player sees their own half of the board.
+        let board = BoardState::from_fen(STARTING_FEN).unwrap().board;
+        let ranks = |ranks: std::ops::Range<i8>| {
+            ranks.fold(bitboard::EMPTY, |bits, rank| bits | bitboard::rank(rank)) & board.area()
+        };
+        assert_eq!(board.visible_squares(Color::White), ranks(0..4));
+        assert_eq!(board.visible_squares(Color::Black), ranks(4..8));
+
+        // The pawn on e4 is blocked by e5, which White sees even though the
+        // pawn can't move there. The rook sees up to and including d8.
+        let board = fog_of_war("3r3k/8/8/4p3/4P3/8/8/3RK3 w - - 0 1").board;
+        let visible = board.visible_squares(Color::White);
+        let sees = |coord| visible & bitboard::bit(coord) != bitboard::EMPTY;
+        assert!(sees(BoardCoord(4, 4)));
+        assert!(sees(BoardCoord(3, 7)));
+        assert!(!sees(BoardCoord(7, 7)));
+    }
+
+    #[test]
+    fn test_fogged() {
+        let state = fog_of_war("3r3k/8/8/4p3/4P3/8/8/3RK3 w - - 0 1");
+        let fogged = state.fogged(Color::White);
+        assert_eq!(fogged.to_fen(), "3r4/8/8/4p3/4P3/8/8/3RK3 w - - 0 1");
+        assert_eq!(fogged.variant().name(), "Fog of War");
+        // Black doesn't have a king that White can see, but may still move.
+        let mut fogged = fogged;
+        play(&mut fogged, &["d1d2"]);
+        assert!(!fogged.legal_moves().is_empty());
+
+        // The history isn't given away.
+        let mut state = BoardState::new_with_variant(Arc::new(FogOfWar));
+        play(&mut state, &["e2e4", "g8f6"]);
+        let fogged = state.fogged(Color::White);
+        assert!(fogged.history().is_empty());
+        assert_eq!(fogged.fullmove_number, 2);
+        assert!(fogged.get(BoardCoord(5, 5)).0.is_none());
+    }
+
+    #[test]
+    fn test_capturing_the_king_wins() {
+        // There is no check, so White may leave their king attacked, castle
+        // through check, or walk into check.
+        let mut state = fog_of_war("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        play(&mut state, &["a1a8"]);
+        assert_eq!(state.checkmate, CheckmateState::Normal);
+        assert!(state.parse_uci_move("e8c8").is_err());
+        assert!(state.parse_uci_move("e8g8").is_ok());
+        play(&mut state, &["e8d7", "h1h7", "d7d8"]);
+        play(&mut state, &["a8d8"]);
+        assert!(state.game_over());
+        assert_eq!(state.winner(), Some(Color::White));
+        assert_eq!(
+            state.checkmate,
+            CheckmateState::VariantEnd {
+                winner: Some(Color::White),
+                reason: "capturing the king",
+            }
+        );
+    }
+}
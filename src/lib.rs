@@ -1,12 +1,25 @@
-#![feature(clamp)]
-#![feature(option_expect_none)]
-#![feature(div_duration)]
-#![feature(try_blocks)]
-pub mod ai;
-pub mod board;
-pub mod color;
-pub mod ease;
-pub mod layout;
-pub mod particle;
-pub mod rect;
-pub mod ui;
+#![feature(clamp)]
+#![feature(option_expect_none)]
+#![feature(div_duration)]
+#![feature(try_blocks)]
+pub mod ai;
+pub mod attacks;
+pub mod bitboard;
+pub mod board;
+pub mod color;
+pub mod ease;
+pub mod fairy;
+pub mod fen;
+pub mod fog;
+pub mod layout;
+pub mod outcome;
+pub mod particle;
+pub mod perft;
+pub mod pgn;
+pub mod position;
+pub mod rect;
+pub mod san;
+pub mod tactics;
+pub mod ui;
+pub mod variant;
+pub mod zobrist;
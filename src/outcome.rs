@@ -0,0 +1,198 @@
+//! How a game ended. A `GameOutcome` is either produced from the position (such
+//! as by checkmate or a draw by repetition, see `BoardState::outcome`), or set
+//! by the players or whoever runs the game (such as by a resignation or by
+//! running out of time, see `BoardState::set_outcome`).
+use std::fmt;
+
+use crate::board::{CheckmateState, Color};
+
+/// Why a game ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Termination {
+    Checkmate,
+    Stalemate,
+    InsufficientMaterial,
+    ThreefoldRepetition,
+    FivefoldRepetition,
+    FiftyMoveRule,
+    SeventyFiveMoveRule,
+    /// The game ended by a rule of the variant being played. This describes
+    /// the rule, such as "giving three checks".
+    Variant(&'static str),
+    /// The loser resigned.
+    Resignation,
+    /// Both players agreed to a draw.
+    AgreedDraw,
+    /// The loser ran out of time.
+    Timeout,
+    /// A player ran out of time, but the game is drawn since their opponent
+    /// couldn't have checkmated them.
+    TimeoutVsInsufficientMaterial,
+    /// The game was stopped and decided by someone other than the players,
+    /// such as a tournament director or an engine match runner.
+    Adjudication,
+}
+
+impl Termination {
+    /// The value of the PGN Termination tag for this reason: "normal" if the
+    /// game ended by the rules (including resignations and agreed draws),
+    /// "time forfeit" if a player ran out of time, and "adjudication".
+    pub fn pgn_tag(&self) -> &'static str {
+        match self {
+            Termination::Timeout | Termination::TimeoutVsInsufficientMaterial => "time forfeit",
+            Termination::Adjudication => "adjudication",
+            _ => "normal",
+        }
+    }
+}
+
+impl fmt::Display for Termination {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Termination::*;
+        let reason = match self {
+            Checkmate => "checkmate",
+            Stalemate => "stalemate",
+            InsufficientMaterial => "insufficient material",
+            ThreefoldRepetition => "threefold repetition",
+            FivefoldRepetition => "fivefold repetition",
+            FiftyMoveRule => "the fifty-move rule",
+            SeventyFiveMoveRule => "the seventy-five-move rule",
+            Variant(reason) => reason,
+            Resignation => "resignation",
+            AgreedDraw => "agreement",
+            Timeout => "timeout",
+            TimeoutVsInsufficientMaterial => "timeout vs insufficient material",
+            Adjudication => "adjudication",
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+/// The result of a finished game: who won (None if the game is drawn), and
+/// why the game ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GameOutcome {
+    pub winner: Option<Color>,
+    pub termination: Termination,
+}
+
+impl GameOutcome {
+    /// Return the outcome of a game which is in `checkmate` state, or None if
+    /// the game isn't over. `winner` is the winner of the game according to
+    /// its variant, see `Variant::winner`.
+    pub fn from_checkmate(checkmate: CheckmateState, winner: Option<Color>) -> Option<GameOutcome> {
+        use Termination::*;
+        let termination = match checkmate {
+            CheckmateState::Normal | CheckmateState::Check => return None,
+            CheckmateState::Checkmate => Checkmate,
+            CheckmateState::Stalemate => Stalemate,
+            CheckmateState::InsuffientMaterial => InsufficientMaterial,
+            CheckmateState::ThreefoldRepetition => ThreefoldRepetition,
+            CheckmateState::FivefoldRepetition => FivefoldRepetition,
+            CheckmateState::FiftyMoveRule => FiftyMoveRule,
+            CheckmateState::SeventyFiveMoveRule => SeventyFiveMoveRule,
+            CheckmateState::VariantEnd { reason, .. } => Variant(reason),
+        };
+        Some(GameOutcome {
+            winner,
+            termination,
+        })
+    }
+
+    /// The PGN game termination marker: "1-0" or "0-1" if a player won, and
+    /// "1/2-1/2" if the game is drawn.
+    pub fn result(&self) -> &'static str {
+        match self.winner {
+            Some(Color::White) => "1-0",
+            Some(Color::Black) => "0-1",
+            None => "1/2-1/2",
+        }
+    }
+
+    /// The points `color` scores from this game, as in a tournament: 1 for a
+    /// win, 0.5 for a draw, and 0 for a loss.
+    pub fn score(&self, color: Color) -> f32 {
+        match self.winner {
+            Some(winner) if winner == color => 1.0,
+            Some(_) => 0.0,
+            None => 0.5,
+        }
+    }
+}
+
+impl fmt::Display for GameOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.winner {
+            Some(winner) => write!(f, "{} wins by {}", winner.as_str(), self.termination),
+            None => write!(f, "Draw by {}", self.termination),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{Board, BoardState};
+    use crate::variant::KingOfTheHill;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_resign_and_timeout() {
+        let mut state = BoardState::new(Board::default());
+        state.take_turn(state.parse_uci_move("e2e4").unwrap());
+        assert_eq!(state.outcome(), None);
+        state.resign(Color::Black);
+        assert!(state.game_over());
+        assert_eq!(state.winner(), Some(Color::White));
+        assert_eq!(state.result(), "1-0");
+        assert_eq!(
+            state.outcome().unwrap().to_string(),
+            "White wins by resignation"
+        );
+        // Taking back a move carries on the game
+        state.undo();
+        assert!(!state.game_over());
+
+        // Running out of time loses, unless the opponent can't checkmate
+        let mut state = BoardState::from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 0 1").unwrap();
+        state.time_out(Color::Black);
+        assert_eq!(state.winner(), Some(Color::White));
+        let mut state = BoardState::from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 0 1").unwrap();
+        state.time_out(Color::White);
+        assert_eq!(
+            state.outcome(),
+            Some(GameOutcome {
+                winner: None,
+                termination: Termination::TimeoutVsInsufficientMaterial,
+            })
+        );
+
+        // In King of the Hill, the lone king could still walk to the hill.
+        let board = state.board.clone();
+        let mut state = BoardState::new_with_rules(board, Color::White, Arc::new(KingOfTheHill));
+        state.time_out(Color::White);
+        assert_eq!(state.winner(), Some(Color::Black));
+        assert_eq!(state.outcome().unwrap().termination, Termination::Timeout);
+
+        // In Crazyhouse, the lone king could capture the queen and drop it.
+        let mut state = BoardState::from_fen("4k3/8/8/8/8/8/8/3QK3[] w - - 0 1").unwrap();
+        state.time_out(Color::White);
+        assert_eq!(state.winner(), Some(Color::Black));
+    }
+
+    #[test]
+    fn test_outcome_from_position() {
+        let state = BoardState::from_fen("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        let outcome = state.outcome().unwrap();
+        assert_eq!(outcome.winner, Some(Color::White));
+        assert_eq!(outcome.termination, Termination::Checkmate);
+        assert_eq!(outcome.score(Color::White), 1.0);
+        assert_eq!(outcome.score(Color::Black), 0.0);
+
+        let state = BoardState::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        let outcome = state.outcome().unwrap();
+        assert_eq!(outcome.to_string(), "Draw by stalemate");
+        assert_eq!(outcome.score(Color::Black), 0.5);
+        assert_eq!(outcome.result(), "1/2-1/2");
+    }
+}
@@ -0,0 +1,207 @@
+//! Move path enumeration ("perft") for checking the move generator. See
+//! https://www.chessprogramming.org/Perft
+//! `perft(depth)` counts the leaf nodes of the game tree `depth` plies deep.
+//! The counts for many positions are well known, so any difference from them
+//! means that the move generator is missing moves or generating illegal ones.
+//! `divide(depth)` splits the count up by the first move, which makes it easy
+//! to find the move that is wrong by comparing against another engine.
+
+use crate::board::*;
+
+impl BoardState {
+    /// Count the positions reachable from this one in exactly `depth` plies.
+    /// Every legal move is counted, even once the game is over by repetition
+    /// or the move rules, since perft only cares about how the pieces move.
+    pub fn perft(&self, depth: u32) -> u64 {
+        perft(&mut self.clone(), depth)
+    }
+
+    /// Like `perft`, but returns the count for each legal move of the
+    /// player-to-move separately, in the order the moves are generated. The
+    /// counts are for the remaining `depth - 1` plies after the move, so they
+    /// sum up to `perft(depth)`. Returns no moves if `depth` is 0.
+    pub fn divide(&self, depth: u32) -> Vec<(Move, u64)> {
+        if depth == 0 {
+            return vec![];
+        }
+
+        let mut state = self.clone();
+        let moves = state.legal_moves();
+        moves
+            .into_iter()
+            .map(|mv| {
+                state.take_turn(mv);
+                let nodes = perft(&mut state, depth - 1);
+                state.undo();
+                (mv, nodes)
+            })
+            .collect()
+    }
+}
+
+fn perft(state: &mut BoardState, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    let moves = state.legal_moves();
+    // There's no need to make the moves on the last ply, since every legal
+    // move leads to exactly one leaf.
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+
+    let mut nodes = 0;
+    for mv in moves {
+        state.take_turn(mv);
+        nodes += perft(state, depth - 1);
+        state.undo();
+    }
+    nodes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::STARTING_FEN;
+    use crate::variant::{Atomic, Variant};
+    use std::sync::Arc;
+
+    // The positions and node counts here are from
+    // https://www.chessprogramming.org/Perft_Results
+    // The depths are kept small enough that the tests run quickly in debug
+    // builds. Use the perft binary in release mode to go deeper.
+    fn assert_perft(fen: &str, expected: &[u64]) {
+        assert_variant_perft(fen, None, expected);
+    }
+
+    // Like `assert_perft`, but the position is played with `variant` if it is
+    // given, rather than the variant the FEN string implies.
+    fn assert_variant_perft(fen: &str, variant: Option<Arc<dyn Variant>>, expected: &[u64]) {
+        let mut state = BoardState::from_fen(fen).unwrap();
+        if let Some(variant) = variant {
+            state = BoardState::new_with_rules(state.board, state.current_player, variant);
+        }
+        for (depth, &nodes) in expected.iter().enumerate() {
+            let depth = depth as u32 + 1;
+            assert_eq!(
+                state.perft(depth),
+                nodes,
+                "perft({}) of {} is wrong. divide:\n{}",
+                depth,
+                fen,
+                state
+                    .divide(depth)
+                    .iter()
+                    .map(|(mv, nodes)| format!("{}: {}", mv, nodes))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            );
+        }
+    }
+
+    #[test]
+    fn test_perft_start_position() {
+        assert_perft(STARTING_FEN, &[20, 400, 8902, 197_281]);
+    }
+
+    #[test]
+    fn test_perft_kiwipete() {
+        assert_perft(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            &[48, 2039, 97_862, 4_085_603],
+        );
+    }
+
+    #[test]
+    fn test_perft_position_3() {
+        assert_perft(
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            &[14, 191, 2812, 43_238, 674_624],
+        );
+    }
+
+    #[test]
+    fn test_perft_position_4() {
+        assert_perft(
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            &[6, 264, 9467, 422_333],
+        );
+        // The same position with the colors swapped
+        assert_perft(
+            "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+            &[6, 264, 9467, 422_333],
+        );
+    }
+
+    #[test]
+    fn test_perft_position_5() {
+        assert_perft(
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            &[44, 1486, 62_379, 2_103_487],
+        );
+    }
+
+    #[test]
+    fn test_perft_position_6() {
+        assert_perft(
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+            &[46, 2079, 89_890, 3_894_594],
+        );
+    }
+
+    // Chess960 positions from https://www.chessprogramming.org/Chess960_Perft_Results
+    #[test]
+    fn test_perft_chess960() {
+        assert_perft(
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+            &[21, 528, 12_189, 326_672],
+        );
+        assert_perft(
+            "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+            &[21, 807, 18_002, 667_366],
+        );
+        assert_perft(
+            "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9",
+            &[20, 479, 10_471, 273_318],
+        );
+    }
+
+    #[test]
+    fn test_perft_crazyhouse() {
+        // Drops first become possible on the fifth ply.
+        assert_perft(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1",
+            &[20, 400, 8902, 197_281, 4_888_832],
+        );
+    }
+
+    // Atomic positions from the perft suite of python-chess
+    #[test]
+    fn test_perft_atomic() {
+        assert_variant_perft(
+            STARTING_FEN,
+            Some(Arc::new(Atomic)),
+            &[20, 400, 8902, 197_326],
+        );
+        assert_variant_perft(
+            "rn2kb1r/1pp1p2p/p2q1pp1/3P4/2P3b1/4PN2/PP3PPP/R2QKB1R b KQkq - 0 1",
+            Some(Arc::new(Atomic)),
+            &[40, 1238, 45_237],
+        );
+        assert_variant_perft(
+            "rn1qkb1r/p5pp/2p5/3p4/N3P3/5P2/PPP4P/R1BQK3 w Qkq - 0 1",
+            Some(Arc::new(Atomic)),
+            &[28, 833, 23_353],
+        );
+    }
+
+    #[test]
+    fn test_divide_sums_to_perft() {
+        let state = BoardState::from_fen(STARTING_FEN).unwrap();
+        let divide = state.divide(3);
+        assert_eq!(divide.len(), 20);
+        assert_eq!(divide.iter().map(|(_, nodes)| nodes).sum::<u64>(), 8902);
+        assert!(state.divide(0).is_empty());
+    }
+}
@@ -0,0 +1,594 @@
+//! Reading and writing games in Portable Game Notation (PGN). See
+//! http://www.saremba.de/chessgml/standards/pgn/pgn-complete.htm
+use std::fmt;
+
+use crate::board::*;
+use crate::fen::STARTING_FEN;
+use crate::outcome::{GameOutcome, Termination};
+
+/// The tags which every PGN game must have, in the order they are written.
+pub const SEVEN_TAG_ROSTER: [&str; 7] =
+    ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+
+/// Movetext lines are wrapped so that they fit in 80 columns.
+const MAX_LINE_LENGTH: usize = 80;
+
+/// A game read from a PGN file.
+#[derive(Debug, Clone)]
+pub struct PgnGame {
+    /// The tag pairs of the game, in the order they appeared.
+    pub tags: Vec<(String, String)>,
+    /// The position at the end of the game. The moves of the game can be found
+    /// in its history.
+    pub state: BoardState,
+    /// The game termination marker: "1-0", "0-1", "1/2-1/2", or "*".
+    pub result: String,
+}
+
+impl PgnGame {
+    /// Return the value of the tag named `name`, if the game has one.
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// An error produced while reading a PGN file. `line` is the (1-based) line
+/// the error occurred on and `reason` describes what was wrong.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgnError {
+    pub line: usize,
+    pub reason: String,
+}
+
+impl PgnError {
+    fn new(line: usize, reason: impl Into<String>) -> PgnError {
+        PgnError {
+            line,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid PGN on line {}: {}", self.line, self.reason)
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+/// Read every game in `pgn`. Comments, numeric annotation glyphs such as "$1",
+/// move suffixes such as "!?", and variations are skipped over. A game starts
+/// from the position in its FEN tag, if it has one, and otherwise from the
+/// standard starting position.
+pub fn read_pgn(pgn: &str) -> Result<Vec<PgnGame>, PgnError> {
+    let mut reader = PgnReader {
+        chars: pgn.chars().peekable(),
+        line: 1,
+        at_line_start: true,
+    };
+    let mut games = vec![];
+    let mut tags = vec![];
+    let mut state: Option<BoardState> = None;
+
+    while let Some(token) = reader.next_token()? {
+        match token {
+            Token::Tag(name, value) => {
+                if state.is_some() {
+                    // The previous game had no result, so this tag starts a new game.
+                    games.push(finish_game(&mut tags, &mut state, "*", reader.line)?);
+                }
+                tags.push((name, value));
+            }
+            Token::Result(result) => {
+                games.push(finish_game(&mut tags, &mut state, &result, reader.line)?);
+            }
+            Token::San(san) => {
+                if state.is_none() {
+                    state = Some(start_position(&tags, reader.line)?);
+                }
+                let state = state.as_mut().unwrap();
+                let mv = state
+                    .parse_san(&san)
+                    .map_err(|err| PgnError::new(reader.line, err.to_string()))?;
+                state.take_turn(mv);
+            }
+        }
+    }
+
+    if state.is_some() || !tags.is_empty() {
+        games.push(finish_game(&mut tags, &mut state, "*", reader.line)?);
+    }
+    Ok(games)
+}
+
+impl BoardState {
+    /// Write this game as PGN in export format. The moves written are the moves
+    /// in the history of this board state. The Seven Tag Roster is always
+    /// written first, using the values given in `tags` or "?" if a tag is not
+    /// given. The Result tag defaults to the result of the game if it has ended.
+    /// Any other tags are written afterwards in the order they are given. If the
+    /// game did not start from the standard starting position, SetUp and FEN
+    /// tags are added, and if the game ended by a time forfeit or adjudication
+    /// a Termination tag is added unless one is given.
+    pub fn to_pgn(&self, tags: &[(&str, &str)]) -> String {
+        let mut position = self.clone();
+        while position.undo().is_some() {}
+
+        let result = match tags.iter().find(|(name, _)| *name == "Result") {
+            Some((_, result)) => result.to_string(),
+            None => self.result().to_string(),
+        };
+
+        let mut pgn = String::new();
+        for &name in SEVEN_TAG_ROSTER.iter() {
+            let value = match tags.iter().find(|(tag, _)| *tag == name) {
+                Some((_, value)) => value.to_string(),
+                None if name == "Date" => "????.??.??".to_string(),
+                None if name == "Result" => result.clone(),
+                None => "?".to_string(),
+            };
+            push_tag(&mut pgn, name, &value);
+        }
+
+        let start_fen = position.to_fen();
+        if start_fen != STARTING_FEN {
+            push_tag(&mut pgn, "SetUp", "1");
+            push_tag(&mut pgn, "FEN", &start_fen);
+        }
+
+        let has_termination = tags.iter().any(|(name, _)| *name == "Termination");
+        match self.outcome() {
+            Some(outcome) if outcome.termination.pgn_tag() != "normal" && !has_termination => {
+                push_tag(&mut pgn, "Termination", outcome.termination.pgn_tag())
+            }
+            _ => (),
+        }
+
+        for &(name, value) in tags {
+            let is_generated = ["SetUp", "FEN"].contains(&name) && start_fen != STARTING_FEN;
+            if !SEVEN_TAG_ROSTER.contains(&name) && !is_generated {
+                push_tag(&mut pgn, name, value);
+            }
+        }
+        pgn.push('\n');
+
+        let mut tokens = vec![];
+        for (i, entry) in self.history().iter().enumerate() {
+            if position.current_player == Color::White {
+                tokens.push(format!("{}.", position.fullmove_number));
+            } else if i == 0 {
+                tokens.push(format!("{}...", position.fullmove_number));
+            }
+            let mv = entry.as_move();
+            tokens.push(position.to_san(mv));
+            position.take_turn(mv);
+        }
+        tokens.push(result);
+
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() > MAX_LINE_LENGTH {
+                pgn.push_str(&line);
+                pgn.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        pgn.push_str(&line);
+        pgn.push('\n');
+        pgn
+    }
+
+    /// Return the PGN game termination marker for this board state: "1-0" or
+    /// "0-1" if a player has won, "1/2-1/2" if the game is drawn, and "*" if
+    /// the game is still in progress.
+    pub fn result(&self) -> &'static str {
+        self.outcome().map_or("*", |outcome| outcome.result())
+    }
+}
+
+/// Return the position a game with the given tags starts from.
+fn start_position(tags: &[(String, String)], line: usize) -> Result<BoardState, PgnError> {
+    match tags.iter().find(|(name, _)| name == "FEN") {
+        Some((_, fen)) => {
+            BoardState::from_fen(fen).map_err(|err| PgnError::new(line, err.to_string()))
+        }
+        None => Ok(BoardState::new(Board::default())),
+    }
+}
+
+fn finish_game(
+    tags: &mut Vec<(String, String)>,
+    state: &mut Option<BoardState>,
+    result: &str,
+    line: usize,
+) -> Result<PgnGame, PgnError> {
+    let mut state = match state.take() {
+        Some(state) => state,
+        None => start_position(tags, line)?,
+    };
+    // A game which ended by something other than the position, such as by a
+    // resignation, has its outcome set from the result and Termination tag.
+    let winner = match result {
+        "1-0" => Some(Some(Color::White)),
+        "0-1" => Some(Some(Color::Black)),
+        "1/2-1/2" => Some(None),
+        _ => None,
+    };
+    if let (Some(winner), false) = (winner, state.game_over()) {
+        let termination = tags
+            .iter()
+            .find(|(name, _)| name == "Termination")
+            .map(|(_, value)| value.to_ascii_lowercase());
+        let termination = match (termination.as_deref(), winner) {
+            (Some("time forfeit"), Some(_)) => Termination::Timeout,
+            (Some("time forfeit"), None) => Termination::TimeoutVsInsufficientMaterial,
+            (Some("adjudication"), _) => Termination::Adjudication,
+            (_, Some(_)) => Termination::Resignation,
+            (_, None) => Termination::AgreedDraw,
+        };
+        state.set_outcome(GameOutcome {
+            winner,
+            termination,
+        });
+    }
+    Ok(PgnGame {
+        tags: std::mem::take(tags),
+        state,
+        result: result.to_string(),
+    })
+}
+
+fn push_tag(pgn: &mut String, name: &str, value: &str) {
+    let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+    pgn.push_str(&format!("[{} \"{}\"]\n", name, value));
+}
+
+/// The meaningful parts of a PGN file. Everything else (move numbers, comments,
+/// annotations, and variations) is skipped by the reader.
+enum Token {
+    Tag(String, String),
+    San(String),
+    Result(String),
+}
+
+struct PgnReader<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    line: usize,
+    /// True if the next character is the first character of a line.
+    at_line_start: bool,
+}
+
+impl<'a> PgnReader<'a> {
+    fn next_char(&mut self) -> Option<char> {
+        let c = self.chars.next();
+        if c == Some('\n') {
+            self.line += 1;
+        }
+        self.at_line_start = c == Some('\n');
+        c
+    }
+
+    /// Skip characters up to and including `end`.
+    fn skip_past(&mut self, end: char) -> Result<(), PgnError> {
+        let line = self.line;
+        loop {
+            match self.next_char() {
+                Some(c) if c == end => return Ok(()),
+                Some(_) => {}
+                None if end == '\n' => return Ok(()),
+                None => return Err(PgnError::new(line, format!("missing '{}'", end))),
+            }
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Option<Token>, PgnError> {
+        loop {
+            let c = match self.chars.peek() {
+                None => return Ok(None),
+                Some(&c) => c,
+            };
+            match c {
+                c if c.is_whitespace() => {
+                    self.next_char();
+                }
+                // Escaped lines and rest-of-line comments
+                '%' if self.at_line_start => self.skip_past('\n')?,
+                ';' => self.skip_past('\n')?,
+                '{' => self.skip_past('}')?,
+                '(' => self.skip_variation()?,
+                '[' => return self.read_tag().map(Some),
+                '*' => {
+                    self.next_char();
+                    return Ok(Some(Token::Result("*".to_string())));
+                }
+                _ => {
+                    let symbol = self.read_symbol();
+                    if symbol.is_empty() {
+                        return Err(PgnError::new(self.line, format!("unexpected '{}'", c)));
+                    }
+                    if let Some(token) = classify_symbol(&symbol) {
+                        return Ok(Some(token));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Skip a (possibly nested) recursive annotation variation.
+    fn skip_variation(&mut self) -> Result<(), PgnError> {
+        let line = self.line;
+        let mut depth = 0;
+        loop {
+            match self.next_char() {
+                Some('(') => depth += 1,
+                Some(')') => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                }
+                Some('{') => self.skip_past('}')?,
+                Some(';') => self.skip_past('\n')?,
+                Some(_) => {}
+                None => return Err(PgnError::new(line, "missing ')'")),
+            }
+        }
+    }
+
+    fn read_tag(&mut self) -> Result<Token, PgnError> {
+        let line = self.line;
+        self.next_char(); // The '['
+
+        let mut name = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if c.is_whitespace() {
+                self.next_char();
+            } else {
+                break;
+            }
+        }
+        while let Some(&c) = self.chars.peek() {
+            if c.is_alphanumeric() || c == '_' {
+                name.push(c);
+                self.next_char();
+            } else {
+                break;
+            }
+        }
+        if name.is_empty() {
+            return Err(PgnError::new(line, "expected a tag name"));
+        }
+
+        while let Some(&c) = self.chars.peek() {
+            if c.is_whitespace() {
+                self.next_char();
+            } else {
+                break;
+            }
+        }
+        if self.next_char() != Some('"') {
+            return Err(PgnError::new(
+                line,
+                format!("expected a value for tag {}", name),
+            ));
+        }
+
+        let mut value = String::new();
+        loop {
+            match self.next_char() {
+                Some('"') => break,
+                Some('\\') => match self.next_char() {
+                    Some(c) => value.push(c),
+                    None => return Err(PgnError::new(line, "unterminated tag value")),
+                },
+                Some('\n') | None => return Err(PgnError::new(line, "unterminated tag value")),
+                Some(c) => value.push(c),
+            }
+        }
+
+        self.skip_past(']')?;
+        Ok(Token::Tag(name, value))
+    }
+
+    /// Read a run of characters which may appear in SAN (including drops such
+    /// as "P@e4"), move numbers, NAGs, or results.
+    fn read_symbol(&mut self) -> String {
+        let mut symbol = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if c.is_ascii_alphanumeric() || "_+#=:-/.!?$@".contains(c) {
+                symbol.push(c);
+                self.next_char();
+            } else {
+                break;
+            }
+        }
+        symbol
+    }
+}
+
+/// Turn a symbol into a token, or None if it should be skipped.
+fn classify_symbol(symbol: &str) -> Option<Token> {
+    match symbol {
+        "1-0" | "0-1" | "1/2-1/2" => return Some(Token::Result(symbol.to_string())),
+        _ => {}
+    }
+
+    // NAGs and standalone annotations, including the "e.p." written after an
+    // en passant capture by `to_san_with_ep`
+    if symbol.starts_with('$') || symbol.chars().all(|c| c == '!' || c == '?') || symbol == "e.p." {
+        return None;
+    }
+
+    // Move numbers, which may be attached to the move ("12.e4")
+    let san = symbol.trim_start_matches(|c: char| c.is_ascii_digit());
+    if san.starts_with('.') {
+        let san = san.trim_start_matches('.');
+        return if san.is_empty() {
+            None
+        } else {
+            Some(Token::San(san.to_string()))
+        };
+    }
+    Some(Token::San(symbol.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPERA_GAME: &str = r#"[Event "Paris"]
+[Site "Paris FRA"]
+[Date "1858.??.??"]
+[Round "?"]
+[White "Paul Morphy"]
+[Black "Duke Karl / Count Isouard"]
+[Result "1-0"]
+
+1. e4 e5 2. Nf3 d6 3. d4 Bg4 {This is a weak move already.} 4. dxe5 Bxf3 5. Qxf3
+dxe5 6. Bc4 Nf6 7. Qb3 Qe7 8. Nc3 c6 9. Bg5 $2 b5 (9... Qb4+ 10. Qxb4) 10. Nxb5
+cxb5 11. Bxb5+ Nbd7 12. O-O-O Rd8 13. Rxd7 Rxd7 14. Rd1 Qe6 15. Bxd7+ Nxd7
+16. Qb8+! Nxb8 17. Rd8# 1-0
+"#;
+
+    #[test]
+    fn test_read_pgn() {
+        let games = read_pgn(OPERA_GAME).unwrap();
+        assert_eq!(games.len(), 1);
+        let game = &games[0];
+        assert_eq!(game.tag("White"), Some("Paul Morphy"));
+        assert_eq!(game.tag("Annotator"), None);
+        assert_eq!(game.result, "1-0");
+        assert_eq!(game.state.history().len(), 33);
+        assert_eq!(game.state.checkmate, CheckmateState::Checkmate);
+        assert_eq!(
+            game.state.to_fen(),
+            "1n1Rkb1r/p4ppp/4q3/4p1B1/4P3/8/PPP2PPP/2K5 b k - 1 17"
+        );
+    }
+
+    #[test]
+    fn test_read_multiple_games() {
+        let pgn = r#"
+[Event "First"]
+[FEN "4k3/P7/8/8/8/8/8/4K3 w - - 0 1"]
+[SetUp "1"]
+
+1. a8=Q+ Kd7 *
+
+% An escaped line
+[Event "Second \"quoted\""]
+
+1.e4 ; a comment
+e5 1/2-1/2
+
+1. d4 d5
+"#;
+        let games = read_pgn(pgn).unwrap();
+        assert_eq!(games.len(), 3);
+        assert_eq!(games[0].state.to_fen(), "Q7/3k4/8/8/8/8/8/4K3 w - - 1 2");
+        assert_eq!(games[0].result, "*");
+        assert_eq!(games[1].tag("Event"), Some("Second \"quoted\""));
+        assert_eq!(games[1].result, "1/2-1/2");
+        assert_eq!(
+            games[1].state.outcome().unwrap().termination,
+            Termination::AgreedDraw
+        );
+        assert_eq!(games[1].state.history().len(), 2);
+        assert_eq!(games[2].tags, vec![]);
+        assert_eq!(games[2].result, "*");
+        assert_eq!(games[2].state.history().len(), 2);
+    }
+
+    #[test]
+    fn test_read_pgn_errors() {
+        assert_eq!(read_pgn("1. e4 e4").unwrap_err().line, 1);
+        assert_eq!(
+            read_pgn("[Event \"x\"]\n\n1. e4 {oops").unwrap_err().line,
+            3
+        );
+        assert!(read_pgn("[Event \"x]").is_err());
+        assert!(read_pgn("1. e4 e5 2. Nc6").is_err());
+    }
+
+    #[test]
+    fn test_read_pgn_en_passant() {
+        let game = &read_pgn("1. e4 a6 2. e5 d5 3. exd6 e.p. *").unwrap()[0];
+        assert_eq!(game.state.history().len(), 5);
+        assert!(game.state.history()[4].as_move().is_en_passant());
+    }
+
+    #[test]
+    fn test_write_pgn() {
+        let game = &read_pgn(OPERA_GAME).unwrap()[0];
+        let tags: Vec<(&str, &str)> = game
+            .tags
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        let pgn = game.state.to_pgn(&tags);
+        assert!(pgn.starts_with("[Event \"Paris\"]\n[Site \"Paris FRA\"]\n"));
+        assert!(pgn.contains("\n\n1. e4 e5 2. Nf3 d6 3. d4 Bg4 4. dxe5 Bxf3"));
+        assert!(pgn.ends_with("16. Qb8+ Nxb8 17. Rd8# 1-0\n"));
+        assert!(pgn.lines().all(|line| line.len() <= MAX_LINE_LENGTH));
+
+        let reread = &read_pgn(&pgn).unwrap()[0];
+        assert_eq!(reread.tags, game.tags);
+        assert_eq!(reread.state.history(), game.state.history());
+    }
+
+    #[test]
+    fn test_write_pgn_from_position() {
+        let mut state = BoardState::from_fen("4k3/8/8/8/8/8/8/R3K3 b Q - 0 40").unwrap();
+        state.take_turn(Move::new(
+            &state.board,
+            BoardCoord(4, 7),
+            BoardCoord(3, 7),
+            None,
+        ));
+        state.take_turn(Move::new(
+            &state.board,
+            BoardCoord(4, 0),
+            BoardCoord(2, 0),
+            None,
+        ));
+        let pgn = state.to_pgn(&[("White", "Alice")]);
+        assert_eq!(
+            pgn,
+            "[Event \"?\"]\n[Site \"?\"]\n[Date \"????.??.??\"]\n[Round \"?\"]\n\
+             [White \"Alice\"]\n[Black \"?\"]\n[Result \"*\"]\n[SetUp \"1\"]\n\
+             [FEN \"4k3/8/8/8/8/8/8/R3K3 b Q - 0 40\"]\n\n40... Kd8 41. O-O-O+ *\n"
+        );
+
+        state.time_out(Color::Black);
+        let pgn = state.to_pgn(&[]);
+        assert!(pgn.contains("[Result \"1-0\"]"));
+        assert!(pgn.contains("[Termination \"time forfeit\"]"));
+        assert!(pgn.ends_with("41. O-O-O+ 1-0\n"));
+        let reread = &read_pgn(&pgn).unwrap()[0];
+        assert_eq!(reread.state.outcome(), state.outcome());
+    }
+
+    #[test]
+    fn test_write_pgn_crazyhouse() {
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1";
+        let mut state = BoardState::from_fen(fen).unwrap();
+        for san in &["e4", "d5", "exd5", "Qxd5", "P@e4"] {
+            let mv = state.parse_san(san).unwrap();
+            state.take_turn(mv);
+        }
+        let pgn = state.to_pgn(&[]);
+        assert!(pgn.contains("\n\n1. e4 d5 2. exd5 Qxd5 3. P@e4 *"));
+
+        let reread = &read_pgn(&pgn).unwrap()[0];
+        assert_eq!(reread.state.history(), state.history());
+        assert_eq!(reread.state.to_fen(), state.to_fen());
+    }
+}
@@ -0,0 +1,571 @@
+//! Setting up a position piece by piece. Unlike `Board::from_string_vec`,
+//! which places whatever it is given, `PositionBuilder::build` checks that the
+//! position could arise in a game of chess before creating it, and returns
+//! every problem it finds.
+use std::fmt;
+
+use crate::bitboard::{self, Bitboard};
+use crate::board::*;
+use crate::fen;
+
+/// Something which makes a position impossible. See `PositionBuilder::build`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PositionError {
+    /// A piece was placed on `square`, which is off the board.
+    OffBoard { square: BoardCoord },
+    /// More than one piece was placed on `square`.
+    SquareOccupied { square: BoardCoord },
+    /// `color` has `count` kings instead of one.
+    WrongKingCount { color: Color, count: usize },
+    /// The pawn on `square` is on the first or last rank.
+    PawnOnBackRank { square: BoardCoord },
+    /// `color` has `count` pawns, which is more than they start with.
+    TooManyPawns { color: Color, count: usize },
+    /// `color` has more extra pieces than they could have promoted to with
+    /// the pawns they're missing.
+    TooManyPromotedPieces { color: Color },
+    /// `color` is in check, even though it is the other player's turn.
+    OpponentInCheck { color: Color },
+    /// The player-to-move is in check from `count` pieces, but a move can give
+    /// at most a double check. This is conservative: double checks which no
+    /// move could have given, such as by two knights, are not rejected.
+    TooManyCheckers { count: u32 },
+    /// `color` was given a castling right on `side`, but their king isn't on
+    /// their first rank.
+    NoCastlingKing { color: Color, side: BoardSide },
+    /// `color` was given a castling right on `side`, but there's no rook on
+    /// that side of their king.
+    NoCastlingRook { color: Color, side: BoardSide },
+    /// The en passant target `square` is impossible, for the given `reason`.
+    InvalidEnPassant {
+        square: BoardCoord,
+        reason: &'static str,
+    },
+    /// The fullmove number is 0, but it starts at 1.
+    InvalidFullmoveNumber,
+}
+
+impl fmt::Display for PositionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use PositionError::*;
+        match *self {
+            OffBoard { square } => write!(f, "{} is off the board", square),
+            SquareOccupied { square } => write!(f, "More than one piece is on {}", square),
+            WrongKingCount { color, count } => {
+                write!(f, "{} has {} kings instead of one", color.as_str(), count)
+            }
+            PawnOnBackRank { square } => write!(f, "The pawn on {} is on a back rank", square),
+            TooManyPawns { color, count } => {
+                write!(f, "{} has too many pawns ({})", color.as_str(), count)
+            }
+            TooManyPromotedPieces { color } => write!(
+                f,
+                "{} has more promoted pieces than missing pawns",
+                color.as_str()
+            ),
+            OpponentInCheck { color } => write!(
+                f,
+                "{} is in check, but it is not their turn",
+                color.as_str()
+            ),
+            TooManyCheckers { count } => write!(f, "The king is in check from {} pieces", count),
+            NoCastlingKing { color, side } => write!(
+                f,
+                "{} can't castle {:?} without a king on their first rank",
+                color.as_str(),
+                side
+            ),
+            NoCastlingRook { color, side } => write!(
+                f,
+                "{} can't castle {:?} without a rook on that side",
+                color.as_str(),
+                side
+            ),
+            InvalidEnPassant { square, reason } => {
+                write!(f, "En passant on {} is impossible: {}", square, reason)
+            }
+            InvalidFullmoveNumber => write!(f, "The fullmove number must be at least 1"),
+        }
+    }
+}
+
+impl std::error::Error for PositionError {}
+
+/// A position being set up. Pieces and the rest of the position are given one
+/// at a time, and `build` checks the position and creates the `BoardState`.
+#[derive(Debug, Clone)]
+pub struct PositionBuilder {
+    dimensions: Dimensions,
+    pieces: Vec<(BoardCoord, Color, PieceType)>,
+    current_player: Color,
+    castling: Vec<(Color, BoardSide)>,
+    /// The square a pawn which just moved two spaces passed over
+    en_passant: Option<BoardCoord>,
+    halfmove_clock: u32,
+    fullmove_number: u32,
+}
+
+impl Default for PositionBuilder {
+    fn default() -> PositionBuilder {
+        PositionBuilder::new()
+    }
+}
+
+impl PositionBuilder {
+    /// Start setting up a position on an empty standard 8x8 board, with White
+    /// to move and no castling rights.
+    pub fn new() -> PositionBuilder {
+        PositionBuilder::with_dimensions(Dimensions::STANDARD)
+    }
+
+    /// Start setting up a position on an empty board of the given size.
+    pub fn with_dimensions(dimensions: Dimensions) -> PositionBuilder {
+        PositionBuilder {
+            dimensions,
+            pieces: vec![],
+            current_player: Color::White,
+            castling: vec![],
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+        }
+    }
+
+    /// Place `color`'s `piece` on `square`. A pawn may still move two spaces
+    /// if it is on its starting rank.
+    pub fn piece(mut self, square: BoardCoord, color: Color, piece: PieceType) -> PositionBuilder {
+        let piece = match piece {
+            PieceType::Pawn { .. } => PieceType::Pawn { just_lunged: false },
+            piece => piece,
+        };
+        self.pieces.push((square, color, piece));
+        self
+    }
+
+    /// Set the player-to-move.
+    pub fn side_to_move(mut self, color: Color) -> PositionBuilder {
+        self.current_player = color;
+        self
+    }
+
+    /// Let `color` castle on `side`, using the outermost rook on that side of
+    /// their king.
+    pub fn castling(mut self, color: Color, side: BoardSide) -> PositionBuilder {
+        if !self.castling.contains(&(color, side)) {
+            self.castling.push((color, side));
+        }
+        self
+    }
+
+    /// Let the player-to-move capture en passant on `target`, which is the
+    /// square the other player's pawn passed over.
+    pub fn en_passant(mut self, target: BoardCoord) -> PositionBuilder {
+        self.en_passant = Some(target);
+        self
+    }
+
+    /// Set the number of halfmoves since the last capture or pawn move.
+    pub fn halfmove_clock(mut self, halfmove_clock: u32) -> PositionBuilder {
+        self.halfmove_clock = halfmove_clock;
+        self
+    }
+
+    /// Set the number of the current move, which starts at 1.
+    pub fn fullmove_number(mut self, fullmove_number: u32) -> PositionBuilder {
+        self.fullmove_number = fullmove_number;
+        self
+    }
+
+    /// Check the position and create it, played as standard chess. The
+    /// position is rejected if it has anything but one king of each color,
+    /// pawns on a back rank, more pieces than promotions could give, the wrong
+    /// player in check, or castling rights and en passant targets which the
+    /// pieces don't allow. Every problem found is returned.
+    pub fn build(&self) -> Result<BoardState, Vec<PositionError>> {
+        use PositionError::*;
+        let mut errors = vec![];
+        let mut board = Board::with_dimensions(self.dimensions);
+        let first_rank = |color| self.dimensions.back_rank(color);
+        let last_rank = |color: Color| self.dimensions.back_rank(color.opposite());
+
+        for &(square, color, piece) in &self.pieces {
+            if !board.contains(square) {
+                errors.push(OffBoard { square });
+                continue;
+            }
+            if board.get(square).0.is_some() {
+                errors.push(SquareOccupied { square });
+                continue;
+            }
+            let mut tile = Tile::new(color, piece);
+            if let PieceType::Pawn { .. } = piece {
+                if square.1 == first_rank(color) || square.1 == last_rank(color) {
+                    errors.push(PawnOnBackRank { square });
+                }
+                // Only pawns on their starting rank may still double move.
+                tile.set_moved(square.1 != first_rank(color) + color.direction());
+            }
+            *board.get_mut(square) = tile;
+        }
+
+        let mut kings_ok = true;
+        for &color in &[Color::White, Color::Black] {
+            // The number of `color`'s pieces of `piece_type` on `squares`
+            let count_on = |piece_type: PieceType, squares: Bitboard| {
+                board
+                    .iter_pieces()
+                    .filter(|&(coord, piece)| {
+                        piece.color == color
+                            && piece.piece == piece_type
+                            && bitboard::bit(coord) & squares != bitboard::EMPTY
+                    })
+                    .count()
+            };
+            let count_of = |piece_type: PieceType| count_on(piece_type, !bitboard::EMPTY);
+            let kings = count_of(PieceType::King);
+            if kings != 1 {
+                errors.push(WrongKingCount {
+                    color,
+                    count: kings,
+                });
+                kings_ok = false;
+            }
+
+            let files = self.dimensions.files as usize;
+            let pawns = count_of(PieceType::Pawn { just_lunged: false });
+            if pawns > files {
+                errors.push(TooManyPawns {
+                    color,
+                    count: pawns,
+                });
+            } else if self.dimensions == Dimensions::STANDARD {
+                // Every piece beyond the ones a player starts with must have
+                // come from promoting a pawn. Bishops start on one light and
+                // one dark square, so a second bishop on either is promoted.
+                let all = !bitboard::EMPTY;
+                let dark = bitboard::DARK_SQUARES;
+                let promoted: usize = [
+                    (PieceType::Knight, all, 2),
+                    (PieceType::Bishop, dark, 1),
+                    (PieceType::Bishop, !dark, 1),
+                    (PieceType::Rook, all, 2),
+                    (PieceType::Queen, all, 1),
+                ]
+                .iter()
+                .map(|&(piece, squares, start)| count_on(piece, squares).saturating_sub(start))
+                .sum();
+                let fairies = board
+                    .iter_pieces()
+                    .filter(|(_, piece)| {
+                        piece.color == color && matches!(piece.piece, PieceType::Fairy(_))
+                    })
+                    .count();
+                if promoted + fairies > files - pawns {
+                    errors.push(TooManyPromotedPieces { color });
+                }
+            }
+        }
+
+        // Checks only make sense once there is one king of each color.
+        if kings_ok {
+            let player = self.current_player;
+            if board.checkers(player.opposite()) != bitboard::EMPTY {
+                errors.push(OpponentInCheck {
+                    color: player.opposite(),
+                });
+            }
+            let count = board.checkers(player).count_ones();
+            if count > 2 {
+                errors.push(TooManyCheckers { count });
+            }
+        }
+
+        // Every king and rook starts off marked as moved, and then the ones
+        // which may castle are marked as unmoved.
+        let castlers: Vec<BoardCoord> = board
+            .iter_pieces()
+            .filter(|(_, piece)| piece.piece == PieceType::King || piece.piece == PieceType::Rook)
+            .map(|(coord, _)| coord)
+            .collect();
+        for coord in castlers {
+            board.get_mut(coord).set_moved(true);
+        }
+        let mut unmoved = vec![];
+        for &(color, side) in &self.castling {
+            let king = (0..self.dimensions.files)
+                .map(|file| BoardCoord(file, first_rank(color)))
+                .find(|&coord| board.get(coord).is(color, PieceType::King));
+            let king = match king {
+                Some(king) => king,
+                None => {
+                    errors.push(NoCastlingKing { color, side });
+                    continue;
+                }
+            };
+            match fen::outermost_rook(&board, color, king, side) {
+                Some(rook) => unmoved.extend_from_slice(&[king, rook]),
+                None => errors.push(NoCastlingRook { color, side }),
+            }
+        }
+        for coord in unmoved {
+            board.get_mut(coord).set_moved(false);
+        }
+
+        if let Some(square) = self.en_passant {
+            if let Err(reason) = self.set_en_passant(&mut board, square) {
+                errors.push(InvalidEnPassant { square, reason });
+            }
+        }
+
+        if self.fullmove_number == 0 {
+            errors.push(InvalidFullmoveNumber);
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        let mut state = BoardState::new_with_player(board, self.current_player);
+        state.set_clocks(self.halfmove_clock, self.fullmove_number);
+        Ok(state)
+    }
+
+    /// Mark the pawn which passed over `target` as having just lunged, or
+    /// return why it can't have.
+    fn set_en_passant(&self, board: &mut Board, target: BoardCoord) -> Result<(), &'static str> {
+        if !board.contains(target) {
+            return Err("the square is off the board");
+        }
+        // The pawn that just lunged belongs to the player who is not to move.
+        let lunged = self.current_player.opposite();
+        let start = BoardCoord(target.0, target.1 - lunged.direction());
+        let pawn = BoardCoord(target.0, target.1 + lunged.direction());
+        if start.1 != self.dimensions.back_rank(lunged) + lunged.direction() {
+            return Err("the square is not one a pawn of the other player passes over");
+        }
+        if board.get(target).0.is_some() || board.get(start).0.is_some() {
+            return Err("the squares the pawn lunged over must be empty");
+        }
+        match &mut board.get_mut(pawn).0 {
+            Some(Piece {
+                piece: PieceType::Pawn { just_lunged },
+                color,
+                ..
+            }) if *color == lunged => {
+                *just_lunged = true;
+                Ok(())
+            }
+            _ => Err("there is no pawn to capture"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::STARTING_FEN;
+    use PieceType::*;
+
+    fn coord(square: &str) -> BoardCoord {
+        square.parse().unwrap()
+    }
+
+    fn kings() -> PositionBuilder {
+        PositionBuilder::new()
+            .piece(coord("e1"), Color::White, King)
+            .piece(coord("e8"), Color::Black, King)
+    }
+
+    #[test]
+    fn test_build() {
+        let mut builder = kings()
+            .piece(coord("a1"), Color::White, Rook)
+            .piece(coord("h1"), Color::White, Rook)
+            .castling(Color::White, BoardSide::Kingside)
+            .castling(Color::White, BoardSide::Queenside)
+            .side_to_move(Color::Black)
+            .halfmove_clock(3)
+            .fullmove_number(20);
+        for file in 0..8 {
+            builder = builder.piece(
+                BoardCoord(file, 6),
+                Color::Black,
+                Pawn { just_lunged: false },
+            );
+        }
+        let state = builder.build().unwrap();
+        assert_eq!(state.to_fen(), "4k3/pppppppp/8/8/8/8/8/R3K2R b KQ - 3 20");
+
+        let state = kings()
+            .piece(coord("d5"), Color::Black, Pawn { just_lunged: false })
+            .piece(coord("e5"), Color::White, Pawn { just_lunged: false })
+            .en_passant(coord("d6"))
+            .build()
+            .unwrap();
+        assert_eq!(state.to_fen(), "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1");
+        assert!(state.parse_uci_move("e5d6").is_ok());
+
+        // The standard starting position, piece by piece
+        let start = BoardState::from_fen(STARTING_FEN).unwrap();
+        let mut builder = PositionBuilder::new();
+        for (coord, piece) in start.board.iter_pieces() {
+            builder = builder.piece(coord, piece.color, piece.piece);
+        }
+        for &color in &[Color::White, Color::Black] {
+            builder = builder
+                .castling(color, BoardSide::Kingside)
+                .castling(color, BoardSide::Queenside);
+        }
+        assert_eq!(builder.build().unwrap().to_fen(), STARTING_FEN);
+
+        // A position after 75 moves without a capture or pawn move is drawn
+        let state = kings()
+            .piece(coord("a1"), Color::White, Rook)
+            .halfmove_clock(150)
+            .build()
+            .unwrap();
+        assert_eq!(state.checkmate, CheckmateState::SeventyFiveMoveRule);
+    }
+
+    #[test]
+    fn test_build_errors() {
+        use PositionError::*;
+        let errors = |builder: PositionBuilder| builder.build().unwrap_err();
+
+        assert_eq!(
+            errors(PositionBuilder::new().piece(coord("e1"), Color::White, King)),
+            vec![WrongKingCount {
+                color: Color::Black,
+                count: 0,
+            }]
+        );
+        assert_eq!(
+            errors(kings().piece(coord("a1"), Color::White, King)),
+            vec![WrongKingCount {
+                color: Color::White,
+                count: 2,
+            }]
+        );
+        assert_eq!(
+            errors(kings().piece(BoardCoord(8, 0), Color::White, Rook).piece(
+                coord("e1"),
+                Color::White,
+                Queen
+            )),
+            vec![
+                OffBoard {
+                    square: BoardCoord(8, 0)
+                },
+                SquareOccupied {
+                    square: coord("e1")
+                }
+            ]
+        );
+        assert_eq!(
+            errors(kings().piece(coord("a8"), Color::White, Pawn { just_lunged: false })),
+            vec![PawnOnBackRank {
+                square: coord("a8")
+            }]
+        );
+
+        // Eleven rooks is too many, since there are only eight pawns to promote
+        let mut builder = kings();
+        for &square in &["a2", "b2", "c2", "d2", "f2", "g2", "h2", "a3", "b3", "c3"] {
+            builder = builder.piece(coord(square), Color::White, Rook);
+        }
+        assert_eq!(builder.clone().build().map(|_| ()), Ok(()));
+        builder = builder.piece(coord("h3"), Color::White, Rook);
+        assert_eq!(
+            errors(builder),
+            vec![TooManyPromotedPieces {
+                color: Color::White
+            }]
+        );
+
+        // With every pawn still on the board, two bishops on dark squares
+        // can't both be original.
+        let mut builder = kings();
+        for file in 0..8 {
+            builder = builder.piece(
+                BoardCoord(file, 1),
+                Color::White,
+                Pawn { just_lunged: false },
+            );
+        }
+        let builder = builder.piece(coord("c1"), Color::White, Bishop);
+        assert_eq!(
+            builder
+                .clone()
+                .piece(coord("f1"), Color::White, Bishop)
+                .build()
+                .map(|_| ()),
+            Ok(())
+        );
+        assert_eq!(
+            errors(builder.piece(coord("e3"), Color::White, Bishop)),
+            vec![TooManyPromotedPieces {
+                color: Color::White
+            }]
+        );
+
+        // The player who just moved can't be in check
+        assert_eq!(
+            errors(
+                kings()
+                    .piece(coord("e4"), Color::White, Rook)
+                    .side_to_move(Color::White)
+            ),
+            vec![OpponentInCheck {
+                color: Color::Black
+            }]
+        );
+        assert_eq!(
+            errors(
+                kings()
+                    .piece(coord("e4"), Color::Black, Rook)
+                    .piece(coord("b4"), Color::Black, Bishop)
+                    .piece(coord("d3"), Color::Black, Knight)
+            ),
+            vec![TooManyCheckers { count: 3 }]
+        );
+
+        assert_eq!(
+            errors(
+                kings()
+                    .piece(coord("a1"), Color::White, Rook)
+                    .castling(Color::White, BoardSide::Kingside)
+                    .castling(Color::Black, BoardSide::Queenside)
+            ),
+            vec![
+                NoCastlingRook {
+                    color: Color::White,
+                    side: BoardSide::Kingside,
+                },
+                NoCastlingRook {
+                    color: Color::Black,
+                    side: BoardSide::Queenside,
+                },
+            ]
+        );
+
+        let error = errors(kings().en_passant(coord("d6")));
+        assert_eq!(
+            error,
+            vec![InvalidEnPassant {
+                square: coord("d6"),
+                reason: "there is no pawn to capture",
+            }]
+        );
+        assert_eq!(
+            error[0].to_string(),
+            "En passant on d6 is impossible: there is no pawn to capture"
+        );
+        assert!(matches!(
+            errors(kings().en_passant(coord("d3")))[..],
+            [InvalidEnPassant { .. }]
+        ));
+        assert_eq!(
+            errors(kings().fullmove_number(0)),
+            vec![InvalidFullmoveNumber]
+        );
+    }
+}
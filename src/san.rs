@@ -0,0 +1,479 @@
+//! Standard Algebraic Notation (SAN), the move notation used by humans and by
+//! PGN files. See https://en.wikipedia.org/wiki/Algebraic_notation_(chess)
+use std::fmt;
+
+use crate::board::*;
+use crate::fairy;
+
+/// The ways in which a SAN string can fail to describe a legal move.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SanErrorKind {
+    /// The string is not well formed SAN.
+    InvalidSyntax,
+    /// The string is well formed, but no legal move matches it.
+    IllegalMove,
+    /// More than one legal move matches the string. This contains the squares
+    /// of the pieces which could make the move.
+    AmbiguousMove(Vec<BoardCoord>),
+    /// A pawn moves to the last rank, but no piece to promote to was given.
+    MissingPromotion,
+    /// A piece to promote to was given, but the move is not a promotion.
+    UnexpectedPromotion,
+}
+
+/// An error produced while parsing a SAN string. `san` is the string that
+/// could not be parsed and `kind` describes what was wrong with it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SanError {
+    pub san: String,
+    pub kind: SanErrorKind,
+}
+
+impl SanError {
+    fn new(san: &str, kind: SanErrorKind) -> SanError {
+        SanError {
+            san: san.to_string(),
+            kind,
+        }
+    }
+}
+
+impl fmt::Display for SanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use SanErrorKind::*;
+        write!(f, "invalid move \"{}\": ", self.san)?;
+        match &self.kind {
+            InvalidSyntax => write!(f, "not valid algebraic notation"),
+            IllegalMove => write!(f, "no legal move matches"),
+            AmbiguousMove(candidates) => {
+                let squares: Vec<String> = candidates.iter().map(|c| c.to_string()).collect();
+                write!(f, "ambiguous, could be made from {}", squares.join(" or "))
+            }
+            MissingPromotion => write!(f, "a piece to promote to must be given"),
+            UnexpectedPromotion => write!(f, "the move is not a promotion"),
+        }
+    }
+}
+
+impl std::error::Error for SanError {}
+
+impl BoardState {
+    /// Return the SAN of `mv`. Moves which give check end in "+" and moves
+    /// which give checkmate end in "#". En passant captures are written as
+    /// normal pawn captures, such as "exd6", since the "e.p." suffix is not
+    /// allowed in PGN files. Use `to_san_with_ep` to get it. Drops are written
+    /// as the piece and the square, such as "N@f3" or "P@e4". This function
+    /// panics if the move is illegal or if the promotion is missing for a pawn
+    /// reaching the last rank.
+    pub fn to_san(&self, mv: Move) -> String {
+        let Move { start, end, .. } = mv;
+        debug_assert!(self.check_turn(mv).is_ok());
+
+        let mut san = if let Some(piece) = mv.drop {
+            format!("{}@{}", piece, end)
+        } else {
+            let piece = self.board.get(start).0.expect("Expected a piece to move");
+            match move_type_coords(&self.board, start, end) {
+                MoveTypeCoords::Castle { king_end, .. } => {
+                    if king_end.0 == 2 {
+                        "O-O-O".to_string()
+                    } else {
+                        "O-O".to_string()
+                    }
+                }
+                MoveTypeCoords::Normal { .. } | MoveTypeCoords::Lunge { .. } => {
+                    let mut san = piece_prefix(self, piece.piece, start, end);
+                    san.push_str(&end.to_string());
+                    san
+                }
+                MoveTypeCoords::Capture { .. } | MoveTypeCoords::EnPassant { .. } => {
+                    let mut san = piece_prefix(self, piece.piece, start, end);
+                    if let PieceType::Pawn { .. } = piece.piece {
+                        san.push((b'a' + start.0 as u8) as char);
+                    }
+                    san.push('x');
+                    san.push_str(&end.to_string());
+                    san
+                }
+                MoveTypeCoords::Drop { .. } => unreachable!("Expected drops to be handled above"),
+            }
+        };
+
+        if let Some(promotion) = mv.promotion {
+            san.push('=');
+            san.push_str(&promotion.to_string());
+        }
+
+        let mut after = self.clone();
+        after.take_turn(mv);
+
+        let opponent = after.current_player;
+        if after.board.is_in_check(opponent) {
+            // In Crazyhouse, the opponent may be able to block the check with
+            // a drop, so ask the variant whether this is checkmate.
+            if after.checkmate == CheckmateState::Checkmate {
+                san.push('#');
+            } else {
+                san.push('+');
+            }
+        }
+        san
+    }
+
+    /// Like `to_san`, but en passant captures end in an "e.p." suffix, such as
+    /// "exd6 e.p.". The suffix comes after any check marker, so the result can
+    /// still be read back by `parse_san`.
+    pub fn to_san_with_ep(&self, mv: Move) -> String {
+        let mut san = self.to_san(mv);
+        if mv.is_en_passant() {
+            san.push_str(" e.p.");
+        }
+        san
+    }
+
+    /// Find the legal move described by `san`. Check and checkmate
+    /// markers, annotations such as "!?", and an "e.p." suffix are accepted
+    /// but not required to be accurate. Castling may be written with either
+    /// the letter O or the digit 0. Pawn drops may be written without the
+    /// "P", such as "@e4".
+    pub fn parse_san(&self, san: &str) -> Result<Move, SanError> {
+        let err = |kind| SanError::new(san, kind);
+
+        let mut text = san.trim();
+        if let Some(stripped) = text.strip_suffix("e.p.") {
+            text = stripped.trim_end();
+        }
+        let text = text.trim_end_matches(&['+', '#', '!', '?'][..]);
+
+        let castle_side = match text {
+            "O-O" | "0-0" => Some(BoardSide::Kingside),
+            "O-O-O" | "0-0-0" => Some(BoardSide::Queenside),
+            _ => None,
+        };
+        if let Some(side) = castle_side {
+            return match self.board.castle_move(self.current_player, side) {
+                Some(mv) if self.check_turn(mv).is_ok() => Ok(mv),
+                _ => Err(err(SanErrorKind::IllegalMove)),
+            };
+        }
+
+        if let Some((piece, square)) = text.split_once('@') {
+            let piece = match piece {
+                "" | "P" => PieceType::Pawn { just_lunged: false },
+                _ => match piece.chars().next().and_then(letter_to_piece) {
+                    Some(PieceType::King) | None => return Err(err(SanErrorKind::InvalidSyntax)),
+                    Some(_) if piece.len() > 1 => return Err(err(SanErrorKind::InvalidSyntax)),
+                    Some(piece) => piece,
+                },
+            };
+            let square = square
+                .parse()
+                .map_err(|_| err(SanErrorKind::InvalidSyntax))?;
+            let mv = Move::drop(piece, square);
+            return match self.check_turn(mv) {
+                Ok(()) => Ok(mv),
+                Err(_) => Err(err(SanErrorKind::IllegalMove)),
+            };
+        }
+
+        let parsed = parse_parts(text).ok_or_else(|| err(SanErrorKind::InvalidSyntax))?;
+
+        // The pieces which could make the move. Promotions are checked
+        // afterwards so that a missing promotion gets a clearer error.
+        let mut candidates: Vec<BoardCoord> = self
+            .legal_moves()
+            .into_iter()
+            .filter(|mv| !mv.is_drop())
+            .filter(|mv| {
+                let piece = self.board.get(mv.start).0.unwrap().piece;
+                mv.end == parsed.end
+                    && same_piece_type(piece, parsed.piece)
+                    && parsed.file.iter().all(|&file| mv.start.0 == file)
+                    && parsed.rank.iter().all(|&rank| mv.start.1 == rank)
+                    // Castling must be written as O-O or O-O-O
+                    && !mv.is_castle()
+            })
+            .map(|mv| mv.start)
+            .collect();
+        candidates.dedup();
+
+        let start = match candidates.as_slice() {
+            [] => return Err(err(SanErrorKind::IllegalMove)),
+            [single] => *single,
+            _ => return Err(err(SanErrorKind::AmbiguousMove(candidates))),
+        };
+
+        let end = parsed.end;
+        match (self.board.is_promotion(start, end), parsed.promotion) {
+            (true, None) => Err(err(SanErrorKind::MissingPromotion)),
+            (false, Some(_)) => Err(err(SanErrorKind::UnexpectedPromotion)),
+            (true, Some(PieceType::King)) => Err(err(SanErrorKind::IllegalMove)),
+            (_, promotion) => Ok(Move::new(&self.board, start, end, promotion)),
+        }
+    }
+}
+
+/// The parts of a non-castling SAN move.
+struct SanParts {
+    piece: PieceType,
+    /// The file of the moving piece, if given to disambiguate the move.
+    file: Option<i8>,
+    /// The rank of the moving piece, if given to disambiguate the move.
+    rank: Option<i8>,
+    end: BoardCoord,
+    promotion: Option<PieceType>,
+}
+
+/// Split a SAN move (without check markers) into its parts. Returns None if
+/// the move isn't well formed.
+fn parse_parts(text: &str) -> Option<SanParts> {
+    if !text.is_ascii() {
+        return None;
+    }
+
+    let (piece, mut rest) = match text.chars().next().and_then(letter_to_piece) {
+        Some(piece) => (piece, &text[1..]),
+        None => (PieceType::Pawn { just_lunged: false }, text),
+    };
+
+    // The promotion is written as "=Q", though "Q" alone is also common.
+    let mut promotion = None;
+    if let Some(letter) = rest.chars().last().and_then(letter_to_piece) {
+        promotion = Some(letter);
+        rest = &rest[..rest.len() - 1];
+        rest = rest.strip_suffix('=').unwrap_or(rest);
+    }
+
+    if rest.len() < 2 {
+        return None;
+    }
+    let end = rest[rest.len() - 2..].parse().ok()?;
+    rest = &rest[..rest.len() - 2];
+    rest = rest.strip_suffix('x').unwrap_or(rest);
+
+    let mut file = None;
+    let mut rank = None;
+    for c in rest.chars() {
+        match c {
+            'a'..='p' if file.is_none() && rank.is_none() => file = Some((c as u8 - b'a') as i8),
+            '1'..='8' if rank.is_none() => rank = Some((c as u8 - b'1') as i8),
+            _ => return None,
+        }
+    }
+
+    Some(SanParts {
+        piece,
+        file,
+        rank,
+        end,
+        promotion,
+    })
+}
+
+/// Return the piece letter and any disambiguation needed for a move of `piece`
+/// from `start` to `end`. Pawns have no prefix.
+fn piece_prefix(
+    state: &BoardState,
+    piece: PieceType,
+    start: BoardCoord,
+    end: BoardCoord,
+) -> String {
+    if let PieceType::Pawn { .. } = piece {
+        return String::new();
+    }
+
+    let mut prefix = piece.to_string();
+    let others: Vec<BoardCoord> = state
+        .legal_moves()
+        .into_iter()
+        .filter(|other| {
+            other.start != start
+                && other.end == end
+                && state.board.get(other.start).0.map(|p| p.piece) == Some(piece)
+        })
+        .map(|other| other.start)
+        .collect();
+
+    if others.is_empty() {
+        return prefix;
+    }
+
+    // Use the file if it is enough to tell the pieces apart, then the rank,
+    // and finally both.
+    let square = start.to_string();
+    if others.iter().all(|other| other.0 != start.0) {
+        prefix.push_str(&square[..1]);
+    } else if others.iter().all(|other| other.1 != start.1) {
+        prefix.push_str(&square[1..]);
+    } else {
+        prefix.push_str(&square);
+    }
+    prefix
+}
+
+/// Return the piece for a SAN piece letter. Only uppercase letters are used, so
+/// that "b" is always a file and "B" is always a bishop.
+fn letter_to_piece(c: char) -> Option<PieceType> {
+    use PieceType::*;
+    match c {
+        'N' => Some(Knight),
+        'B' => Some(Bishop),
+        'R' => Some(Rook),
+        'Q' => Some(Queen),
+        'K' => Some(King),
+        c => fairy::Fairy::from_letter(c).map(Fairy),
+    }
+}
+
+/// Returns true if the pieces are the same type, ignoring the `just_lunged` flag.
+fn same_piece_type(a: PieceType, b: PieceType) -> bool {
+    match (a, b) {
+        (PieceType::Pawn { .. }, PieceType::Pawn { .. }) => true,
+        _ => a == b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn san_of(fen: &str, uci: &str) -> String {
+        let state = BoardState::from_fen(fen).unwrap();
+        state.to_san(state.parse_uci_move(uci).unwrap())
+    }
+
+    fn uci(state: &BoardState, uci: &str) -> Result<Move, SanError> {
+        Ok(state.parse_uci_move(uci).unwrap())
+    }
+
+    #[test]
+    fn test_to_san() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(san_of(start, "e2e4"), "e4");
+        assert_eq!(san_of(start, "g1f3"), "Nf3");
+
+        // Both knights can reach d7
+        let knights = "rnbqkb1r/ppp1pppp/5n2/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1";
+        assert_eq!(san_of(knights, "b8d7"), "Nbd7");
+        assert_eq!(san_of(knights, "f6d7"), "Nfd7");
+
+        // Rooks on the same file need the rank
+        let rooks = "4k3/8/R7/8/8/8/R7/4K3 w - - 0 1";
+        assert_eq!(san_of(rooks, "a2a4"), "R2a4");
+
+        // Three queens need the full square
+        let queens = "4k3/8/8/8/Q1Q5/8/Q7/4K3 w - - 0 1";
+        assert_eq!(san_of(queens, "a4b3"), "Qa4b3");
+
+        let en_passant = "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1";
+        assert_eq!(san_of(en_passant, "e5d6"), "exd6");
+        let state = BoardState::from_fen(en_passant).unwrap();
+        let mv = uci(&state, "e5d6").unwrap();
+        assert_eq!(state.to_san_with_ep(mv), "exd6 e.p.");
+        assert_eq!(state.parse_san(&state.to_san_with_ep(mv)), Ok(mv));
+        assert_eq!(state.to_san_with_ep(uci(&state, "e1e2").unwrap()), "Ke2");
+        // The suffix goes after the check marker.
+        let check = "8/2k5/8/3pP3/8/8/8/4K3 w - d6 0 1";
+        let state = BoardState::from_fen(check).unwrap();
+        assert_eq!(
+            state.to_san_with_ep(uci(&state, "e5d6").unwrap()),
+            "exd6+ e.p."
+        );
+
+        let castles = "r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1";
+        assert_eq!(san_of(castles, "e8c8"), "O-O-O");
+        assert_eq!(san_of(castles, "e8g8"), "O-O");
+
+        let promotion = "8/4P1k1/8/8/8/8/8/4K3 w - - 0 1";
+        assert_eq!(san_of(promotion, "e7e8q"), "e8=Q");
+        assert_eq!(san_of(promotion, "e7e8n"), "e8=N+");
+
+        let check = "4k3/8/8/8/8/8/8/R3K3 w - - 0 1";
+        assert_eq!(san_of(check, "a1a8"), "Ra8+");
+
+        let mate = "6k1/5ppp/8/8/8/8/8/R3K3 w - - 0 1";
+        assert_eq!(san_of(mate, "a1a8"), "Ra8#");
+
+        let drops = "6k1/5ppp/8/8/8/8/8/4K3[QP] w - - 0 1";
+        assert_eq!(san_of(drops, "P@e4"), "P@e4");
+        assert_eq!(san_of(drops, "Q@e8"), "Q@e8#");
+        // In Crazyhouse, Black can block the check by dropping the knight.
+        let blocked = "6k1/5ppp/8/8/8/8/8/R3K3[n] w - - 0 1";
+        assert_eq!(san_of(blocked, "a1a8"), "Ra8+");
+    }
+
+    #[test]
+    fn test_parse_san() {
+        let state = BoardState::new(Board::default());
+        assert_eq!(state.parse_san("e4"), uci(&state, "e2e4"));
+        assert_eq!(state.parse_san("Nf3"), uci(&state, "g1f3"));
+        assert_eq!(state.parse_san("Ng1f3!?"), uci(&state, "g1f3"));
+
+        let state = BoardState::from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1").unwrap();
+        let en_passant = uci(&state, "e5d6");
+        assert_eq!(state.parse_san("exd6"), en_passant);
+        assert_eq!(state.parse_san("exd6 e.p."), en_passant);
+
+        let state = BoardState::from_fen("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1").unwrap();
+        assert_eq!(state.parse_san("O-O-O"), uci(&state, "e8c8"));
+        assert_eq!(state.parse_san("0-0"), uci(&state, "e8g8"));
+
+        let state = BoardState::from_fen("8/4P1k1/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(state.parse_san("e8=Q+"), uci(&state, "e7e8q"));
+        assert_eq!(state.parse_san("e8R"), uci(&state, "e7e8r"));
+
+        let state = BoardState::from_fen("4k3/8/8/8/8/8/8/4K3[NP] w - - 0 1").unwrap();
+        assert_eq!(state.parse_san("N@f3"), uci(&state, "N@f3"));
+        assert_eq!(state.parse_san("P@e4+"), uci(&state, "P@e4"));
+        assert_eq!(state.parse_san("@e4"), uci(&state, "P@e4"));
+        assert_eq!(
+            state.parse_san("B@e4").unwrap_err().kind,
+            SanErrorKind::IllegalMove
+        );
+        assert_eq!(
+            state.parse_san("K@e4").unwrap_err().kind,
+            SanErrorKind::InvalidSyntax
+        );
+    }
+
+    #[test]
+    fn test_parse_san_errors() {
+        let kind = |fen: &str, san: &str| {
+            let state = BoardState::from_fen(fen).unwrap();
+            state.parse_san(san).unwrap_err().kind
+        };
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(kind(start, "e5"), SanErrorKind::IllegalMove);
+        assert_eq!(kind(start, "O-O"), SanErrorKind::IllegalMove);
+        assert_eq!(kind(start, "Xe4"), SanErrorKind::InvalidSyntax);
+        assert_eq!(kind(start, "e9"), SanErrorKind::InvalidSyntax);
+        assert_eq!(kind(start, ""), SanErrorKind::InvalidSyntax);
+        assert_eq!(kind(start, "e4=Q"), SanErrorKind::UnexpectedPromotion);
+
+        let rooks = "4k3/8/R7/8/8/8/R7/4K3 w - - 0 1";
+        assert_eq!(
+            kind(rooks, "Ra4"),
+            SanErrorKind::AmbiguousMove(vec![BoardCoord(0, 1), BoardCoord(0, 5)])
+        );
+
+        let promotion = "8/4P1k1/8/8/8/8/8/4K3 w - - 0 1";
+        assert_eq!(kind(promotion, "e8"), SanErrorKind::MissingPromotion);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // Every legal move should parse back to itself
+        for &fen in &[
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/1P6/8/8/8/8/6p1/R3K2R w KQkq - 0 1",
+            "r3k2r/1P6/8/8/8/8/6p1/R3K2R b KQkq - 0 1",
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R[Pn] w KQkq - 2 3",
+        ] {
+            let state = BoardState::from_fen(fen).unwrap();
+            for mv in state.legal_moves() {
+                let san = state.to_san(mv);
+                assert_eq!(state.parse_san(&san), Ok(mv), "{}", san);
+            }
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use std::collections::{BinaryHeap, HashMap};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use rand::Rng;
@@ -10,9 +11,10 @@ use ggez::nalgebra as na;
 use ggez::{Context, GameResult};
 
 use chess::ai::{AIPlayer, RandomPlayer, TreeSearchPlayer};
+use chess::bitboard::{self, Bitboard};
 use chess::board::{
-    move_type_coords, Board, BoardCoord, BoardState, CheckmateState, Color, MoveTypeCoords, Piece,
-    PieceType, BISHOP_STR, KNIGHT_STR, QUEEN_STR, ROOK_STR,
+    move_type_coords, Board, BoardCoord, BoardState, CheckmateState, Color, Dimensions, Explosion,
+    Move, MoveError, MoveTypeCoords, Piece, PieceType, Pocket, Tile, PROMOTION_PIECES,
 };
 use chess::color;
 use chess::ease;
@@ -20,6 +22,7 @@ use chess::layout::{FlexBox, HStack, Layout, VStack};
 use chess::particle;
 use chess::rect;
 use chess::ui::{self, Button, Selector, TextBox};
+use chess::variant::{self, Variant};
 use chess::{hstack, vstack};
 
 const PI: f32 = std::f32::consts::PI;
@@ -31,6 +34,9 @@ const DEBUG_RESTART: bool = true;
 
 const DEFAULT_SCALE: f32 = 20.0;
 const DONTCARE: f32 = -999.0;
+// The size of a square on an 8x8 board. Wider boards use smaller squares so
+// that they take up the same width.
+const SQUARE_SIZE: f32 = 70.0;
 
 const MIN_TIME_BETWEEN_MOVES: f32 = 1.0;
 const DEFAULT_ANIMATION_LENGTH: f32 = 0.22;
@@ -71,9 +77,10 @@ impl EventHandler for Game {
     fn update(&mut self, ctx: &mut Context) -> GameResult<()> {
         // If we need to do some screen transition, then do it
         match &mut self.transition {
-            ScreenTransition::StartGame(ai_white, ai_black) => {
+            ScreenTransition::StartGame(ai_white, ai_black, variant) => {
                 self.grid.set_ais(ai_white.take(), ai_black.take());
-                self.grid.new_game();
+                self.grid.variant = variant.clone();
+                self.grid.new_game(ctx);
                 self.screen = ScreenState::InGame;
             }
             ScreenTransition::ToTitleScreen => {
@@ -242,6 +249,9 @@ pub struct TitleScreen {
     start_game: Button,
     white_selector: Selector,
     black_selector: Selector,
+    variant_selector: Selector,
+    /// The variants which can be picked with `variant_selector`
+    variants: Vec<Arc<dyn Variant>>,
     quit_game: Button,
     title: TextBox,
 }
@@ -263,6 +273,15 @@ impl TitleScreen {
         let mut black_selector = Selector::new(buttons.clone());
         let mut white_selector = Selector::new(buttons);
 
+        let variants = variant::all_variants();
+        let mut variant_selector = Selector::new(
+            variants
+                .iter()
+                .map(|variant| text(variant.name(), font, 30.0))
+                .map(|text| Button::fit_to_text(ctx, (150.0, 35.0), text))
+                .collect(),
+        );
+
         let mut white_selector_stack = VStack {
             pos: mint::Point2 { x: 0.0, y: 0.0 },
             children: &mut white_selector.buttons,
@@ -286,6 +305,19 @@ impl TitleScreen {
             FlexBox::new(1.0);
         };
 
+        let mut variant_selector_stack = HStack {
+            pos: mint::Point2 { x: 0.0, y: 0.0 },
+            children: &mut variant_selector.buttons,
+            min_dimensions: (None, None),
+        };
+        let mut variant_stack: HStack<&mut dyn Layout> = hstack! {
+            Some(SCREEN_WIDTH), None =>
+            FlexBox::new(1.0);
+            variant_selector_stack;
+            FlexBox::new(1.0);
+        };
+        let mut variant_padding = rect::from_dims((1.0, 25.0));
+
         let mut padding = rect::from_dims((1.0, SCREEN_HEIGHT * 0.10));
         let mut start_game =
             Button::fit_to_text(ctx, (300.0, 35.0), text("Start Game", font, 30.0));
@@ -297,6 +329,8 @@ impl TitleScreen {
             title;
             upper_padding;
             selector_stack;
+            variant_padding;
+            variant_stack;
             padding;
             start_game;
             padding2;
@@ -312,6 +346,8 @@ impl TitleScreen {
             title,
             white_selector,
             black_selector,
+            variant_selector,
+            variants,
             start_game,
             quit_game,
         }
@@ -322,6 +358,7 @@ impl TitleScreen {
         self.quit_game.upd8(ctx);
         self.white_selector.upd8(ctx);
         self.black_selector.upd8(ctx);
+        self.variant_selector.upd8(ctx);
     }
 
     fn mouse_up_upd8(
@@ -344,7 +381,8 @@ impl TitleScreen {
                 2 => Some(Box::new(TreeSearchPlayer::new(HARD_AI_MAX_DEPTH))),
                 _ => unreachable!(),
             };
-            *screen_transition = ScreenTransition::StartGame(white_ai, black_ai);
+            let variant = self.variants[self.variant_selector.selected].clone();
+            *screen_transition = ScreenTransition::StartGame(white_ai, black_ai, variant);
         }
 
         if self.quit_game.pressed(mouse_pos) {
@@ -356,6 +394,7 @@ impl TitleScreen {
         self.title.draw(ctx)?;
         self.white_selector.draw(ctx)?;
         self.black_selector.draw(ctx)?;
+        self.variant_selector.draw(ctx)?;
         self.start_game.draw(ctx)?;
         self.quit_game.draw(ctx)?;
 
@@ -367,7 +406,13 @@ impl TitleScreen {
 #[derive(Debug)]
 pub enum ScreenTransition {
     None,
-    StartGame(Option<Box<dyn AIPlayer>>, Option<Box<dyn AIPlayer>>),
+    /// Start a game of the given variant with the given AIs for White and
+    /// Black (None for a human player).
+    StartGame(
+        Option<Box<dyn AIPlayer>>,
+        Option<Box<dyn AIPlayer>>,
+        Arc<dyn Variant>,
+    ),
     ToTitleScreen,
     QuitGame,
 }
@@ -389,50 +434,57 @@ pub struct Grid {
     ai_black: Option<Box<dyn AIPlayer>>,
     ai_white: Option<Box<dyn AIPlayer>>,
     time_since_last_move: f32,
+    // If Some, then a human player has moved a pawn to the last rank and is
+    // choosing which piece to promote it to.
+    pending_promotion: Option<Move>,
+    // If Some, then a human player has picked this piece from their pocket (in
+    // Crazyhouse) and the next square they click on is where it is dropped.
+    pending_drop: Option<PieceType>,
+    // The variant new games are played with
+    variant: Arc<dyn Variant>,
     // Handles drawing the sidebar UI
     sidebar: GameSidebar,
 }
 
 impl Grid {
     fn new(ctx: &mut Context, ext_ctx: &mut ExtendedContext) -> Grid {
-        use PieceType::*;
-        let square_size = 70.0;
+        let square_size = SQUARE_SIZE;
         let font = ext_ctx.font;
 
         let button_size = (40.0, 35.0);
-        let promote_buttons = vec![
-            (
-                Button::fit_to_text(ctx, button_size, text(QUEEN_STR, font, 40.0)),
-                Queen,
-            ),
-            (
-                Button::fit_to_text(ctx, button_size, text(ROOK_STR, font, 40.0)),
-                Rook,
-            ),
-            (
-                Button::fit_to_text(ctx, button_size, text(BISHOP_STR, font, 40.0)),
-                Bishop,
-            ),
-            (
-                Button::fit_to_text(ctx, button_size, text(KNIGHT_STR, font, 40.0)),
-                Knight,
-            ),
-        ];
+        let mut piece_buttons = |pieces: &[PieceType]| -> Vec<(Button, PieceType)> {
+            pieces
+                .iter()
+                .map(|&piece| {
+                    let text = text(piece.as_str(), font, 40.0);
+                    (Button::fit_to_text(ctx, button_size, text), piece)
+                })
+                .collect()
+        };
+        // A button for every piece any variant may promote to. Only the ones
+        // the variant being played allows are shown.
+        let promote_buttons = piece_buttons(&Pocket::PIECES[..Pocket::PIECES.len() - 1]);
+        let drop_buttons = piece_buttons(&Pocket::PIECES);
         let board = BoardState::new(Board::default());
+        let dimensions = board.board.dimensions();
         let offset: na::Vector2<f32> = na::Vector2::new(DONTCARE, DONTCARE);
         let mut grid = Grid {
             grid: BoardView {
                 square_size,
+                dimensions,
                 offset,
                 drop_locations: vec![],
                 animated_board: AnimatedBoard::new(&board, square_size, offset),
-                background_mesh: BoardView::background_mesh(ctx, square_size),
+                background_mesh: BoardView::background_mesh(ctx, square_size, dimensions),
                 last_move: None,
             },
             board,
             ai_black: None,
             ai_white: None,
             time_since_last_move: 0.0,
+            pending_promotion: None,
+            pending_drop: None,
+            variant: Arc::new(variant::Standard),
             sidebar: GameSidebar {
                 restart: Button::fit_to_text(
                     ctx,
@@ -444,8 +496,24 @@ impl Grid {
                     (100.0, 35.0),
                     text("Main Menu", font, DEFAULT_SCALE),
                 ),
+                claim_draw: Button::fit_to_text(
+                    ctx,
+                    (100.0, 35.0),
+                    text("Claim Draw", font, DEFAULT_SCALE),
+                ),
+                draw_claimable: false,
+                resign: Button::fit_to_text(
+                    ctx,
+                    (100.0, 35.0),
+                    text("Resign", font, DEFAULT_SCALE),
+                ),
+                can_resign: false,
                 status: TextBox::new((110.0, 100.0)),
                 promote_buttons,
+                promotions: &PROMOTION_PIECES,
+                move_error: None,
+                drop_buttons,
+                pocket: Pocket::default(),
                 dead_black_list: vec![],
                 dead_white_list: vec![],
                 dead_black: TextBox::new((110.0, 100.0)),
@@ -470,16 +538,32 @@ impl Grid {
         let off_y = 10.0;
 
         let button_size = self.sidebar.promote_buttons[0].0.hitbox;
+        // The drop buttons go in the same place as the promotion buttons, since
+        // they are never shown at the same time.
+        let promoting = self.pending_promotion.is_some();
+        let buttons = if promoting {
+            &mut self.sidebar.promote_buttons
+        } else {
+            &mut self.sidebar.drop_buttons
+        };
+        let (promotions, pocket) = (self.sidebar.promotions, self.sidebar.pocket);
         let mut layout_buttons = vec![];
-        for (button, _) in self.sidebar.promote_buttons.iter_mut() {
-            layout_buttons.push(button);
+        for (button, piece) in buttons.iter_mut() {
+            let shown = if promoting {
+                promotions.contains(piece)
+            } else {
+                pocket.count(*piece) > 0
+            };
+            if shown {
+                layout_buttons.push(button);
+            }
         }
 
         let mut grid = Rect::new(
             off_x,
             off_y,
-            8.0 * self.grid.square_size,
-            8.0 * self.grid.square_size,
+            self.grid.dimensions.files as f32 * self.grid.square_size,
+            self.grid.dimensions.ranks as f32 * self.grid.square_size,
         );
         let mut button_stack = HStack {
             pos: mint::Point2 { x: 0.0, y: 0.0 },
@@ -491,6 +575,8 @@ impl Grid {
             None, None =>
             self.sidebar.restart;
             self.sidebar.main_menu;
+            self.sidebar.claim_draw;
+            self.sidebar.resign;
         };
 
         // Same size as the buttons, but used as padding
@@ -555,7 +641,7 @@ impl Grid {
         self.grid.animated_board.offset = na::Vector2::new(grid.x, grid.y);
     }
 
-    fn new_game(&mut self) {
+    fn new_game(&mut self, ctx: &mut Context) {
         // let board = vec![
         //     "BR .. .. .. BK .. .. BR",
         //     "BP BP BP BP BP BP BP BP",
@@ -567,14 +653,28 @@ impl Grid {
         //     "WR .. .. .. WK .. .. WR",
         // ];
         // let board = Board::from_string_vec(board);
-        let board = Board::default();
-        self.board = BoardState::new(board);
+        self.board = BoardState::new_with_variant(self.variant.clone());
         self.time_since_last_move = 0.0;
-        self.grid.new_game(&self.board);
+        self.pending_promotion = None;
+        self.pending_drop = None;
+        self.sidebar.move_error = None;
+        self.grid.new_game(ctx, &self.board);
     }
 
     fn upd8(&mut self, ctx: &mut Context, ext_ctx: &mut ExtendedContext) {
         self.sidebar.upd8(ctx, ext_ctx, &self.board);
+        // Only human players get to decide if they want to claim a draw
+        self.sidebar.draw_claimable =
+            self.current_player_is_human() && self.board.claimable_draw().is_some();
+        // Human players may resign on their turn
+        self.sidebar.can_resign = self.current_player_is_human() && !self.board.game_over();
+        // Only human players get to pick pieces to drop
+        self.sidebar.pocket = if self.current_player_is_human() {
+            *self.board.pocket(self.board.current_player)
+        } else {
+            Pocket::default()
+        };
+        self.sidebar.promotions = self.board.variant().promotion_pieces();
 
         // Take AI turn, if it isn't game over and it has been at least MIN_TIME_BETWEEN_MOVES
         if !self.board.game_over() {
@@ -585,35 +685,23 @@ impl Grid {
             // If we have an AI and the AI is ready, take the move if we have waited some
             // minimum time. This is done to limit fast AIs from spam moving
             if let Some(ai) = ai {
-                if let std::task::Poll::Ready((start, end)) =
-                    ai.next_move(&self.board, self.board.current_player)
+                // In variants such as Fog of War, the AI only gets to see what
+                // its player can see.
+                let fogged;
+                let view = if self.board.variant().hides_pieces() {
+                    fogged = self.board.fogged(self.board.current_player);
+                    &fogged
+                } else {
+                    &self.board
+                };
+                if let std::task::Poll::Ready(the_move) =
+                    ai.next_move(view, self.board.current_player)
                 {
                     if self.time_since_last_move >= MIN_TIME_BETWEEN_MOVES {
                         self.board
-                            .check_turn(start, end)
+                            .check_turn(the_move)
                             .expect("AI made an illegal move");
-                        self.take_turn(ctx, start, end);
-                    }
-                }
-            }
-            // We have to do this reborrow here because rust can't seem to figure out
-            // the lifetimes otherwise (it complains that reusing `ai` down here would
-            // result in a double mutable borrow, so we do this to end the lifetime of
-            // `ai` early)
-            let ai = match self.board.current_player {
-                Color::White => &mut self.ai_white,
-                Color::Black => &mut self.ai_black,
-            };
-            if let Some(ai) = ai {
-                // If this move would require the AI to promote a piece, then ask
-                // the AI to promote the piece.
-                if let Some(coord) = self.board.need_promote() {
-                    let piece = ai.next_promote(&self.board);
-                    if let std::task::Poll::Ready(piece) = piece {
-                        self.board
-                            .check_promote(coord, piece)
-                            .expect("AI made an illegal promote");
-                        Self::promote(&mut self.board, &mut self.grid, coord, piece);
+                        self.take_turn(ctx, the_move);
                     }
                 }
             }
@@ -628,7 +716,7 @@ impl Grid {
     }
 
     fn mouse_down_upd8(&mut self, mouse_pos: mint::Point2<f32>) {
-        if self.current_player_is_human() {
+        if self.current_player_is_human() && self.pending_drop.is_none() {
             self.grid.upd8_drop_locations(mouse_pos, &self.board)
         }
     }
@@ -643,16 +731,55 @@ impl Grid {
         use UIState::*;
         match self.ui_state() {
             Normal => {
-                if self.current_player_is_human() {
+                let chosen_drop = self
+                    .sidebar
+                    .drop_buttons
+                    .iter()
+                    .find(|(button, piece)| {
+                        self.sidebar.pocket.count(*piece) > 0 && button.pressed(mouse.pos)
+                    })
+                    .map(|&(_, piece)| piece);
+                if self.sidebar.draw_claimable && self.sidebar.claim_draw.pressed(mouse.pos) {
+                    self.board.claim_draw();
+                } else if self.sidebar.can_resign && self.sidebar.resign.pressed(mouse.pos) {
+                    self.board.resign(self.board.current_player);
+                } else if chosen_drop.is_some() {
+                    self.pending_drop = chosen_drop;
+                } else if let Some(piece) = self.pending_drop.take() {
+                    // Drop the chosen piece on the square that was clicked
+                    if let Ok(end) = self.grid.to_grid_coord(mouse.pos) {
+                        let the_move = Move::drop(piece, end);
+                        if self.board.check_turn(the_move).is_ok() {
+                            self.take_turn(ctx, the_move);
+                        }
+                    }
+                } else if self.current_player_is_human() {
                     // On a mouse up, try moving the held piece to the current mouse position
                     let dragging = self.grid.to_grid_coord(mouse.last_down.unwrap());
                     let drop_loc = self.grid.to_grid_coord(mouse.pos);
                     // If both the drag point and drop point are within the grid
                     if let (Ok(start), Ok(end)) = (dragging, drop_loc) {
-                        if self.board.check_turn(start, end).is_ok() {
-                            // We don't ratelimit how fast humans can move since it's really unlikely they'll
-                            // move too fast for the other player to see
-                            self.take_turn(ctx, start, end);
+                        let the_move = Move::new(&self.board.board, start, end, None);
+                        if self.board.board.is_promotion(start, end) {
+                            // Ask which piece to promote to before making the move
+                            let queen_promotion = Move {
+                                promotion: Some(PieceType::Queen),
+                                ..the_move
+                            };
+                            match self.board.check_turn(queen_promotion) {
+                                Ok(()) => self.pending_promotion = Some(the_move),
+                                Err(err) => self.sidebar.move_error = Some(err),
+                            }
+                        } else if start != end {
+                            // A click without a drag isn't a move, so it isn't
+                            // reported as rejected
+                            match self.board.check_turn(the_move) {
+                                // We don't ratelimit how fast humans can move since it's really unlikely they'll
+                                // move too fast for the other player to see
+                                Ok(()) => self.take_turn(ctx, the_move),
+                                // Tell the player why their move was rejected
+                                Err(err) => self.sidebar.move_error = Some(err),
+                            }
                         }
                     }
                 }
@@ -660,7 +787,7 @@ impl Grid {
             GameOver => {
                 if self.sidebar.restart.pressed(mouse.pos) {
                     ext_ctx.particles.clear();
-                    self.new_game();
+                    self.new_game(ctx);
                 }
 
                 if self.sidebar.main_menu.pressed(mouse.pos) {
@@ -668,30 +795,48 @@ impl Grid {
                     *transition = ScreenTransition::ToTitleScreen;
                 }
             }
-            Promote(coord) => {
-                for (button, piece) in &self.sidebar.promote_buttons {
-                    if button.pressed(mouse.pos) {
-                        Self::promote(&mut self.board, &mut self.grid, coord, *piece);
-                    }
+            Promote(the_move) => {
+                let chosen = self
+                    .sidebar
+                    .promote_buttons
+                    .iter()
+                    .find(|(button, piece)| {
+                        self.sidebar.promotions.contains(piece) && button.pressed(mouse.pos)
+                    })
+                    .map(|&(_, piece)| piece);
+                // Some variants don't allow every piece to be promoted to
+                let the_move = chosen.map(|piece| Move {
+                    promotion: Some(piece),
+                    ..the_move
+                });
+                if let Some(the_move) = the_move.filter(|&mv| self.board.check_turn(mv).is_ok()) {
+                    self.pending_promotion = None;
+                    self.take_turn(ctx, the_move);
                 }
             }
         }
         if DEBUG_RESTART && self.sidebar.restart.pressed(mouse.pos) {
             ext_ctx.particles.clear();
-            self.new_game();
+            self.new_game(ctx);
         }
         self.grid.drop_locations = vec![];
+        // Show where the chosen piece may be dropped
+        if let Some(piece) = self.pending_drop {
+            self.grid.drop_locations = self
+                .board
+                .legal_moves()
+                .into_iter()
+                .filter(|mv| mv.drop == Some(piece))
+                .map(|mv| mv.end)
+                .collect();
+        }
     }
 
-    fn promote(board: &mut BoardState, grid: &mut BoardView, coord: BoardCoord, piece: PieceType) {
-        grid.promote(coord, piece);
-        board.promote(coord, piece);
-    }
-
-    // Move the piece from start to end and update the last move/animation boards
-    fn take_turn(&mut self, _ctx: &mut Context, start: BoardCoord, end: BoardCoord) {
+    // Make the move and update the last move/animation boards
+    fn take_turn(&mut self, _ctx: &mut Context, the_move: Move) {
+        self.sidebar.move_error = None;
         // In the event of a capture, add the piece to the appropriate list
-        match move_type_coords(&self.board.board, start, end) {
+        match move_type_coords(&self.board.board, the_move.start, the_move.end) {
             MoveTypeCoords::Capture { end: capture, .. }
             | MoveTypeCoords::EnPassant {
                 captured_pawn: capture,
@@ -712,8 +857,27 @@ impl Grid {
 
         // Update the view first here because we want it to work off of the state
         // of board _before_ we make the actual move
-        self.grid.take_turn(&self.board, start, end);
-        self.board.take_turn(start, end);
+        self.grid.take_turn(&self.board, the_move);
+        self.board.take_turn(the_move);
+
+        // In Atomic chess, a capture also blows up the capturing piece and the
+        // pieces around it.
+        if let Some(entry) = self.board.history().last() {
+            if let Some(explosion) = entry.explosion {
+                let capturer = entry.promotion.unwrap_or(entry.moved.piece);
+                let exploded = explosion
+                    .pieces()
+                    .map(|(_, piece)| (piece.color, piece.piece));
+                for (color, piece) in std::iter::once((entry.moved.color, capturer)).chain(exploded)
+                {
+                    match color {
+                        Color::Black => self.sidebar.dead_black_list.push(piece),
+                        Color::White => self.sidebar.dead_white_list.push(piece),
+                    }
+                }
+                self.grid.animated_board.explode(&explosion);
+            }
+        }
 
         // Set the time since the last move so the AI does not move immediately.
         self.time_since_last_move = 0.0;
@@ -735,7 +899,30 @@ impl Grid {
     }
 
     fn draw_pieces(&self, ctx: &mut Context, ext_ctx: &ExtendedContext) -> GameResult<()> {
-        self.grid.animated_board.draw(ctx, ext_ctx)
+        self.grid.animated_board.draw(ctx, ext_ctx)?;
+        // The fog goes over everything else, so that nothing moving through a
+        // hidden square gives it away.
+        if let Some(viewer) = self.fog_viewer() {
+            self.grid
+                .draw_fog(ctx, self.board.board.visible_squares(viewer))?;
+        }
+        Ok(())
+    }
+
+    /// Returns the player whose view of the board is shown, if the variant
+    /// hides pieces. With one human player, this is always them. In hot-seat
+    /// games it is whoever is moving, and when two AIs play each other (or
+    /// once the game is over) the whole board is shown.
+    fn fog_viewer(&self) -> Option<Color> {
+        if !self.board.variant().hides_pieces() || self.board.game_over() {
+            return None;
+        }
+        match (self.ai_white.is_none(), self.ai_black.is_none()) {
+            (true, true) => Some(self.board.current_player),
+            (true, false) => Some(Color::White),
+            (false, true) => Some(Color::Black),
+            (false, false) => None,
+        }
     }
 
     /// Returns true if the current player is a human player
@@ -748,9 +935,9 @@ impl Grid {
 
     /// Get the current UIState based on if it's game over or if a piece needs to be promoted
     fn ui_state(&self) -> UIState {
-        match (self.board.game_over(), self.board.need_promote()) {
+        match (self.board.game_over(), self.pending_promotion) {
             (false, None) => UIState::Normal,
-            (false, Some(coord)) => UIState::Promote(coord),
+            (false, Some(the_move)) => UIState::Promote(the_move),
             (true, _) => UIState::GameOver,
         }
     }
@@ -759,7 +946,7 @@ impl Grid {
 #[derive(PartialEq, Eq, Copy, Clone, Debug)]
 enum UIState {
     Normal,
-    Promote(BoardCoord),
+    Promote(Move),
     GameOver,
 }
 
@@ -767,6 +954,8 @@ enum UIState {
 struct BoardView {
     // Size of a single square, in pixels
     square_size: f32,
+    // The number of files and ranks of the board being drawn
+    dimensions: Dimensions,
     // Offset of the entire screen from the upper left.
     offset: na::Vector2<f32>,
     // The list of locations the currently held piece can be placed. If this
@@ -786,9 +975,17 @@ struct BoardView {
 /// This struct mostly handles drawing the chess board, its pieces, square
 /// highlights, and handling screenspace/boardspace convesions
 impl BoardView {
-    fn new_game(&mut self, board: &BoardState) {
+    fn new_game(&mut self, ctx: &mut Context, board: &BoardState) {
         self.drop_locations = vec![];
         self.last_move = None;
+        // The variant may be played on a board of a different size, so the
+        // checkerboard is rebuilt to fit it.
+        let dimensions = board.board.dimensions();
+        if dimensions != self.dimensions {
+            self.dimensions = dimensions;
+            self.square_size = SQUARE_SIZE * 8.0 / dimensions.files.max(8) as f32;
+            self.background_mesh = BoardView::background_mesh(ctx, self.square_size, dimensions);
+        }
         self.animated_board = AnimatedBoard::new(board, self.square_size, self.offset);
     }
 
@@ -798,7 +995,7 @@ impl BoardView {
     }
 
     fn upd8_drop_locations(&mut self, mouse_pos: mint::Point2<f32>, board: &BoardState) {
-        let coord = to_grid_coord(self.square_size, self.offset, mouse_pos);
+        let coord = self.to_grid_coord(mouse_pos);
         match coord {
             Err(_) => {
                 self.drop_locations = vec![];
@@ -837,7 +1034,7 @@ impl BoardView {
 
         // If king in check, draw it in red.
         if let Some(coord) = king_coord {
-            if board.checkmate != CheckmateState::Normal {
+            if let CheckmateState::Check | CheckmateState::Checkmate = board.checkmate {
                 let offset = self.to_screen_coord(coord) + self.offset;
                 graphics::draw(ctx, &solid_rect, (offset, color::RED))?;
             }
@@ -864,7 +1061,7 @@ impl BoardView {
         }
 
         // Color the currently highlighted square red if it is the player's piece or if the player is dragging it
-        let pos = to_grid_coord(self.square_size, self.offset, mouse.pos).ok();
+        let pos = self.to_grid_coord(mouse.pos).ok();
         if let Some(coord) = pos {
             let same_color = board.get(coord).is_color(board.current_player);
             let is_dragging = mouse.dragging.is_some();
@@ -879,7 +1076,7 @@ impl BoardView {
 
         // Color the dragged square green
         if let Some(dragging) = mouse.dragging {
-            let dragging = to_grid_coord(self.square_size, self.offset, dragging);
+            let dragging = self.to_grid_coord(dragging);
             if let Ok(coord) = dragging {
                 let offset: na::Point2<f32> = self.to_screen_coord(coord) + self.offset;
                 graphics::draw(ctx, &hollow_rect, (offset, color::GREEN))?;
@@ -889,12 +1086,36 @@ impl BoardView {
         Ok(())
     }
 
+    /// Cover every square which isn't `visible` in fog.
+    fn draw_fog(&self, ctx: &mut Context, visible: Bitboard) -> GameResult<()> {
+        const FOG: graphics::Color = graphics::Color::new(0.1, 0.1, 0.12, 1.0);
+        let hidden = !visible & self.dimensions.area();
+        // An empty mesh can't be built
+        if hidden == bitboard::EMPTY {
+            return Ok(());
+        }
+
+        let fill: graphics::DrawMode = graphics::DrawMode::fill();
+        let mut mesh = graphics::MeshBuilder::new();
+        for square in bitboard::squares(hidden) {
+            let pos = self.to_screen_coord(bitboard::coord(square));
+            let rect = Rect::new(pos.x, pos.y, self.square_size, self.square_size);
+            mesh.rectangle(fill, rect, FOG);
+        }
+        let mesh = mesh.build(ctx)?;
+        graphics::draw(ctx, &mesh, (na::Point2::from(self.offset),))
+    }
+
     /// Construct the checkerboard mesh background
-    fn background_mesh(ctx: &mut Context, square_size: f32) -> graphics::Mesh {
+    fn background_mesh(
+        ctx: &mut Context,
+        square_size: f32,
+        dimensions: Dimensions,
+    ) -> graphics::Mesh {
         let mut mesh = graphics::MeshBuilder::new();
         let fill: graphics::DrawMode = graphics::DrawMode::fill();
-        for i in 0..8 {
-            for j in 0..8 {
+        for i in 0..dimensions.ranks {
+            for j in 0..dimensions.files {
                 let rect = Rect::new(
                     j as f32 * square_size,
                     i as f32 * square_size,
@@ -912,23 +1133,36 @@ impl BoardView {
         mesh.build(ctx).unwrap()
     }
 
-    fn promote(&mut self, coord: BoardCoord, piece: PieceType) {
-        self.last_move = Some((coord, coord));
-        self.animated_board.promote(coord, piece);
-    }
-
-    // Move the piece from start to end and update the last move/animation boards
-    fn take_turn(&mut self, board: &BoardState, start: BoardCoord, end: BoardCoord) {
-        self.last_move = Some((start, end));
-        self.animated_board.take_turn(board, start, end);
+    // Make the move and update the last move/animation boards
+    fn take_turn(&mut self, board: &BoardState, the_move: Move) {
+        self.last_move = Some((the_move.start, the_move.end));
+        if let Some(piece) = the_move.drop {
+            self.animated_board
+                .drop_piece(board.current_player, piece, the_move.end);
+            return;
+        }
+        self.animated_board
+            .take_turn(board, the_move.start, the_move.end);
+        if let Some(piece) = the_move.promotion {
+            self.animated_board.promote(the_move.end, piece);
+        }
     }
 
     fn to_grid_coord(&self, screen_coords: mint::Point2<f32>) -> Result<BoardCoord, &'static str> {
-        to_grid_coord(self.square_size, self.offset, screen_coords)
+        to_grid_coord(
+            self.square_size,
+            self.dimensions,
+            self.offset,
+            screen_coords,
+        )
     }
 
     fn to_screen_coord(&self, board_coord: BoardCoord) -> na::Point2<f32> {
-        na::Point2::from(to_screen_coord(self.square_size, board_coord))
+        na::Point2::from(to_screen_coord(
+            self.square_size,
+            self.dimensions,
+            board_coord,
+        ))
     }
 }
 
@@ -985,13 +1219,14 @@ enum AnimationType {
 #[derive(Debug)]
 struct AnimatedBoard {
     square_size: f32,
+    dimensions: Dimensions,
     offset: na::Vector2<f32>,
     // A hashmap of all of the _alive_ pieces on the board and their current position
     // Note that this hash map stores indicies into `pieces`.
     coords: HashMap<BoardCoord, usize>,
-    // The actual pieces. This vector should not be changed after initalization
-    // Piece which end up "dead" should not be removed from this vector, instead
-    // remove it from coords.
+    // The actual pieces. Pieces should only be added to this vector (when a
+    // piece is dropped in Crazyhouse). Piece which end up "dead" should not be
+    // removed from this vector, instead remove it from coords.
     pieces: Vec<AnimatedPiece>,
     event_queue: BinaryHeap<AnimationEvent>,
 }
@@ -1000,35 +1235,22 @@ impl AnimatedBoard {
     fn new(board: &BoardState, square_size: f32, offset: na::Vector2<f32>) -> AnimatedBoard {
         let mut coords = HashMap::with_capacity(32);
         let mut pieces = Vec::with_capacity(32);
-        for i in 0..8 {
-            for j in 0..8 {
-                let coord = BoardCoord::new((i, j)).expect("Expected a valid BoardCoord");
-                if let Some(piece) = board.get(coord).0 {
-                    let end = to_screen_coord(square_size, coord);
-                    let start = match piece.color {
-                        Color::Black => mint::Point2::<f32> {
-                            x: end.x,
-                            y: -100.0,
-                        },
-                        Color::White => mint::Point2::<f32> {
-                            x: end.x,
-                            y: SCREEN_HEIGHT + 100.0,
-                        },
-                    };
-                    let piece = AnimatedPiece::new(
-                        start,
-                        end,
-                        piece,
-                        rand::thread_rng().gen_range(0.4, 0.8),
-                    );
-                    pieces.push(piece);
-                    let id = pieces.len() - 1;
-                    coords.insert(coord, id);
-                }
-            }
+        let dimensions = board.board.dimensions();
+        for (coord, piece) in board.board.iter_pieces() {
+            let end = to_screen_coord(square_size, dimensions, coord);
+            let piece = AnimatedPiece::new(
+                offscreen_start(piece.color, end),
+                end,
+                piece,
+                rand::thread_rng().gen_range(0.4, 0.8),
+            );
+            pieces.push(piece);
+            let id = pieces.len() - 1;
+            coords.insert(coord, id);
         }
         AnimatedBoard {
             square_size,
+            dimensions,
             offset,
             coords,
             pieces,
@@ -1086,6 +1308,20 @@ impl AnimatedBoard {
         Ok(())
     }
 
+    /// Add a piece of `color` which flies in from off the screen to `coord`.
+    fn drop_piece(&mut self, color: Color, piece: PieceType, coord: BoardCoord) {
+        let end = self.to_screen_coord(coord);
+        let piece = Tile::new(color, piece).0.unwrap();
+        let piece = AnimatedPiece::new(
+            offscreen_start(color, end),
+            end,
+            piece,
+            DEFAULT_ANIMATION_LENGTH,
+        );
+        self.pieces.push(piece);
+        self.coords.insert(coord, self.pieces.len() - 1);
+    }
+
     fn promote(&mut self, coord: BoardCoord, piece: PieceType) {
         self.event_queue.push(AnimationEvent {
             action: AnimationType::Change { piece },
@@ -1095,6 +1331,45 @@ impl AnimatedBoard {
         })
     }
 
+    /// Blow up the piece which made the capture at the center of `explosion`
+    /// and the pieces around it, sending the pieces around it flying outwards.
+    fn explode(&mut self, explosion: &Explosion) {
+        let desync = "HashMap did not contain piece--this is probably a desync earlier on";
+        let center = explosion.center;
+        let start_time = Instant::now() + Duration::from_secs_f32(DEFAULT_ANIMATION_LENGTH);
+        self.event_queue.push(AnimationEvent {
+            action: AnimationType::Remove {
+                coord: center,
+                angle: na::Vector2::new(1.0, 0.0),
+                intensity: 60.0,
+                spread: PI * 2.0,
+                num_particles: 50,
+                size: 8,
+            },
+            id: self.coords.remove(&center).expect(desync),
+            animation_duration: DEFAULT_ANIMATION_LENGTH,
+            start_time,
+        });
+
+        for (coord, _) in explosion.pieces() {
+            let angle =
+                self.to_screen_coord_centered(coord) - self.to_screen_coord_centered(center);
+            self.event_queue.push(AnimationEvent {
+                action: AnimationType::Remove {
+                    coord,
+                    angle,
+                    intensity: 45.0,
+                    spread: PI / 3.0,
+                    num_particles: 20,
+                    size: 6,
+                },
+                id: self.coords.remove(&coord).expect(desync),
+                animation_duration: DEFAULT_ANIMATION_LENGTH,
+                start_time,
+            });
+        }
+    }
+
     fn take_turn(&mut self, board: &BoardState, start: BoardCoord, end: BoardCoord) {
         fn move_event(
             coords: &HashMap<BoardCoord, usize>,
@@ -1179,8 +1454,13 @@ impl AnimatedBoard {
                 let rook = move_event(&self.coords, rook_start, rook_end);
                 self.event_queue.push(king);
                 self.event_queue.push(rook);
-                move_piece(&mut self.coords, king_start, king_end);
-                move_piece(&mut self.coords, rook_start, rook_end);
+                // In Chess960, the king may end up where the rook started (or
+                // the other way around), so both are picked up first.
+                let king_id = self.coords.remove(&king_start);
+                let rook_id = self.coords.remove(&rook_start);
+                let desync = "HashMap did not contain piece--this is probably a desync earlier on";
+                self.coords.insert(king_end, king_id.expect(desync));
+                self.coords.insert(rook_end, rook_id.expect(desync));
             }
             EnPassant {
                 start,
@@ -1212,7 +1492,7 @@ impl AnimatedBoard {
     }
 
     fn to_screen_coord(&self, board_coord: BoardCoord) -> mint::Point2<f32> {
-        to_screen_coord(self.square_size, board_coord)
+        to_screen_coord(self.square_size, self.dimensions, board_coord)
     }
 
     fn to_screen_coord_centered(&self, coord: BoardCoord) -> na::Point2<f32> {
@@ -1225,20 +1505,45 @@ impl AnimatedBoard {
 /// on the grid. This function returns Err if the point would be off the grid.
 fn to_grid_coord<V: Into<na::Vector2<f32>>>(
     square_size: f32,
+    dimensions: Dimensions,
     offset: V,
     screen_coords: mint::Point2<f32>,
 ) -> Result<BoardCoord, &'static str> {
     let offset_coords = na::Point2::from(screen_coords) - offset.into();
     let grid_x = (offset_coords.x / square_size).floor() as i8;
     let grid_y = (offset_coords.y / square_size).floor() as i8;
-    BoardCoord::new((grid_x, 7 - grid_y))
+    let coord = BoardCoord::new((grid_x, dimensions.ranks - 1 - grid_y))?;
+    if dimensions.contains(coord) {
+        Ok(coord)
+    } else {
+        Err("Expected a square on the board")
+    }
+}
+
+/// Returns where a piece of `color` starts from when flying in to `end`. This
+/// is just above the screen for Black and just below it for White.
+fn offscreen_start(color: Color, end: mint::Point2<f32>) -> mint::Point2<f32> {
+    match color {
+        Color::Black => mint::Point2::<f32> {
+            x: end.x,
+            y: -100.0,
+        },
+        Color::White => mint::Point2::<f32> {
+            x: end.x,
+            y: SCREEN_HEIGHT + 100.0,
+        },
+    }
 }
 
 /// Returns the upper left corner of the square located at `board_coords`
-fn to_screen_coord(square_size: f32, board_coord: BoardCoord) -> mint::Point2<f32> {
+fn to_screen_coord(
+    square_size: f32,
+    dimensions: Dimensions,
+    board_coord: BoardCoord,
+) -> mint::Point2<f32> {
     mint::Point2 {
         x: board_coord.0 as f32 * square_size,
-        y: (7 - board_coord.1) as f32 * square_size,
+        y: (dimensions.ranks - 1 - board_coord.1) as f32 * square_size,
     }
 }
 
@@ -1324,10 +1629,28 @@ struct GameSidebar {
     // Restart and main menu buttons
     restart: Button,
     main_menu: Button,
+    // Claim draw button. This is only shown if `draw_claimable` is true.
+    claim_draw: Button,
+    draw_claimable: bool,
+    // Resign button, which ends the game with a win for the other player. This
+    // is only shown if `can_resign` is true.
+    resign: Button,
+    can_resign: bool,
     // Promotion buttons. Note that this is reused for both white's and black's side
     // and we just move the buttons around as needed. The PieceType tells what
     // piece the pawn will promote to.
     promote_buttons: Vec<(Button, PieceType)>,
+    // Why the last move a human player tried was illegal, if it was. This is
+    // cleared once a move is made.
+    move_error: Option<MoveError>,
+    // The pieces the variant being played allows a pawn to promote to. Only
+    // their promotion buttons are shown.
+    promotions: &'static [PieceType],
+    // Buttons for picking a piece to drop, in Crazyhouse. These are in the same
+    // place as the promotion buttons. Only the pieces in `pocket` are shown.
+    drop_buttons: Vec<(Button, PieceType)>,
+    // The pocket of the player-to-move, if they are human. Otherwise, empty.
+    pocket: Pocket,
     // Displays who's turn it is and if there is check/checkmate/etc or not
     status: TextBox,
     dead_black_list: Vec<PieceType>,
@@ -1341,51 +1664,77 @@ impl GameSidebar {
         // Update status message
         let player_str = board.current_player.as_str();
 
-        let status_text = match board.checkmate {
-            CheckmateState::Stalemate => "The game has ended!\nStalemate!".to_owned(),
-            CheckmateState::InsuffientMaterial => {
-                "The game has ended!\nInsuffient material!".to_owned()
-            }
-            CheckmateState::Checkmate => {
-                ["The game has ended!\n", player_str, " is in checkmate!"].concat()
-            }
-            CheckmateState::Check => [player_str, " is in check!"].concat(),
-            CheckmateState::Normal => [player_str, " to move."].concat(),
+        let mut status_text = match (board.outcome(), board.checkmate) {
+            (Some(outcome), _) => format!("The game has ended!\n{}!", outcome),
+            (None, CheckmateState::Check) => [player_str, " is in check!"].concat(),
+            (None, _) => [player_str, " to move."].concat(),
         };
+        if let Some(err) = self.move_error {
+            status_text.push_str(&format!("\n{}.", err));
+        }
+        // In Three-check, show how many checks each player has given.
+        if board.variant().counts_checks() {
+            status_text.push_str(&format!(
+                "\nChecks: White {}, Black {}",
+                board.checks_given(Color::White),
+                board.checks_given(Color::Black)
+            ));
+        }
         self.status.text = text(status_text, ext_ctx.font, 25.0);
 
-        self.dead_black.text = text(
-            piece_slice_to_str(&self.dead_black_list),
-            ext_ctx.font,
-            30.0,
-        );
-        self.dead_white.text = text(
-            piece_slice_to_str(&self.dead_white_list),
-            ext_ctx.font,
-            30.0,
-        );
+        // In Crazyhouse, show the pieces each player may drop instead of the
+        // pieces they have lost.
+        let (dead_black, dead_white) = if board.variant().uses_pockets() {
+            (
+                pocket_to_vec(board.pocket(Color::Black)),
+                pocket_to_vec(board.pocket(Color::White)),
+            )
+        } else {
+            (self.dead_black_list.clone(), self.dead_white_list.clone())
+        };
+        self.dead_black.text = text(piece_slice_to_str(&dead_black), ext_ctx.font, 30.0);
+        self.dead_white.text = text(piece_slice_to_str(&dead_white), ext_ctx.font, 30.0);
 
         // Update buttons
         self.main_menu.upd8(ctx);
         self.restart.upd8(ctx);
+        self.claim_draw.upd8(ctx);
+        self.resign.upd8(ctx);
 
         for (button, _) in &mut self.promote_buttons {
             button.upd8(ctx);
         }
+        for (button, _) in &mut self.drop_buttons {
+            button.upd8(ctx);
+        }
     }
 
     fn draw(&self, ctx: &mut Context, ui_state: UIState) -> GameResult<()> {
         // Draw UI buttons, if applicable
         use UIState::*;
         match ui_state {
-            Normal => (),
+            Normal => {
+                if self.draw_claimable {
+                    self.claim_draw.draw(ctx)?;
+                }
+                if self.can_resign {
+                    self.resign.draw(ctx)?;
+                }
+                for (button, piece) in &self.drop_buttons {
+                    if self.pocket.count(*piece) > 0 {
+                        button.draw(ctx)?;
+                    }
+                }
+            }
             GameOver => {
                 self.restart.draw(ctx)?;
                 self.main_menu.draw(ctx)?;
             }
             Promote(_) => {
-                for (button, _) in &self.promote_buttons {
-                    button.draw(ctx)?;
+                for (button, piece) in &self.promote_buttons {
+                    if self.promotions.contains(piece) {
+                        button.draw(ctx)?;
+                    }
                 }
             }
         }
@@ -1402,6 +1751,14 @@ impl GameSidebar {
     }
 }
 
+/// Return a list of the pieces in `pocket`, most valuable first.
+fn pocket_to_vec(pocket: &Pocket) -> Vec<PieceType> {
+    pocket
+        .pieces()
+        .flat_map(|(piece, count)| std::iter::repeat(piece).take(count as usize))
+        .collect()
+}
+
 /// Convert a list of pieces to their string representation.
 fn piece_slice_to_str(pieces: &[PieceType]) -> String {
     let mut string = String::new();
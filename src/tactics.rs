@@ -0,0 +1,452 @@
+//! Finding tactical motifs, such as forks and pins, for annotating positions
+//! and moves. `Board::tactics` finds the motifs in a position, and
+//! `BoardState::move_tactics` finds the ones a move creates. Each `Finding`
+//! has the squares involved, so that they can be highlighted.
+//!
+//! These are meant as hints for a human, not as analysis. A motif being found
+//! doesn't mean it can be taken advantage of, since nothing is searched.
+
+use crate::bitboard::{self, Bitboard};
+use crate::board::*;
+
+/// A tactical motif, and the squares it involves.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Motif {
+    /// The piece on `square` is attacked and nothing defends it.
+    Hanging { square: BoardCoord },
+    /// The piece on `attacker` attacks every piece in `targets`. Each target
+    /// is the king, is worth more than the attacker, or is undefended.
+    Fork {
+        attacker: BoardCoord,
+        targets: Vec<BoardCoord>,
+    },
+    /// The piece on `pinned` can't move without exposing the king on `king` to
+    /// the piece on `pinner`.
+    AbsolutePin {
+        pinner: BoardCoord,
+        pinned: BoardCoord,
+        king: BoardCoord,
+    },
+    /// The piece on `pinned` shouldn't move, since that would expose the more
+    /// valuable piece on `behind` to the piece on `pinner`.
+    RelativePin {
+        pinner: BoardCoord,
+        pinned: BoardCoord,
+        behind: BoardCoord,
+    },
+    /// The piece on `front` is attacked by the piece on `attacker`, and is
+    /// worth more than (or is the king in front of) the piece on `behind`,
+    /// which will be attacked once `front` moves out of the way.
+    Skewer {
+        attacker: BoardCoord,
+        front: BoardCoord,
+        behind: BoardCoord,
+    },
+    /// A move uncovered a check on the king on `king` by the piece on
+    /// `checker`, which didn't move.
+    DiscoveredCheck {
+        checker: BoardCoord,
+        king: BoardCoord,
+    },
+    /// A move uncovered an attack on the piece on `target` by the piece on
+    /// `attacker`, which didn't move.
+    DiscoveredAttack {
+        attacker: BoardCoord,
+        target: BoardCoord,
+    },
+    /// The king on `king` is in check from more than one piece, so it has to
+    /// move.
+    DoubleCheck {
+        checkers: Vec<BoardCoord>,
+        king: BoardCoord,
+    },
+    /// The king on `king` is on its first rank and can't step off of it,
+    /// because the squares in front of it are attacked or blocked by its own
+    /// pieces on `blockers`. A rook or queen reaching the rank may mate.
+    BackRankWeakness {
+        king: BoardCoord,
+        blockers: Vec<BoardCoord>,
+    },
+}
+
+/// A motif found on the board, and the player who may take advantage of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub player: Color,
+    pub motif: Motif,
+}
+
+impl Finding {
+    /// Return every square involved in this finding.
+    pub fn squares(&self) -> Vec<BoardCoord> {
+        use Motif::*;
+        match &self.motif {
+            Hanging { square } => vec![*square],
+            Fork { attacker, targets } => {
+                let mut squares = vec![*attacker];
+                squares.extend(targets);
+                squares
+            }
+            AbsolutePin {
+                pinner,
+                pinned,
+                king,
+            } => vec![*pinner, *pinned, *king],
+            RelativePin {
+                pinner,
+                pinned,
+                behind,
+            } => vec![*pinner, *pinned, *behind],
+            Skewer {
+                attacker,
+                front,
+                behind,
+            } => vec![*attacker, *front, *behind],
+            DiscoveredCheck { checker, king } => vec![*checker, *king],
+            DiscoveredAttack { attacker, target } => vec![*attacker, *target],
+            DoubleCheck { checkers, king } => {
+                let mut squares = checkers.clone();
+                squares.push(*king);
+                squares
+            }
+            BackRankWeakness { king, blockers } => {
+                let mut squares = vec![*king];
+                squares.extend(blockers);
+                squares
+            }
+        }
+    }
+}
+
+impl Board {
+    /// Find the tactical motifs in this position, for both players.
+    pub fn tactics(&self) -> Vec<Finding> {
+        let mut findings = vec![];
+        for &color in [Color::White, Color::Black].iter() {
+            self.hanging_pieces(color, &mut findings);
+            self.forks(color, &mut findings);
+            self.pins_and_skewers(color, &mut findings);
+            self.double_check(color, &mut findings);
+            self.back_rank_weakness(color, &mut findings);
+        }
+        findings
+    }
+
+    /// Find `color`'s pieces which are attacked and undefended.
+    fn hanging_pieces(&self, color: Color, findings: &mut Vec<Finding>) {
+        for (square, piece) in self.iter_pieces() {
+            if piece.color == color && piece.piece != PieceType::King && self.is_hanging(square) {
+                findings.push(Finding {
+                    player: color.opposite(),
+                    motif: Motif::Hanging { square },
+                });
+            }
+        }
+    }
+
+    /// Find `color`'s pieces which attack more than one enemy piece worth
+    /// attacking.
+    fn forks(&self, color: Color, findings: &mut Vec<Finding>) {
+        for (attacker, piece) in self.iter_pieces().filter(|(_, piece)| piece.color == color) {
+            let targets: Vec<BoardCoord> =
+                bitboard::squares(self.attacks_from(attacker) & self.pieces_of(color.opposite()))
+                    .map(bitboard::coord)
+                    .filter(|&target| {
+                        let target_piece = self.get(target).0.unwrap().piece;
+                        target_piece == PieceType::King
+                            || value(target_piece) > value(piece.piece)
+                            || self.attackers(target, color.opposite()) == bitboard::EMPTY
+                    })
+                    .collect();
+            if targets.len() >= 2 {
+                findings.push(Finding {
+                    player: color,
+                    motif: Motif::Fork { attacker, targets },
+                });
+            }
+        }
+    }
+
+    /// Find the pins and skewers made by `color`'s sliding pieces, by looking
+    /// through each enemy piece a slider attacks for the piece behind it.
+    fn pins_and_skewers(&self, color: Color, findings: &mut Vec<Finding>) {
+        let enemies = self.pieces_of(color.opposite());
+        for (slider, piece) in self.iter_pieces().filter(|(_, piece)| piece.color == color) {
+            let square = bitboard::square(slider);
+            let slider_attacks = |occupied: Bitboard| match piece.piece {
+                PieceType::Bishop => self.bishop_attacks(square, occupied),
+                PieceType::Rook => self.rook_attacks(square, occupied),
+                PieceType::Queen => self.queen_attacks(square, occupied),
+                _ => bitboard::EMPTY,
+            };
+            let attacks = slider_attacks(self.occupied());
+            for front in bitboard::squares(attacks & enemies) {
+                // Taking the front piece off the board only extends the
+                // slider's attacks along the line through it.
+                let xray = slider_attacks(self.occupied() & !(1 << front)) & !attacks & enemies;
+                let behind = match bitboard::squares(xray).next() {
+                    Some(behind) => bitboard::coord(behind),
+                    None => continue,
+                };
+                let front = bitboard::coord(front);
+                let front_piece = self.get(front).0.unwrap().piece;
+                let behind_piece = self.get(behind).0.unwrap().piece;
+                // The piece behind is only worth going after if it can't
+                // simply be taken back.
+                let worth_winning = value(behind_piece) > value(piece.piece)
+                    || self.attackers(behind, color.opposite()) == bitboard::EMPTY;
+                let motif = if behind_piece == PieceType::King {
+                    Motif::AbsolutePin {
+                        pinner: slider,
+                        pinned: front,
+                        king: behind,
+                    }
+                } else if value(behind_piece) > value(front_piece) && worth_winning {
+                    Motif::RelativePin {
+                        pinner: slider,
+                        pinned: front,
+                        behind,
+                    }
+                } else if value(front_piece) > value(behind_piece) && worth_winning {
+                    Motif::Skewer {
+                        attacker: slider,
+                        front,
+                        behind,
+                    }
+                } else {
+                    continue;
+                };
+                findings.push(Finding {
+                    player: color,
+                    motif,
+                });
+            }
+        }
+    }
+
+    /// Find if `color`'s king is in check from more than one piece.
+    fn double_check(&self, color: Color, findings: &mut Vec<Finding>) {
+        let checkers = self.checkers(color);
+        if let (Some(king), true) = (self.get_king(color), checkers.count_ones() >= 2) {
+            findings.push(Finding {
+                player: color.opposite(),
+                motif: Motif::DoubleCheck {
+                    checkers: bitboard::squares(checkers).map(bitboard::coord).collect(),
+                    king,
+                },
+            });
+        }
+    }
+
+    /// Find if `color`'s king is stuck on its first rank while the opponent
+    /// has a rook or queen which could mate it there.
+    fn back_rank_weakness(&self, color: Color, findings: &mut Vec<Finding>) {
+        let king = match self.get_king(color) {
+            Some(king) if king.1 == self.dimensions().back_rank(color) => king,
+            _ => return,
+        };
+        let has_heavy_piece = self.iter_pieces().any(|(_, piece)| {
+            piece.color != color && matches!(piece.piece, PieceType::Rook | PieceType::Queen)
+        });
+        let first_rank = bitboard::rank(king.1);
+        let escapes = bitboard::king_attacks(bitboard::square(king)) & self.area() & !first_rank;
+        let blockers = escapes & self.pieces_of(color);
+        let blocked = bitboard::squares(escapes & !blockers).all(|escape| {
+            self.attackers(bitboard::coord(escape), color.opposite()) != bitboard::EMPTY
+        });
+        if has_heavy_piece && escapes != bitboard::EMPTY && blockers != bitboard::EMPTY && blocked {
+            findings.push(Finding {
+                player: color.opposite(),
+                motif: Motif::BackRankWeakness {
+                    king,
+                    blockers: bitboard::squares(blockers).map(bitboard::coord).collect(),
+                },
+            });
+        }
+    }
+
+    /// Return true if the piece on `square` is attacked and undefended.
+    fn is_hanging(&self, square: BoardCoord) -> bool {
+        let color = self.get(square).0.expect("Expected a piece").color;
+        self.attackers(square, color.opposite()) != bitboard::EMPTY
+            && self.attackers(square, color) == bitboard::EMPTY
+    }
+}
+
+impl BoardState {
+    /// Find the tactical motifs the player-to-move creates by making the move
+    /// `mv`: discovered checks and attacks, and every motif they may take
+    /// advantage of after the move which they couldn't before it. The move
+    /// must be legal.
+    pub fn move_tactics(&self, mv: Move) -> Vec<Finding> {
+        let player = self.current_player;
+        let before = &self.board;
+        let mut after = self.clone();
+        after.take_turn(mv);
+        let after = &after.board;
+
+        // The pieces which moved, including the rook when castling
+        let moved = after.pieces_of(player) & !before.pieces_of(player);
+        let enemies = after.pieces_of(player.opposite());
+
+        let mut findings = vec![];
+        for target in bitboard::squares(enemies).map(bitboard::coord) {
+            let target_piece = after.get(target).0.unwrap().piece;
+            let uncovered =
+                after.attackers(target, player) & !moved & !before.attackers(target, player);
+            for attacker in bitboard::squares(uncovered).map(bitboard::coord) {
+                let motif = if target_piece == PieceType::King {
+                    Motif::DiscoveredCheck {
+                        checker: attacker,
+                        king: target,
+                    }
+                } else if value(target_piece) > value(after.get(attacker).0.unwrap().piece)
+                    || after.attackers(target, player.opposite()) == bitboard::EMPTY
+                {
+                    Motif::DiscoveredAttack { attacker, target }
+                } else {
+                    continue;
+                };
+                findings.push(Finding { player, motif });
+            }
+        }
+
+        let old_findings = before.tactics();
+        findings.extend(
+            after
+                .tactics()
+                .into_iter()
+                .filter(|finding| finding.player == player && !old_findings.contains(finding)),
+        );
+        findings
+    }
+}
+
+/// The value of a piece rounded to whole pawns (see `PieceType::value`), so
+/// that a knight and a bishop are worth the same. The king is still worth more
+/// than everything else.
+fn value(piece: PieceType) -> i32 {
+    (piece.value() + 50) / 100
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Motif::*;
+
+    fn coord(square: &str) -> BoardCoord {
+        square.parse().unwrap()
+    }
+
+    fn tactics(fen: &str) -> Vec<Finding> {
+        BoardState::from_fen(fen).unwrap().board.tactics()
+    }
+
+    fn move_tactics(fen: &str, uci: &str) -> Vec<Finding> {
+        let state = BoardState::from_fen(fen).unwrap();
+        state.move_tactics(state.parse_uci_move(uci).unwrap())
+    }
+
+    fn white(motif: Motif) -> Finding {
+        Finding {
+            player: Color::White,
+            motif,
+        }
+    }
+
+    #[test]
+    fn test_hanging_and_forks() {
+        assert_eq!(
+            tactics("4k3/8/8/3n4/8/8/8/3RK3 w - - 0 1"),
+            vec![white(Hanging {
+                square: coord("d5")
+            })]
+        );
+
+        let findings = tactics("r3k3/2N5/8/8/8/8/8/4K3 b - - 0 1");
+        assert!(findings.contains(&white(Fork {
+            attacker: coord("c7"),
+            targets: vec![coord("a8"), coord("e8")],
+        })));
+        assert!(findings.contains(&white(Hanging {
+            square: coord("a8")
+        })));
+        assert_eq!(findings.len(), 2);
+    }
+
+    #[test]
+    fn test_pins_and_skewers() {
+        let findings = tactics("4r1k1/8/8/8/8/8/4B3/4K3 w - - 0 1");
+        assert_eq!(
+            findings,
+            vec![Finding {
+                player: Color::Black,
+                motif: AbsolutePin {
+                    pinner: coord("e8"),
+                    pinned: coord("e2"),
+                    king: coord("e1"),
+                },
+            }]
+        );
+
+        // The knight is pinned to the queen
+        let findings = tactics("3rk3/8/8/8/8/8/3N4/3QK3 w - - 0 1");
+        assert_eq!(
+            findings,
+            vec![Finding {
+                player: Color::Black,
+                motif: RelativePin {
+                    pinner: coord("d8"),
+                    pinned: coord("d2"),
+                    behind: coord("d1"),
+                },
+            }]
+        );
+
+        // The king has to move out of check, leaving the rook behind it
+        let findings = tactics("7r/8/8/4k3/8/8/1B6/K7 b - - 0 1");
+        assert!(findings.contains(&white(Skewer {
+            attacker: coord("b2"),
+            front: coord("e5"),
+            behind: coord("h8"),
+        })));
+    }
+
+    #[test]
+    fn test_back_rank_weakness() {
+        assert_eq!(
+            tactics("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1"),
+            vec![white(BackRankWeakness {
+                king: coord("g8"),
+                blockers: vec![coord("f7"), coord("g7"), coord("h7")],
+            })]
+        );
+        // Without a rook or queen to mate with, there is no weakness
+        assert_eq!(tactics("6k1/5ppp/8/8/8/8/8/B5K1 w - - 0 1"), vec![]);
+    }
+
+    #[test]
+    fn test_move_tactics() {
+        let findings = move_tactics("4k3/8/8/8/4B3/8/8/4R1K1 w - - 0 1", "e4c6");
+        assert!(findings.contains(&white(DiscoveredCheck {
+            checker: coord("e1"),
+            king: coord("e8"),
+        })));
+        assert!(findings.contains(&white(DoubleCheck {
+            checkers: vec![coord("e1"), coord("c6")],
+            king: coord("e8"),
+        })));
+
+        // Moving the knight also pins the queen to the king
+        let findings = move_tactics("4k3/8/4q3/8/8/8/4N3/4R1K1 w - - 0 1", "e2c3");
+        assert!(findings.contains(&white(DiscoveredAttack {
+            attacker: coord("e1"),
+            target: coord("e6"),
+        })));
+        assert!(findings.contains(&white(AbsolutePin {
+            pinner: coord("e1"),
+            pinned: coord("e6"),
+            king: coord("e8"),
+        })));
+    }
+}
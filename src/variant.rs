@@ -0,0 +1,970 @@
+//! Rules for chess variants. A `BoardState` holds the `Variant` it is being
+//! played with, which decides where the pieces start, which moves are legal,
+//! and how the game ends. The default methods of `Variant` are the rules of
+//! standard chess, so a variant only needs to override the rules it changes.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::bitboard::{self, Bitboard};
+use crate::board::*;
+
+/// The rules of a chess variant. Variants are shared between a `BoardState`
+/// and its copies (such as the ones the AI searches with), so they should not
+/// hold any state about a particular game. That belongs in the `BoardState`.
+pub trait Variant: fmt::Debug + Send + Sync {
+    /// The name of the variant, such as "Standard" or "Chess960".
+    fn name(&self) -> &'static str;
+
+    /// The board a new game starts with. White moves first.
+    fn starting_board(&self) -> Board {
+        Board::default()
+    }
+
+    /// Returns true if captured pieces go into the capturer's pocket, from
+    /// where they may be dropped back onto the board, as in Crazyhouse. The
+    /// drops themselves should be added by `extra_moves`.
+    fn uses_pockets(&self) -> bool {
+        false
+    }
+
+    /// Returns true if a capture blows up the capturing piece and every piece
+    /// next to it other than a pawn, as in Atomic chess.
+    fn captures_explode(&self) -> bool {
+        false
+    }
+
+    /// Returns true if the number of checks each player gives is counted, as
+    /// in Three-check. See `BoardState::checks_given`.
+    fn counts_checks(&self) -> bool {
+        false
+    }
+
+    /// Returns true if each player may only see the squares their own pieces
+    /// can move to or attack, as in Fog of War. See `Board::visible_squares`
+    /// and `BoardState::fogged`.
+    fn hides_pieces(&self) -> bool {
+        false
+    }
+
+    /// The squares a player wins by moving their king onto, as in King of the
+    /// Hill. This is empty in variants without such a goal.
+    fn hill(&self) -> Bitboard {
+        bitboard::EMPTY
+    }
+
+    /// The pieces a pawn may be promoted to, most valuable first.
+    fn promotion_pieces(&self) -> &'static [PieceType] {
+        &PROMOTION_PIECES
+    }
+
+    /// Add the moves this variant allows, other than the moves of standard
+    /// chess, to `moves`.
+    fn extra_moves(&self, _state: &BoardState, _moves: &mut Vec<Move>) {}
+
+    /// Check if `mv` is legal in this variant, given that it is either legal
+    /// in standard chess or is one of the `extra_moves`.
+    fn check_move(&self, _state: &BoardState, _mv: Move) -> Result<(), MoveError> {
+        Ok(())
+    }
+
+    /// Remove the moves which `check_move` rejects from `moves`. Variants
+    /// which don't restrict moves should override this to do nothing, so that
+    /// they don't have to check every move.
+    fn filter_moves(&self, state: &BoardState, moves: &mut Vec<Move>) {
+        moves.retain(|&mv| self.check_move(state, mv).is_ok());
+    }
+
+    /// Return whether the player-to-move is in check, checkmate, etc. This is
+    /// called after every move. Draws by repetition and by the fifty- and
+    /// seventy-five-move rules are handled by `BoardState` afterwards, unless
+    /// this returns a state where the game is over.
+    fn checkmate_state(&self, state: &BoardState) -> CheckmateState {
+        state.board.checkmate_state(state.current_player)
+    }
+
+    /// Returns true if `color` could never win, no matter how the game goes
+    /// on. If a player runs out of time, the game is drawn rather than lost
+    /// when this is true of their opponent. See `BoardState::time_out`.
+    fn insufficient_to_win(&self, state: &BoardState, color: Color) -> bool {
+        state.board.insufficient_to_win(color)
+    }
+
+    /// Returns true if, once the game has started, this variant plays by
+    /// exactly the rules of standard chess, meaning it overrides none of the
+    /// methods above other than `name`, `starting_board`, and `filter_moves`
+    /// (to do nothing). `BoardState` then skips those methods when making and
+    /// generating moves, which saves a lot of time in the AI's search.
+    fn standard_rules(&self) -> bool {
+        false
+    }
+
+    /// Return the winner of the game, or None if the game is drawn or is not
+    /// over yet.
+    fn winner(&self, state: &BoardState) -> Option<Color> {
+        match state.checkmate {
+            CheckmateState::Checkmate => Some(state.current_player.opposite()),
+            CheckmateState::VariantEnd { winner, .. } => winner,
+            _ => None,
+        }
+    }
+}
+
+/// The normal rules of chess.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Standard;
+
+impl Variant for Standard {
+    fn name(&self) -> &'static str {
+        "Standard"
+    }
+
+    fn filter_moves(&self, _state: &BoardState, _moves: &mut Vec<Move>) {}
+
+    fn standard_rules(&self) -> bool {
+        true
+    }
+}
+
+/// Chess960, also known as Fischer Random Chess. The rules are the same as in
+/// standard chess (castling works for any starting files of the king and
+/// rooks), but each game starts from a random one of 960 positions. See
+/// `Board::chess960`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chess960;
+
+impl Variant for Chess960 {
+    fn name(&self) -> &'static str {
+        "Chess960"
+    }
+
+    fn starting_board(&self) -> Board {
+        let index = rand::thread_rng().gen_range(0, 960);
+        Board::chess960(index).expect("Expected a valid Chess960 position")
+    }
+
+    fn filter_moves(&self, _state: &BoardState, _moves: &mut Vec<Move>) {}
+
+    fn standard_rules(&self) -> bool {
+        true
+    }
+}
+
+/// Crazyhouse, where captured pieces go into the capturer's pocket and may be
+/// dropped back onto any empty square instead of making a move. Pawns can't be
+/// dropped on the first or last rank, and promoted pieces turn back into pawns
+/// when captured. See https://en.wikipedia.org/wiki/Crazyhouse
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crazyhouse;
+
+impl Variant for Crazyhouse {
+    fn name(&self) -> &'static str {
+        "Crazyhouse"
+    }
+
+    fn uses_pockets(&self) -> bool {
+        true
+    }
+
+    fn extra_moves(&self, state: &BoardState, moves: &mut Vec<Move>) {
+        let pocket = state.pocket(state.current_player);
+        if pocket.is_empty() || state.board.pawn_needs_promotion().is_some() {
+            return;
+        }
+
+        let targets = state.board.drop_targets(state.current_player);
+        let last_ranks = bitboard::rank(0) | bitboard::rank(state.board.dimensions().ranks - 1);
+        for (piece, _) in pocket.pieces() {
+            let targets = match piece {
+                PieceType::Pawn { .. } => targets & !last_ranks,
+                _ => targets,
+            };
+            for square in bitboard::squares(targets) {
+                moves.push(Move::drop(piece, bitboard::coord(square)));
+            }
+        }
+    }
+
+    fn filter_moves(&self, _state: &BoardState, _moves: &mut Vec<Move>) {}
+
+    fn checkmate_state(&self, state: &BoardState) -> CheckmateState {
+        let player = state.current_player;
+        let pockets_empty =
+            state.pocket(Color::White).is_empty() && state.pocket(Color::Black).is_empty();
+        // Pieces in a pocket can come back, so there's only ever insufficient
+        // material if both pockets are empty.
+        match state.board.checkmate_state(player) {
+            CheckmateState::InsuffientMaterial if !pockets_empty => (),
+            CheckmateState::Checkmate | CheckmateState::Stalemate => (),
+            checkmate => return checkmate,
+        }
+
+        // The player may have no moves on the board, but still be able to
+        // drop a piece.
+        let mut drops = vec![];
+        self.extra_moves(state, &mut drops);
+        let has_moves = !drops.is_empty() || state.board.has_legal_moves(player);
+        moves_state(has_moves, state.board.is_in_check(player))
+    }
+
+    fn insufficient_to_win(&self, state: &BoardState, _color: Color) -> bool {
+        // Even a lone king may capture a piece and then drop it to help
+        // checkmate, so a player can only never win if there is nothing left
+        // to capture or drop.
+        only_kings(&state.board)
+            && state.pocket(Color::White).is_empty()
+            && state.pocket(Color::Black).is_empty()
+    }
+}
+
+/// Atomic chess, where every capture is an explosion which blows up the
+/// capturing piece and every piece next to it other than a pawn. Kings can't
+/// capture, since they would blow themselves up, and a player wins by blowing
+/// up the other king. A king next to the other king is never in check, since
+/// capturing it would blow up both kings. See
+/// https://en.wikipedia.org/wiki/Atomic_chess
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Atomic;
+
+impl Atomic {
+    /// Returns true if `player`'s king is attacked and isn't next to the other
+    /// king.
+    fn is_in_check(board: &Board, player: Color) -> bool {
+        let kings_touch = match (board.get_king(player), board.get_king(player.opposite())) {
+            (Some(king), Some(other)) => {
+                bitboard::king_attacks(bitboard::square(king)) & bitboard::bit(other)
+                    != bitboard::EMPTY
+            }
+            _ => false,
+        };
+        !kings_touch && board.is_in_check(player)
+    }
+}
+
+impl Variant for Atomic {
+    fn name(&self) -> &'static str {
+        "Atomic"
+    }
+
+    fn captures_explode(&self) -> bool {
+        true
+    }
+
+    fn extra_moves(&self, state: &BoardState, moves: &mut Vec<Move>) {
+        // A move which leaves the king attacked may still be legal, if it
+        // blows up the other king or puts the kings next to each other.
+        let pseudo_legal = state.board.pseudo_legal_moves(state.current_player);
+        extend_new_moves(moves, pseudo_legal);
+    }
+
+    fn check_move(&self, state: &BoardState, mv: Move) -> Result<(), MoveError> {
+        let player = state.current_player;
+        if mv.is_capture() && state.board.get(mv.start).is(player, PieceType::King) {
+            return Err(MoveError::VariantRule(
+                "Kings can't capture in atomic chess",
+            ));
+        }
+
+        let mut after = state.board.clone();
+        after.make_move(mv);
+        if mv.is_capture() {
+            after.explode(mv.end);
+        }
+
+        match (after.get_king(player), after.get_king(player.opposite())) {
+            (None, _) => Err(MoveError::VariantRule("Can't blow up your own king")),
+            (Some(_), None) => Ok(()),
+            (Some(king), Some(_)) if Atomic::is_in_check(&after, player) => {
+                let by = after.attacker_of(king, player.opposite());
+                Err(MoveError::LeavesKingInCheck {
+                    king,
+                    by: by.expect("Expected the king to be attacked"),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn checkmate_state(&self, state: &BoardState) -> CheckmateState {
+        let player = state.current_player;
+        let board = &state.board;
+        if board.get_king(player).is_none() {
+            return CheckmateState::VariantEnd {
+                winner: Some(player.opposite()),
+                reason: "blowing up the king",
+            };
+        }
+
+        // Two bare kings can never blow each other up.
+        if only_kings(board) {
+            return CheckmateState::InsuffientMaterial;
+        }
+
+        let has_moves = !state.legal_moves().is_empty();
+        moves_state(has_moves, Atomic::is_in_check(board, player))
+    }
+
+    fn insufficient_to_win(&self, state: &BoardState, color: Color) -> bool {
+        // Any piece other than the king may win by capturing next to the other
+        // king, but a lone king can't capture at all.
+        only_king(&state.board, color)
+    }
+}
+
+/// Three-check, where a player also wins by putting the other king in check
+/// for the third time. See https://en.wikipedia.org/wiki/Three-check_chess
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThreeCheck;
+
+impl ThreeCheck {
+    /// The number of checks a player wins by giving.
+    pub const CHECKS_TO_WIN: u8 = 3;
+}
+
+impl Variant for ThreeCheck {
+    fn name(&self) -> &'static str {
+        "Three-check"
+    }
+
+    fn counts_checks(&self) -> bool {
+        true
+    }
+
+    fn filter_moves(&self, _state: &BoardState, _moves: &mut Vec<Move>) {}
+
+    fn checkmate_state(&self, state: &BoardState) -> CheckmateState {
+        let player = state.current_player;
+        let last_player = player.opposite();
+        if state.checks_given(last_player) >= ThreeCheck::CHECKS_TO_WIN {
+            return CheckmateState::VariantEnd {
+                winner: Some(last_player),
+                reason: "giving three checks",
+            };
+        }
+        // Even a lone minor piece can give check, so there is only ever
+        // insufficient material if there are no pieces left to check with.
+        if only_kings(&state.board) {
+            return CheckmateState::InsuffientMaterial;
+        }
+        ignoring_material(&state.board, player)
+    }
+
+    fn insufficient_to_win(&self, state: &BoardState, color: Color) -> bool {
+        only_king(&state.board, color)
+    }
+}
+
+/// King of the Hill, where a player also wins by moving their king onto one of
+/// the four squares in the center of the board. See
+/// https://lichess.org/variant/kingOfTheHill
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KingOfTheHill;
+
+impl Variant for KingOfTheHill {
+    fn name(&self) -> &'static str {
+        "King of the Hill"
+    }
+
+    fn hill(&self) -> Bitboard {
+        bitboard::bit(BoardCoord(3, 3))
+            | bitboard::bit(BoardCoord(4, 3))
+            | bitboard::bit(BoardCoord(3, 4))
+            | bitboard::bit(BoardCoord(4, 4))
+    }
+
+    fn filter_moves(&self, _state: &BoardState, _moves: &mut Vec<Move>) {}
+
+    fn checkmate_state(&self, state: &BoardState) -> CheckmateState {
+        let player = state.current_player;
+        let last_player = player.opposite();
+        if let Some(king) = state.board.get_king(last_player) {
+            if self.hill() & bitboard::bit(king) != bitboard::EMPTY {
+                return CheckmateState::VariantEnd {
+                    winner: Some(last_player),
+                    reason: "reaching the hill",
+                };
+            }
+        }
+        // A lone king can still walk to the hill, so there is never
+        // insufficient material.
+        ignoring_material(&state.board, player)
+    }
+
+    fn insufficient_to_win(&self, _state: &BoardState, _color: Color) -> bool {
+        false
+    }
+}
+
+/// Fog of War, where each player only sees the squares their own pieces can
+/// move to or attack. There is no check, so the king may move into or stay in
+/// danger, and the game is won by capturing it. See
+/// https://www.chess.com/terms/fog-of-war-chess
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FogOfWar;
+
+impl Variant for FogOfWar {
+    fn name(&self) -> &'static str {
+        "Fog of War"
+    }
+
+    fn hides_pieces(&self) -> bool {
+        true
+    }
+
+    fn extra_moves(&self, state: &BoardState, moves: &mut Vec<Move>) {
+        // Any move which leaves the king attacked is allowed, including
+        // castling out of, through, or into check.
+        let board = &state.board;
+        let player = state.current_player;
+        let mut extra = board.pseudo_legal_moves(player);
+        if let Some(king) = board.get_king(player) {
+            for end in board.castle_locations_ignoring_attacks(player) {
+                extra.push(Move::new(board, king, end, None));
+            }
+        }
+        extend_new_moves(moves, extra);
+    }
+
+    fn filter_moves(&self, _state: &BoardState, _moves: &mut Vec<Move>) {}
+
+    fn checkmate_state(&self, state: &BoardState) -> CheckmateState {
+        // The capture is looked for in the history rather than by the missing
+        // king, since a fogged view of the board may not show the other king.
+        if let Some(entry) = state.history().last() {
+            if let Some(Piece {
+                piece: PieceType::King,
+                ..
+            }) = entry.captured
+            {
+                return CheckmateState::VariantEnd {
+                    winner: Some(entry.moved.color),
+                    reason: "capturing the king",
+                };
+            }
+        }
+        // Without check, a player with no moves is only ever stalemated. Even
+        // a lone king may capture the other, so there is never insufficient
+        // material.
+        moves_state(!state.legal_moves().is_empty(), false)
+    }
+
+    fn insufficient_to_win(&self, _state: &BoardState, _color: Color) -> bool {
+        false
+    }
+}
+
+/// Los Alamos chess, played on a 6x6 board without bishops. Pawns can't move
+/// two squares forward (so there is no en passant), there is no castling, and
+/// pawns can't promote to a bishop. See
+/// https://www.chessvariants.com/small.dir/losalamos.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LosAlamos;
+
+impl LosAlamos {
+    /// The size of the board.
+    pub const DIMENSIONS: Dimensions = Dimensions { files: 6, ranks: 6 };
+    pub const STARTING_FEN: &'static str = "rnqknr/pppppp/6/6/PPPPPP/RNQKNR w - - 0 1";
+}
+
+impl Variant for LosAlamos {
+    fn name(&self) -> &'static str {
+        "Los Alamos"
+    }
+
+    fn starting_board(&self) -> Board {
+        BoardState::from_fen(LosAlamos::STARTING_FEN)
+            .expect("Expected a valid Los Alamos position")
+            .board
+    }
+
+    fn promotion_pieces(&self) -> &'static [PieceType] {
+        &[PieceType::Queen, PieceType::Rook, PieceType::Knight]
+    }
+
+    fn check_move(&self, _state: &BoardState, mv: Move) -> Result<(), MoveError> {
+        if mv.is_double_push() {
+            Err(MoveError::VariantRule(
+                "Pawns can only move one square forward",
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Capablanca chess, played on a 10x8 board with an Archbishop (which moves as
+/// a bishop or a knight) and a Chancellor (which moves as a rook or a knight)
+/// for each player. Pawns may also promote to either of them, and castling
+/// moves the king three squares towards the rook. See
+/// https://www.chessvariants.com/large.dir/capablanca.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capablanca;
+
+impl Capablanca {
+    /// The size of the board.
+    pub const DIMENSIONS: Dimensions = Dimensions {
+        files: 10,
+        ranks: 8,
+    };
+    pub const STARTING_FEN: &'static str =
+        "rnabqkbcnr/pppppppppp/10/10/10/10/PPPPPPPPPP/RNABQKBCNR w KQkq - 0 1";
+}
+
+impl Variant for Capablanca {
+    fn name(&self) -> &'static str {
+        "Capablanca"
+    }
+
+    fn starting_board(&self) -> Board {
+        BoardState::from_fen(Capablanca::STARTING_FEN)
+            .expect("Expected a valid Capablanca position")
+            .board
+    }
+
+    fn promotion_pieces(&self) -> &'static [PieceType] {
+        use crate::fairy::Fairy;
+        &[
+            PieceType::Queen,
+            PieceType::Fairy(Fairy::Chancellor),
+            PieceType::Fairy(Fairy::Archbishop),
+            PieceType::Rook,
+            PieceType::Bishop,
+            PieceType::Knight,
+        ]
+    }
+
+    fn filter_moves(&self, _state: &BoardState, _moves: &mut Vec<Move>) {}
+}
+
+/// Returns the checkmate state of a player given whether they have a legal
+/// move and whether they are in check.
+fn moves_state(has_moves: bool, in_check: bool) -> CheckmateState {
+    match (has_moves, in_check) {
+        (false, false) => CheckmateState::Stalemate,
+        (false, true) => CheckmateState::Checkmate,
+        (true, false) => CheckmateState::Normal,
+        (true, true) => CheckmateState::Check,
+    }
+}
+
+/// Add the moves in `extra` which aren't already in `moves` to `moves`.
+fn extend_new_moves(moves: &mut Vec<Move>, extra: Vec<Move>) {
+    let key = |mv: &Move| (mv.start, mv.end, mv.promotion, mv.drop);
+    let mut seen: HashSet<_> = moves.iter().map(key).collect();
+    moves.extend(extra.into_iter().filter(|mv| seen.insert(key(mv))));
+}
+
+/// Returns true if there are no pieces on the board other than kings.
+fn only_kings(board: &Board) -> bool {
+    board
+        .iter_pieces()
+        .all(|(_, piece)| piece.piece == PieceType::King)
+}
+
+/// Returns true if `color` has no pieces on the board other than their king.
+fn only_king(board: &Board, color: Color) -> bool {
+    board
+        .iter_pieces()
+        .all(|(_, piece)| piece.color != color || piece.piece == PieceType::King)
+}
+
+/// Return the checkmate state of `player` under the rules of standard chess,
+/// except that the game is never drawn by insufficient material. This is for
+/// variants which can be won without checkmate.
+fn ignoring_material(board: &Board, player: Color) -> CheckmateState {
+    match board.checkmate_state(player) {
+        CheckmateState::InsuffientMaterial => {
+            moves_state(board.has_legal_moves(player), board.is_in_check(player))
+        }
+        checkmate => checkmate,
+    }
+}
+
+/// Return every variant which can be picked when starting a game, with
+/// standard chess first.
+pub fn all_variants() -> Vec<Arc<dyn Variant>> {
+    vec![
+        Arc::new(Standard),
+        Arc::new(Chess960),
+        Arc::new(Crazyhouse),
+        Arc::new(Atomic),
+        Arc::new(ThreeCheck),
+        Arc::new(KingOfTheHill),
+        Arc::new(FogOfWar),
+        Arc::new(LosAlamos),
+        Arc::new(Capablanca),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A variant where the queens may never move, for testing the hooks of
+    // `Variant`.
+    #[derive(Debug)]
+    struct FrozenQueens;
+
+    impl Variant for FrozenQueens {
+        fn name(&self) -> &'static str {
+            "Frozen Queens"
+        }
+
+        fn check_move(&self, state: &BoardState, mv: Move) -> Result<(), MoveError> {
+            match state.board.get(mv.start).0.map(|piece| piece.piece) {
+                Some(PieceType::Queen) => Err(MoveError::VariantRule("Queens can't move")),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    fn play(state: &mut BoardState, moves: &[&str]) {
+        for uci in moves {
+            let mv = state.parse_uci_move(uci).unwrap();
+            state.take_turn(mv);
+        }
+    }
+
+    #[test]
+    fn test_standard_legal_moves() {
+        let state = BoardState::new_with_variant(Arc::new(Standard));
+        assert_eq!(state.legal_moves(), state.board.get_all_moves(Color::White));
+        assert_eq!(state.variant().name(), "Standard");
+    }
+
+    #[test]
+    fn test_chess960_starting_board() {
+        for _ in 0..10 {
+            let state = BoardState::new_with_variant(Arc::new(Chess960));
+            // Every pawn can move one or two squares.
+            let pawn_moves = state
+                .legal_moves()
+                .into_iter()
+                .filter(|mv| mv.start.1 == 1)
+                .count();
+            assert_eq!(pawn_moves, 16);
+            assert_eq!(state.checkmate, CheckmateState::Normal);
+        }
+    }
+
+    #[test]
+    fn test_variant_filters_moves() {
+        let mut state = BoardState::new_with_variant(Arc::new(FrozenQueens));
+        play(&mut state, &["e2e4", "e7e5"]);
+        let queen = BoardCoord(3, 0);
+        assert!(state.legal_moves().iter().all(|mv| mv.start != queen));
+        assert!(state.parse_uci_move("d1h5").is_err());
+        assert!(state.parse_uci_move("f1c4").is_ok());
+    }
+
+    #[test]
+    fn test_crazyhouse_captures_go_to_pocket() {
+        let mut state = BoardState::new_with_variant(Arc::new(Crazyhouse));
+        play(&mut state, &["e2e4", "d7d5", "e4d5", "d8d5"]);
+        assert_eq!(
+            state
+                .pocket(Color::White)
+                .count(PieceType::Pawn { just_lunged: false }),
+            1
+        );
+        assert_eq!(
+            state
+                .pocket(Color::Black)
+                .count(PieceType::Pawn { just_lunged: false }),
+            1
+        );
+
+        // White may drop the pawn anywhere empty, other than the first and
+        // last ranks.
+        play(&mut state, &["P@e4"]);
+        assert!(state.pocket(Color::White).is_empty());
+        assert!(state.parse_uci_move("P@e1").is_err());
+
+        let mut state = BoardState::from_fen("4k3/8/8/8/8/8/8/4K3[P] w - - 0 1").unwrap();
+        let drops = state.legal_moves().into_iter().filter(|mv| mv.is_drop());
+        // 62 empty squares, less the 14 on the first and last ranks
+        assert_eq!(drops.count(), 62 - 14);
+        assert!(state.parse_uci_move("P@a8").is_err());
+        assert!(state.parse_uci_move("N@a3").is_err());
+        play(&mut state, &["P@a2"]);
+        // A pawn dropped on its starting rank may move two squares.
+        play(&mut state, &["e8d8", "a2a4"]);
+
+        // The starting ranks depend on the size of the board.
+        let mut state = BoardState::from_fen("4k1/6/6/6/6/1K4[p] b - - 0 1").unwrap();
+        play(&mut state, &["P@c5", "b1a1", "c5c3"]);
+    }
+
+    #[test]
+    fn test_crazyhouse_promoted_pieces_demote() {
+        let mut state = BoardState::from_fen("2r1k3/1P6/8/8/8/8/8/4K3[] w - - 0 1").unwrap();
+        play(&mut state, &["b7b8q", "c8b8"]);
+        assert_eq!(state.to_fen(), "1r2k3/8/8/8/8/8/8/4K3[p] w - - 0 2");
+
+        let mut state = BoardState::from_fen("1r2k3/P7/8/8/8/8/8/4K3[] w - - 0 1").unwrap();
+        play(&mut state, &["a7b8r"]);
+        assert_eq!(state.to_fen(), "1R~2k3/8/8/8/8/8/8/4K3[R] b - - 0 1");
+    }
+
+    #[test]
+    fn test_crazyhouse_drop_blocks_check() {
+        // This would be checkmate, but White can block the check with a drop.
+        let fen = "4k3/8/8/8/8/8/5PPP/r5K1[N] w - - 0 1";
+        let state = BoardState::from_fen(fen).unwrap();
+        assert_eq!(state.checkmate, CheckmateState::Check);
+        let moves = state.legal_moves();
+        assert_eq!(moves.len(), 5);
+        assert!(moves.iter().all(|mv| mv.is_drop() && mv.end.1 == 0));
+
+        let standard = BoardState::new(state.board.clone());
+        assert_eq!(standard.checkmate, CheckmateState::Checkmate);
+
+        // A check by a knight can't be blocked.
+        let fen = "4k3/8/8/8/8/5n2/5PPP/6K1[Q] w - - 0 1";
+        let state = BoardState::from_fen(fen).unwrap();
+        assert!(state.legal_moves().iter().all(|mv| !mv.is_drop()));
+    }
+
+    // Play random games of `variant`, then undo every move, checking that the
+    // position before each move comes back.
+    fn assert_undo_random_games(variant: Arc<dyn Variant>) {
+        use rand::seq::SliceRandom;
+        use rand::SeedableRng;
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        for _ in 0..10 {
+            let mut state = BoardState::new_with_variant(variant.clone());
+            let start = state.to_fen();
+            let mut fens = vec![];
+            for _ in 0..150 {
+                let moves = state.legal_moves();
+                let mv = match moves.choose(&mut rng) {
+                    Some(&mv) if !state.game_over() => mv,
+                    _ => break,
+                };
+                fens.push(state.to_fen());
+                state.take_turn(mv);
+            }
+            while let Some(fen) = fens.pop() {
+                state.undo();
+                assert_eq!(state.to_fen(), fen);
+            }
+            assert_eq!(state.to_fen(), start);
+        }
+    }
+
+    #[test]
+    fn test_insufficient_to_win() {
+        let insufficient = |fen: &str, variant: Arc<dyn Variant>, color: Color| {
+            let state = BoardState::from_fen(fen).unwrap();
+            let state = BoardState::new_with_rules(state.board, state.current_player, variant);
+            state.variant().insufficient_to_win(&state, color)
+        };
+        let queen = "4k3/8/8/8/8/8/8/3QK3 w - - 0 1";
+        let knight = "4k3/8/8/8/8/8/8/3NK3 w - - 0 1";
+        assert!(insufficient(queen, Arc::new(Standard), Color::Black));
+        assert!(!insufficient(queen, Arc::new(Standard), Color::White));
+        assert!(insufficient(knight, Arc::new(Standard), Color::White));
+
+        // A lone king can walk to the hill, or capture the other king.
+        assert!(!insufficient(queen, Arc::new(KingOfTheHill), Color::Black));
+        assert!(!insufficient(queen, Arc::new(FogOfWar), Color::Black));
+
+        // A knight can give three checks, or blow up the other king, but a
+        // lone king can do neither.
+        for variant in [Arc::new(ThreeCheck) as Arc<dyn Variant>, Arc::new(Atomic)].iter() {
+            assert!(!insufficient(knight, variant.clone(), Color::White));
+            assert!(insufficient(knight, variant.clone(), Color::Black));
+        }
+
+        // A lone king may capture the queen and drop it.
+        assert!(!insufficient(queen, Arc::new(Crazyhouse), Color::Black));
+        // The pockets come from the FEN, so use its Crazyhouse state as is.
+        let pocket_insufficient = |fen: &str| {
+            let state = BoardState::from_fen(fen).unwrap();
+            state.variant().insufficient_to_win(&state, Color::White)
+        };
+        assert!(pocket_insufficient("4k3/8/8/8/8/8/8/4K3[] w - - 0 1"));
+        assert!(!pocket_insufficient("4k3/8/8/8/8/8/8/4K3[q] w - - 0 1"));
+    }
+
+    #[test]
+    fn test_crazyhouse_undo_random_games() {
+        assert_undo_random_games(Arc::new(Crazyhouse));
+    }
+
+    fn atomic(fen: &str) -> BoardState {
+        let state = BoardState::from_fen(fen).unwrap();
+        BoardState::new_with_rules(state.board, state.current_player, Arc::new(Atomic))
+    }
+
+    #[test]
+    fn test_atomic_capture_explodes() {
+        let mut state = atomic("4k3/8/2p5/3pn3/4P3/8/8/4K3 w - - 0 1");
+        play(&mut state, &["e4d5"]);
+        // The knight is blown up along with both pawns on d5, but the pawn
+        // on c6 survives.
+        assert_eq!(state.to_fen(), "4k3/8/2p5/8/8/8/8/4K3 b - - 0 1");
+        let explosion = state.history().last().unwrap().explosion.unwrap();
+        assert_eq!(explosion.center, BoardCoord(3, 4));
+        assert_eq!(explosion.pieces().count(), 1);
+
+        state.undo();
+        assert_eq!(state.to_fen(), "4k3/8/2p5/3pn3/4P3/8/8/4K3 w - - 0 1");
+    }
+
+    #[test]
+    fn test_atomic_kings() {
+        // Kings can't capture.
+        let state = atomic("4k3/8/8/8/8/8/3q4/4K3 w - - 0 1");
+        assert_eq!(state.checkmate, CheckmateState::Check);
+        assert!(state.parse_uci_move("e1d2").is_err());
+
+        // A king may move next to the other king, even onto an attacked
+        // square, since the kings protect each other.
+        let state = atomic("8/8/8/8/8/4k3/8/r3K3 w - - 0 1");
+        assert!(state.parse_uci_move("e1e2").is_ok());
+        assert!(state.parse_uci_move("e1f2").is_ok());
+        assert!(state.parse_uci_move("e1f1").is_err());
+        let standard = BoardState::from_fen("8/8/8/8/8/4k3/8/r3K3 w - - 0 1").unwrap();
+        assert!(standard.parse_uci_move("e1e2").is_err());
+
+        // A capture can't blow up the capturing player's king.
+        let state = atomic("7k/8/8/8/8/8/3p4/2K1B3 w - - 0 1");
+        assert!(state.parse_uci_move("e1d2").is_err());
+    }
+
+    #[test]
+    fn test_atomic_blowing_up_king_wins() {
+        let mut state = atomic("4k3/4r3/8/8/8/8/8/4RK2 w - - 0 1");
+        play(&mut state, &["e1e7"]);
+        assert!(state.game_over());
+        assert_eq!(state.winner(), Some(Color::White));
+
+        // Blowing up the other king wins even if it leaves your own king in
+        // check.
+        let state = atomic("4k3/4r3/8/8/8/8/8/4RK1q w - - 0 1");
+        assert_eq!(state.checkmate, CheckmateState::Check);
+        assert!(state.parse_uci_move("e1e7").is_ok());
+    }
+
+    #[test]
+    fn test_atomic_undo_random_games() {
+        assert_undo_random_games(Arc::new(Atomic));
+    }
+
+    #[test]
+    fn test_three_check() {
+        let mut state = BoardState::new_with_variant(Arc::new(ThreeCheck));
+        play(&mut state, &["e2e4", "f7f6", "d1h5"]);
+        assert_eq!(state.checks_given(Color::White), 1);
+        assert_eq!(state.checks_given(Color::Black), 0);
+        state.undo();
+        assert_eq!(state.checks_given(Color::White), 0);
+
+        // The third check wins, even though it isn't checkmate.
+        let mut state = BoardState::from_fen("4k3/8/8/8/8/8/8/4K2R w - - 1+3 0 1").unwrap();
+        assert_eq!(state.variant().name(), "Three-check");
+        assert_eq!(state.checks_given(Color::White), 2);
+        play(&mut state, &["h1h8"]);
+        assert_eq!(state.winner(), Some(Color::White));
+        assert_eq!(
+            state.checkmate,
+            CheckmateState::VariantEnd {
+                winner: Some(Color::White),
+                reason: "giving three checks",
+            }
+        );
+
+        // A lone knight can still give check.
+        let state = BoardState::from_fen("4k3/8/8/8/8/8/8/4KN2 w - - 3+3 0 1").unwrap();
+        assert_eq!(state.checkmate, CheckmateState::Normal);
+    }
+
+    #[test]
+    fn test_king_of_the_hill() {
+        let mut state = BoardState::new_with_variant(Arc::new(KingOfTheHill));
+        play(
+            &mut state,
+            &["e2e4", "a7a6", "e1e2", "a6a5", "e2d3", "a5a4"],
+        );
+        assert!(!state.game_over());
+        play(&mut state, &["d3d4"]);
+        assert_eq!(state.winner(), Some(Color::White));
+
+        // Bare kings aren't a draw, since either king may reach the hill.
+        let fen = "8/8/8/8/8/8/8/K6k w - - 0 1";
+        let board = BoardState::from_fen(fen).unwrap().board;
+        let state = BoardState::new_with_rules(board, Color::White, Arc::new(KingOfTheHill));
+        assert_eq!(state.checkmate, CheckmateState::Normal);
+    }
+
+    #[test]
+    fn test_los_alamos() {
+        let mut state = BoardState::new_with_variant(Arc::new(LosAlamos));
+        assert_eq!(state.to_fen(), LosAlamos::STARTING_FEN);
+        // Six pawn moves and two for each knight
+        assert_eq!(state.legal_moves().len(), 10);
+        assert_eq!(state.perft(2), 100);
+        assert!(state.parse_uci_move("c2c4").is_err());
+        play(&mut state, &["c2c3", "d5d4", "c3d4"]);
+
+        let state = BoardState::from_fen("3k2/P5/6/6/6/3K2 w - - 0 1").unwrap();
+        assert!(state.parse_uci_move("a5a6b").is_err());
+        assert!(state.parse_uci_move("a5a6n").is_ok());
+        assert_eq!(state.legal_moves().len(), 5 + 3);
+    }
+
+    #[test]
+    fn test_los_alamos_undo_random_games() {
+        assert_undo_random_games(Arc::new(LosAlamos));
+    }
+
+    #[test]
+    fn test_capablanca() {
+        let mut state = BoardState::new_with_variant(Arc::new(Capablanca));
+        assert_eq!(state.to_fen(), Capablanca::STARTING_FEN);
+        assert_eq!(state.perft(1), 28);
+        assert_eq!(state.perft(2), 784);
+        assert_eq!(state.perft(3), 25228);
+
+        // The Archbishop gives check with a knight's leap
+        play(&mut state, &["c1d3", "a7a6", "d3f4", "a6a5", "f4g6"]);
+        assert!(state.board.is_in_check(Color::Black));
+
+        // Pawns may also promote to an Archbishop or a Chancellor
+        let state = BoardState::from_fen("4k5/P9/10/10/10/10/10/4K5 w - - 0 1").unwrap();
+        assert_eq!(state.variant().name(), "Capablanca");
+        assert!(state.parse_uci_move("a7a8c").is_ok());
+        assert!(state.parse_uci_move("a7a8a").is_ok());
+        assert!(state.parse_uci_move("a7a8m").is_err());
+        assert_eq!(state.legal_moves().len(), 5 + 6);
+    }
+
+    #[test]
+    fn test_capablanca_undo_random_games() {
+        assert_undo_random_games(Arc::new(Capablanca));
+    }
+
+    #[test]
+    fn test_three_check_undo_random_games() {
+        assert_undo_random_games(Arc::new(ThreeCheck));
+    }
+
+    #[test]
+    fn test_fog_of_war_undo_random_games() {
+        assert_undo_random_games(Arc::new(FogOfWar));
+    }
+
+    #[test]
+    fn test_winner() {
+        let mut state = BoardState::new_with_variant(Arc::new(Standard));
+        assert_eq!(state.winner(), None);
+        play(&mut state, &["f2f3", "e7e5", "g2g4", "d8h4"]);
+        assert_eq!(state.checkmate, CheckmateState::Checkmate);
+        assert_eq!(state.winner(), Some(Color::Black));
+    }
+}
@@ -0,0 +1,274 @@
+//! Zobrist hashing of chess positions. See https://www.chessprogramming.org/Zobrist_Hashing
+//! Each feature of a position (a piece on a square, the side to move, a castling
+//! right, an en passant file, the number of a piece in a pocket, the number of
+//! checks given) has a random 64-bit key, and the hash of a position is the
+//! XOR of the keys of all of its features. This means that the hash can be
+//! updated cheaply when a move is made by XORing out the features which went
+//! away and XORing in the ones which were added.
+
+use crate::bitboard::{self, FILES, SQUARES};
+use crate::board::*;
+use crate::fairy::Fairy;
+
+struct ZobristKeys {
+    /// Indexed by `piece_index` and then by square (see `bitboard::square`)
+    pieces: [[u64; SQUARES]; 2 * PIECE_KINDS],
+    black_to_move: u64,
+    /// Indexed in the same order as the bits of `Board::castling_rights`
+    castling: [u64; 4],
+    /// Indexed by file
+    en_passant: [u64; FILES],
+    /// Indexed by `piece_index` (of a pawn through a queen) and then by the
+    /// number of that piece in the pocket. The key for zero pieces is 0.
+    pockets: [[u64; MAX_POCKET + 1]; 2 * PIECE_KINDS],
+    /// Indexed by color and then by the number of checks given. The key for
+    /// zero checks is 0.
+    checks: [[u64; MAX_CHECKS + 1]; 2],
+}
+
+/// The number of kinds of pieces: the six standard ones and the fairy pieces
+const PIECE_KINDS: usize = 6 + Fairy::COUNT;
+
+/// The most pieces of one kind which can be in a pocket. There are only 16
+/// pawns (and promoted pieces turn back into pawns when captured), so this
+/// can't be reached in a real game.
+const MAX_POCKET: usize = 16;
+
+/// The most checks a player can have given. A game of Three-check is over
+/// after the third check, so more than this only happens in set up positions.
+const MAX_CHECKS: usize = 3;
+
+impl ZobristKeys {
+    /// Generate the keys. This is done at compile time using a fixed seed so
+    /// that hashes are the same across runs.
+    const fn new() -> ZobristKeys {
+        let mut seed = 0x2545_F491_4F6C_DD1D;
+        let mut keys = ZobristKeys {
+            pieces: [[0; SQUARES]; 2 * PIECE_KINDS],
+            black_to_move: 0,
+            castling: [0; 4],
+            en_passant: [0; FILES],
+            pockets: [[0; MAX_POCKET + 1]; 2 * PIECE_KINDS],
+            checks: [[0; MAX_CHECKS + 1]; 2],
+        };
+
+        let mut piece = 0;
+        while piece < 2 * PIECE_KINDS {
+            let mut square = 0;
+            while square < SQUARES {
+                seed = splitmix64(seed);
+                keys.pieces[piece][square] = seed;
+                square += 1;
+            }
+            piece += 1;
+        }
+
+        seed = splitmix64(seed);
+        keys.black_to_move = seed;
+
+        let mut i = 0;
+        while i < 4 {
+            seed = splitmix64(seed);
+            keys.castling[i] = seed;
+            i += 1;
+        }
+
+        let mut i = 0;
+        while i < FILES {
+            seed = splitmix64(seed);
+            keys.en_passant[i] = seed;
+            i += 1;
+        }
+
+        let mut piece = 0;
+        while piece < 2 * PIECE_KINDS {
+            let mut count = 1;
+            while count <= MAX_POCKET {
+                seed = splitmix64(seed);
+                keys.pockets[piece][count] = seed;
+                count += 1;
+            }
+            piece += 1;
+        }
+
+        let mut color = 0;
+        while color < 2 {
+            let mut count = 1;
+            while count <= MAX_CHECKS {
+                seed = splitmix64(seed);
+                keys.checks[color][count] = seed;
+                count += 1;
+            }
+            color += 1;
+        }
+
+        keys
+    }
+}
+
+const KEYS: ZobristKeys = ZobristKeys::new();
+
+/// A small, fast pseudorandom number generator. See http://prng.di.unimi.it/splitmix64.c
+const fn splitmix64(state: u64) -> u64 {
+    let mut z = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+fn piece_index(piece: Piece) -> usize {
+    use PieceType::*;
+    let piece_type = match piece.piece {
+        Pawn { .. } => 0,
+        Knight => 1,
+        Bishop => 2,
+        Rook => 3,
+        Queen => 4,
+        King => 5,
+        Fairy(fairy) => 6 + fairy as usize,
+    };
+    match piece.color {
+        Color::White => piece_type,
+        Color::Black => piece_type + PIECE_KINDS,
+    }
+}
+
+/// The key for `piece` being on `coord`. The `has_moved` and `just_lunged`
+/// flags are ignored, as they are covered by the castling and en passant keys.
+pub fn piece_key(piece: Piece, coord: BoardCoord) -> u64 {
+    KEYS.pieces[piece_index(piece)][bitboard::square(coord)]
+}
+
+/// The key for Black being the player-to-move.
+pub fn black_to_move_key() -> u64 {
+    KEYS.black_to_move
+}
+
+/// The key for a set of castling rights, as returned by `Board::castling_rights`
+pub fn castling_key(rights: u8) -> u64 {
+    let mut key = 0;
+    for (i, castling) in KEYS.castling.iter().enumerate() {
+        if rights & (1 << i) != 0 {
+            key ^= castling;
+        }
+    }
+    key
+}
+
+/// The key for a pawn having just lunged to `lunged_pawn`, if there is one
+/// which may be captured en passant (see `Board::en_passant_pawn`).
+pub fn en_passant_key(lunged_pawn: Option<BoardCoord>) -> u64 {
+    match lunged_pawn {
+        Some(coord) => KEYS.en_passant[coord.0 as usize],
+        None => 0,
+    }
+}
+
+/// The key for `color` having `count` of `piece` in their pocket. This is 0
+/// when `count` is 0, so adding a piece to a pocket can be hashed by XORing
+/// out the key for the old count and XORing in the key for the new one.
+pub fn pocket_key(color: Color, piece: PieceType, count: u8) -> u64 {
+    let index = piece_index(Tile::new(color, piece).0.unwrap());
+    KEYS.pockets[index][(count as usize).min(MAX_POCKET)]
+}
+
+/// The hash of all of the pieces in `color`'s pocket.
+pub fn pocket_hash(color: Color, pocket: &Pocket) -> u64 {
+    pocket.pieces().fold(0, |hash, (piece, count)| {
+        hash ^ pocket_key(color, piece, count)
+    })
+}
+
+/// The key for `color` having given `count` checks, in variants such as
+/// Three-check. This is 0 when `count` is 0, like `pocket_key`.
+pub fn checks_key(color: Color, count: u8) -> u64 {
+    KEYS.checks[color as usize][(count as usize).min(MAX_CHECKS)]
+}
+
+/// Compute the hash of a position from scratch. `BoardState` keeps its hash up
+/// to date as moves are made, so this is only needed when creating a new
+/// position (or for checking that the incremental hash is correct). Pockets
+/// and checks given are not included, see `pocket_hash` and `checks_key`.
+pub fn hash(board: &Board, current_player: Color) -> u64 {
+    let mut hash = board
+        .iter_pieces()
+        .fold(0, |hash, (coord, piece)| hash ^ piece_key(piece, coord));
+
+    if current_player == Color::Black {
+        hash ^= black_to_move_key();
+    }
+
+    hash ^ castling_key(board.castling_rights())
+        ^ en_passant_key(board.en_passant_pawn(current_player))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transposition() {
+        // 1. Nf3 Nf6 2. Nc3 and 1. Nc3 Nf6 2. Nf3 reach the same position
+        let mut a = BoardState::new(Board::default());
+        a.take_turn(Move::new(
+            &a.board,
+            BoardCoord(6, 0),
+            BoardCoord(5, 2),
+            None,
+        ));
+        a.take_turn(Move::new(
+            &a.board,
+            BoardCoord(6, 7),
+            BoardCoord(5, 5),
+            None,
+        ));
+        a.take_turn(Move::new(
+            &a.board,
+            BoardCoord(1, 0),
+            BoardCoord(2, 2),
+            None,
+        ));
+
+        let mut b = BoardState::new(Board::default());
+        b.take_turn(Move::new(
+            &b.board,
+            BoardCoord(1, 0),
+            BoardCoord(2, 2),
+            None,
+        ));
+        b.take_turn(Move::new(
+            &b.board,
+            BoardCoord(6, 7),
+            BoardCoord(5, 5),
+            None,
+        ));
+        b.take_turn(Move::new(
+            &b.board,
+            BoardCoord(6, 0),
+            BoardCoord(5, 2),
+            None,
+        ));
+
+        assert_eq!(a.position_hash(), b.position_hash());
+        assert_ne!(
+            a.position_hash(),
+            BoardState::new(Board::default()).position_hash()
+        );
+    }
+
+    #[test]
+    fn test_state_differences() {
+        let hash = |fen| BoardState::from_fen(fen).unwrap().position_hash();
+        let base = hash("r3k2r/8/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1");
+        assert_ne!(base, hash("r3k2r/8/8/3pP3/8/8/8/R3K2R b KQkq - 0 1"));
+        assert_ne!(base, hash("r3k2r/8/8/3pP3/8/8/8/R3K2R w KQk d6 0 1"));
+        assert_ne!(base, hash("r3k2r/8/8/3pP3/8/8/8/R3K2R w KQkq - 0 1"));
+        // The move counters aren't part of the position
+        assert_eq!(base, hash("r3k2r/8/8/3pP3/8/8/8/R3K2R w KQkq d6 7 30"));
+        // Nor is an en passant square where no pawn can capture
+        assert_eq!(
+            hash("r3k2r/8/8/3p4/8/8/8/R3K2R w KQkq d6 0 1"),
+            hash("r3k2r/8/8/3p4/8/8/8/R3K2R w KQkq - 0 1")
+        );
+    }
+}